@@ -0,0 +1,48 @@
+use codecrafters_shell::Shell;
+use codecrafters_shell::completion::{CompletionContext, longest_common_prefix};
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::fs;
+use std::hint::black_box;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+/// How many executables the fake `PATH` holds, as on a system with many
+/// packages installed.
+const EXECUTABLES: usize = 5000;
+
+/// Fills a directory with executables named like `git-annex-0042`, so
+/// that they share long prefixes.
+fn fake_path() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("completion-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for i in 0..EXECUTABLES {
+        let path = dir.join(format!("git-annex-{:04}", i));
+        fs::write(&path, "").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    dir
+}
+
+fn completion(c: &mut Criterion) {
+    let dir = fake_path();
+    // SAFETY: nothing else runs yet
+    unsafe { std::env::set_var("PATH", &dir) };
+    let shell = Shell::default();
+
+    let names: Vec<String> = (0..EXECUTABLES)
+        .map(|i| format!("git-annex-{:04}", i))
+        .collect();
+    c.bench_function("longest_common_prefix", |b| {
+        b.iter(|| longest_common_prefix(black_box(&names)))
+    });
+
+    let context = CompletionContext::parse("git-an");
+    c.bench_function("command candidates", |b| {
+        b.iter(|| black_box(&context).candidates(&shell))
+    });
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+criterion_group!(benches, completion);
+criterion_main!(benches);
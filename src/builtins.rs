@@ -1,8 +1,18 @@
-use crate::ShellStatus;
-use crate::get_executable_path;
-use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader, Write};
+use crate::completion::CompleteSpec;
+use crate::expand::DEFAULT_IFS;
+use crate::history::{self, History};
+use crate::jump::{self, Frecency};
+use crate::keymap::{self, Binding, EditCommand};
+use crate::shell::Shell;
+use crate::spell;
+use crate::variables;
+use crate::{CommandResult, ShellStatus};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Arc;
+use termion::{clear, cursor};
 
 /// Enumeration of all supported builtin commands.
 pub enum Builtin {
@@ -12,6 +22,20 @@ pub enum Builtin {
     Pwd,
     Cd,
     History,
+    Set,
+    Abbr,
+    Bind,
+    Complete,
+    Source,
+    Eval,
+    Export,
+    Unset,
+    Shopt,
+    Plugin,
+    Jump,
+    Clear,
+    Trap,
+    Read,
 }
 
 impl FromStr for Builtin {
@@ -25,206 +49,1306 @@ impl FromStr for Builtin {
             "pwd" => Ok(Builtin::Pwd),
             "cd" => Ok(Builtin::Cd),
             "history" => Ok(Builtin::History),
+            "set" => Ok(Builtin::Set),
+            "abbr" => Ok(Builtin::Abbr),
+            "bind" => Ok(Builtin::Bind),
+            "complete" => Ok(Builtin::Complete),
+            "source" | "." => Ok(Builtin::Source),
+            "eval" => Ok(Builtin::Eval),
+            "export" => Ok(Builtin::Export),
+            "unset" => Ok(Builtin::Unset),
+            "shopt" => Ok(Builtin::Shopt),
+            "plugin" => Ok(Builtin::Plugin),
+            "j" => Ok(Builtin::Jump),
+            "clear" => Ok(Builtin::Clear),
+            "trap" => Ok(Builtin::Trap),
+            "read" => Ok(Builtin::Read),
             _ => Err(()),
         }
     }
 }
 
 impl Builtin {
+    /// Names of all builtins, as typed on the command line.
+    pub const NAMES: &'static [&'static str] = &[
+        "echo", "exit", "type", "pwd", "cd", "history", "set", "abbr", "bind", "complete",
+        "source", ".", "eval", "export", "unset", "shopt", "plugin", "j", "clear", "trap", "read",
+    ];
+
+    /// The options the builtin accepts, offered when completing a word
+    /// starting with `-`.
+    pub fn options(&self) -> &'static [&'static str] {
+        match self {
+            Builtin::History => &["--json", "-a", "-c", "-d", "-r", "-w"],
+            Builtin::Set => &["-n", "+n", "-o", "+o"],
+            Builtin::Abbr => &["--add", "--erase", "--list", "-a", "-e", "-l"],
+            Builtin::Bind => &["-X", "-f", "-l", "-p", "-r", "-x"],
+            Builtin::Complete => &["-W", "-c", "-d", "-f", "-p", "-r"],
+            Builtin::Export => &["-n", "-p"],
+            Builtin::Unset => &["-v"],
+            Builtin::Shopt => &["-o", "-p", "-s", "-u"],
+            Builtin::Plugin => &["list", "load"],
+            Builtin::Type => &["-a"],
+            Builtin::Jump => &["-l"],
+            Builtin::Clear => &["-x"],
+            Builtin::Trap => &["-p"],
+            Builtin::Read => &["-a", "-d", "-n", "-r"],
+            Builtin::Exit
+            | Builtin::Echo
+            | Builtin::Pwd
+            | Builtin::Cd
+            | Builtin::Source
+            | Builtin::Eval => &[],
+        }
+    }
+
     /// Executes the builtin command.
     ///
-    /// Returns a `ShellStatus` indicating whether the shell should continue
-    /// or exit with a specific code.
+    /// Returns its result: a non-zero status if it failed, and `Exit` for
+    /// `exit`, or a sourced file that exits.
     pub fn execute<W: Write, E: Write>(
         &self,
         args: Vec<String>,
         mut stdout: W,
         mut stderr: E,
-        history: &[String],
-        last_saved_index: usize,
-    ) -> ShellStatus {
-        match self {
+        shell: &mut Shell,
+    ) -> CommandResult {
+        let status = match self {
             Builtin::Exit => {
                 let code = args
                     .first()
                     .and_then(|s| s.parse::<i32>().ok())
                     .unwrap_or(0);
-                ShellStatus::Exit(code)
+                return CommandResult::exit(code);
+            }
+            Builtin::Echo => echo_cmd(args, &mut stdout),
+            Builtin::Type => type_cmd(args, &mut stdout, &mut stderr, shell),
+            Builtin::Pwd => {
+                let _ = writeln!(stdout, "{}", shell.cwd.display());
+                0
             }
-            Builtin::Echo => {
-                echo_cmd(args, &mut stdout);
-                ShellStatus::Continue
+            Builtin::Cd => cd_cmd(args, &mut stdout, &mut stderr, shell),
+            Builtin::History => history_cmd(args, &mut stdout, &mut stderr, shell),
+            Builtin::Set => set_cmd(args, &mut stdout, &mut stderr, shell),
+            Builtin::Abbr => abbr_cmd(args, &mut stdout, &mut stderr, shell),
+            Builtin::Bind => bind_cmd(args, &mut stdout, &mut stderr, shell),
+            Builtin::Complete => complete_cmd(args, &mut stdout, &mut stderr, shell),
+            Builtin::Source => return source_cmd(args, &mut stderr, shell),
+            Builtin::Eval => return eval_cmd(args, shell),
+            Builtin::Export => export_cmd(args, &mut stdout, &mut stderr, shell),
+            Builtin::Unset => unset_cmd(args, &mut stderr, shell),
+            Builtin::Shopt => shopt_cmd(args, &mut stdout, &mut stderr, shell),
+            Builtin::Plugin => plugin_cmd(args, &mut stdout, &mut stderr, shell),
+            Builtin::Jump => jump_cmd(args, &mut stdout, &mut stderr, shell),
+            Builtin::Clear => clear_cmd(args, &mut stdout, &mut stderr),
+            Builtin::Trap => trap_cmd(args, &mut stdout, &mut stderr, shell),
+            Builtin::Read => read_cmd(args, &mut stderr, shell),
+        };
+        CommandResult::finished(status)
+    }
+}
+
+/// Implementation of the `cd` command.
+///
+/// Changes to the directory given, `~` for the home directory, looking for
+/// relative names under `CDPATH` too. A name that isn't found may be a
+/// misspelling, which is suggested, or corrected with `shopt -s cdspell`.
+/// `PWD` and `OLDPWD` are set to the new and old directories.
+pub fn cd_cmd<W: Write, E: Write>(
+    args: Vec<String>,
+    stdout: &mut W,
+    stderr: &mut E,
+    shell: &mut Shell,
+) -> i32 {
+    let Some(path) = args.first() else {
+        return 0;
+    };
+    let new_dir = if path == "~" {
+        match shell.variables.get("HOME") {
+            Some(val) => val,
+            None => {
+                let _ = writeln!(stderr, "cd: HOME not set");
+                return 1;
             }
-            Builtin::Type => {
-                type_cmd(args, &mut stdout, &mut stderr);
-                ShellStatus::Continue
+        }
+    } else if !path.starts_with(['/', '.'])
+        && let Some(dir) = find_in_cdpath(path, shell)
+    {
+        // Like bash, show where a CDPATH search led
+        let _ = writeln!(stdout, "{}", dir.display());
+        dir.to_string_lossy().into_owned()
+    } else {
+        path.clone()
+    };
+
+    if shell.change_dir(&new_dir).is_ok() {
+        return 0;
+    }
+    match spell::correct_dir(&shell.cwd, Path::new(&new_dir)) {
+        // Like bash, show the corrected name before going there
+        Some(dir) if shell.options.cd_spell => {
+            let _ = writeln!(stdout, "{}", dir.display());
+            if shell.change_dir(&dir).is_ok() {
+                return 0;
             }
-            Builtin::Pwd => {
-                match std::env::current_dir() {
-                    Ok(path) => {
-                        let _ = writeln!(stdout, "{}", path.display());
+            let _ = writeln!(stderr, "cd: no such file or directory: {}", dir.display());
+        }
+        Some(dir) => {
+            let _ = writeln!(stderr, "cd: no such file or directory: {}", new_dir);
+            let _ = writeln!(stderr, "cd: did you mean {}?", dir.display());
+        }
+        None => {
+            let _ = writeln!(stderr, "cd: no such file or directory: {}", new_dir);
+        }
+    }
+    1
+}
+
+/// Implementation of the `abbr` command.
+///
+/// Defines abbreviations with `abbr name=expansion` or `abbr -a name
+/// expansion...`, removes them with `abbr -e name`, and lists them with no
+/// arguments (or only their names with `abbr -l`).
+pub fn abbr_cmd<W: Write, E: Write>(
+    args: Vec<String>,
+    stdout: &mut W,
+    stderr: &mut E,
+    shell: &mut Shell,
+) -> i32 {
+    let show = |stdout: &mut W, name: &str, expansion: &str| {
+        let _ = writeln!(
+            stdout,
+            "abbr {}='{}'",
+            name,
+            expansion.replace('\'', "'\\''")
+        );
+    };
+
+    let mut status = 0;
+    match args.first().map(|s| s.as_str()) {
+        None => {
+            for (name, expansion) in shell.abbreviations.iter() {
+                show(stdout, name, expansion);
+            }
+        }
+        Some("-l" | "--list") => {
+            for name in shell.abbreviations.keys() {
+                let _ = writeln!(stdout, "{}", name);
+            }
+        }
+        Some("-a" | "--add") => match args.get(1) {
+            Some(name) if args.len() > 2 => {
+                Arc::make_mut(&mut shell.abbreviations).insert(name.clone(), args[2..].join(" "));
+            }
+            _ => {
+                let _ = writeln!(stderr, "abbr: -a requires a name and an expansion");
+                status = 2;
+            }
+        },
+        Some("-e" | "--erase") => {
+            for name in &args[1..] {
+                if !shell.abbreviations.contains_key(name) {
+                    let _ = writeln!(stderr, "abbr: {}: not found", name);
+                    status = 1;
+                } else {
+                    Arc::make_mut(&mut shell.abbreviations).remove(name);
+                }
+            }
+        }
+        Some(option) if option.starts_with('-') => {
+            let _ = writeln!(stderr, "abbr: {}: invalid option", option);
+            let _ = writeln!(
+                stderr,
+                "abbr: usage: abbr [name[=expansion] ...] [-a name expansion] [-e name ...] [-l]"
+            );
+            status = 2;
+        }
+        Some(_) => {
+            for arg in &args {
+                match arg.split_once('=') {
+                    Some((name, _)) if name.is_empty() || name.contains(char::is_whitespace) => {
+                        let _ = writeln!(stderr, "abbr: `{}': invalid abbreviation name", name);
+                        status = 1;
                     }
-                    Err(e) => {
-                        let _ = writeln!(stderr, "pwd: error retrieving current directory: {}", e);
+                    Some((name, expansion)) => {
+                        Arc::make_mut(&mut shell.abbreviations)
+                            .insert(name.to_string(), expansion.to_string());
                     }
+                    None => match shell.abbreviations.get(arg) {
+                        Some(expansion) => show(stdout, arg, expansion),
+                        None => {
+                            let _ = writeln!(stderr, "abbr: {}: not found", arg);
+                            status = 1;
+                        }
+                    },
                 }
-                ShellStatus::Continue
             }
-            Builtin::Cd => {
-                if let Some(path) = args.first() {
-                    let new_dir = if path == "~" {
-                        match std::env::var("HOME") {
-                            Ok(val) => val,
-                            Err(_) => {
-                                let _ = writeln!(stderr, "cd: HOME not set");
-                                return ShellStatus::Continue;
-                            }
-                        }
-                    } else {
-                        path.clone()
-                    };
-
-                    if std::env::set_current_dir(&new_dir).is_err() {
-                        let _ = writeln!(stderr, "cd: no such file or directory: {}", new_dir);
-                    }
-                }
-                ShellStatus::Continue
-            }
-            Builtin::History => {
-                // Check for -r flag to read from file
-                if args.first().map(|s| s.as_str()) == Some("-r") {
-                    if let Some(filepath) = args.get(1) {
-                        match File::open(filepath) {
-                            Ok(file) => {
-                                let reader = BufReader::new(file);
-                                let mut loaded_history = Vec::new();
-                                for cmd in reader.lines().map_while(Result::ok) {
-                                    loaded_history.push(cmd);
-                                }
-                                return ShellStatus::LoadHistory(loaded_history);
-                            }
-                            Err(e) => {
-                                let _ = writeln!(stderr, "history: {}: {}", filepath, e);
-                                return ShellStatus::Continue;
-                            }
+        }
+    }
+    status
+}
+
+/// Implementation of the `complete` command.
+///
+/// Defines how the arguments of commands complete with `complete -W
+/// "start stop" name`, or with `-d`, `-f` and `-c` for directory, file and
+/// command names. `complete -r` removes the definitions and `complete -p`
+/// (or no arguments) prints them.
+pub fn complete_cmd<W: Write, E: Write>(
+    args: Vec<String>,
+    stdout: &mut W,
+    stderr: &mut E,
+    shell: &mut Shell,
+) -> i32 {
+    let usage = "complete: usage: complete [-pr] [-cdf] [-W wordlist] [name ...]";
+    let mut spec = CompleteSpec::default();
+    let mut print = false;
+    let mut remove = false;
+    let mut names = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let Some(flags) = arg.strip_prefix('-').filter(|flags| !flags.is_empty()) else {
+            names.push(arg);
+            names.extend(args.by_ref());
+            break;
+        };
+        for flag in flags.chars() {
+            match flag {
+                'p' => print = true,
+                'r' => remove = true,
+                'c' => spec.commands = true,
+                'd' => spec.directories = true,
+                'f' => spec.files = true,
+                'W' => match args.next() {
+                    Some(words) => {
+                        spec.words = words.split_whitespace().map(String::from).collect()
+                    }
+                    None => {
+                        let _ = writeln!(stderr, "complete: -W: option requires an argument");
+                        let _ = writeln!(stderr, "{}", usage);
+                        return 2;
+                    }
+                },
+                _ => {
+                    let _ = writeln!(stderr, "complete: -{}: invalid option", flag);
+                    let _ = writeln!(stderr, "{}", usage);
+                    return 2;
+                }
+            }
+        }
+    }
+
+    let mut status = 0;
+    if remove {
+        if names.is_empty() {
+            shell.completions.clear();
+        }
+        for name in &names {
+            if !shell.completions.remove(name) {
+                let _ = writeln!(stderr, "complete: {}: no completion specification", name);
+                status = 1;
+            }
+        }
+    } else if print || (names.is_empty() && spec == CompleteSpec::default()) {
+        let specs: Vec<_> = shell.completions.specs().collect();
+        if names.is_empty() {
+            for (name, spec) in &specs {
+                let _ = writeln!(stdout, "{}", spec.to_command(name));
+            }
+        }
+        for name in &names {
+            match specs.iter().find(|(n, _)| n == name) {
+                Some((name, spec)) => {
+                    let _ = writeln!(stdout, "{}", spec.to_command(name));
+                }
+                None => {
+                    let _ = writeln!(stderr, "complete: {}: no completion specification", name);
+                    status = 1;
+                }
+            }
+        }
+    } else if names.is_empty() {
+        let _ = writeln!(stderr, "{}", usage);
+        status = 2;
+    } else {
+        for name in &names {
+            shell.completions.define(name, spec.clone());
+        }
+    }
+    status
+}
+
+/// Implementation of the `bind` command.
+///
+/// Binds keys to line-editing commands with `bind '"\C-g": undo'` or to
+/// shell commands with `bind -x '"\C-g": command'`, removes bindings with
+/// `bind -r`, and reads inputrc-style bindings from a file with `bind -f`.
+/// `bind -l` lists the command names and `bind -p`/`bind -X` the bindings.
+/// Like inputrc lines, arguments may also be settings such as
+/// `set completion-query-items 200`.
+pub fn bind_cmd<W: Write, E: Write>(
+    args: Vec<String>,
+    stdout: &mut W,
+    stderr: &mut E,
+    shell: &mut Shell,
+) -> i32 {
+    let mut status = 0;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-l" => {
+                for (name, _) in EditCommand::ALL {
+                    let _ = writeln!(stdout, "{}", name);
+                }
+            }
+            "-p" | "-X" => {
+                for (keys, binding) in shell.keymap.bindings() {
+                    match binding {
+                        Binding::Command(command) if arg == "-p" => {
+                            let _ = writeln!(stdout, "\"{}\": {}", keys, command.name());
                         }
-                    } else {
-                        let _ = writeln!(stderr, "history: -r requires a filename argument");
-                        return ShellStatus::Continue;
-                    }
-                }
-
-                // Check for -a flag to append to file
-                if args.first().map(|s| s.as_str()) == Some("-a") {
-                    if let Some(filepath) = args.get(1) {
-                        match OpenOptions::new().create(true).append(true).open(filepath) {
-                            Ok(mut file) => {
-                                // Only append entries that haven't been saved yet
-                                for cmd in history.iter().skip(last_saved_index) {
-                                    if let Err(e) = writeln!(file, "{}", cmd) {
-                                        let _ = writeln!(
-                                            stderr,
-                                            "history: error appending to {}: {}",
-                                            filepath, e
-                                        );
-                                        return ShellStatus::Continue;
-                                    }
-                                }
-                                return ShellStatus::HistorySaved(history.len());
-                            }
-                            Err(e) => {
-                                let _ = writeln!(stderr, "history: {}: {}", filepath, e);
-                                return ShellStatus::Continue;
-                            }
+                        Binding::Shell(command) if arg == "-X" => {
+                            let _ = writeln!(stdout, "\"{}\": \"{}\"", keys, command);
                         }
-                    } else {
-                        let _ = writeln!(stderr, "history: -a requires a filename argument");
-                        return ShellStatus::Continue;
-                    }
-                }
-
-                // Check for -w flag to write to file
-                if args.first().map(|s| s.as_str()) == Some("-w") {
-                    if let Some(filepath) = args.get(1) {
-                        match File::create(filepath) {
-                            Ok(mut file) => {
-                                for cmd in history {
-                                    if let Err(e) = writeln!(file, "{}", cmd) {
-                                        let _ = writeln!(
-                                            stderr,
-                                            "history: error writing to {}: {}",
-                                            filepath, e
-                                        );
-                                        return ShellStatus::Continue;
-                                    }
-                                }
-                                return ShellStatus::HistorySaved(history.len());
-                            }
-                            Err(e) => {
-                                let _ = writeln!(stderr, "history: {}: {}", filepath, e);
-                                return ShellStatus::Continue;
-                            }
+                        _ => {}
+                    }
+                }
+            }
+            "-x" => match args.next().map(|line| keymap::parse_binding(line)) {
+                Some(Some((keys, command))) => shell.keymap.bind(keys, Binding::Shell(command)),
+                Some(None) => {
+                    let _ = writeln!(stderr, "bind: -x: invalid key binding");
+                    status = 1;
+                }
+                None => {
+                    let _ = writeln!(stderr, "bind: -x: option requires an argument");
+                    status = 2;
+                }
+            },
+            "-r" => match args.next() {
+                Some(sequence) => match keymap::parse_key_sequence(sequence.trim_matches('"')) {
+                    Some(keys) => {
+                        shell.keymap.unbind(&keys);
+                    }
+                    None => {
+                        let _ = writeln!(stderr, "bind: {}: invalid key sequence", sequence);
+                        status = 1;
+                    }
+                },
+                None => {
+                    let _ = writeln!(stderr, "bind: -r: option requires an argument");
+                    status = 2;
+                }
+            },
+            "-f" => match args.next() {
+                Some(path) => match std::fs::read_to_string(shell.cwd.join(path)) {
+                    Ok(contents) => {
+                        for line in contents.lines() {
+                            status = status.max(bind_inputrc_line(line, stderr, shell));
                         }
-                    } else {
-                        let _ = writeln!(stderr, "history: -w requires a filename argument");
-                        return ShellStatus::Continue;
                     }
+                    Err(e) => {
+                        let _ = writeln!(
+                            stderr,
+                            "bind: {}: {}",
+                            path,
+                            crate::redirect::describe_io_error(&e)
+                        );
+                        status = 1;
+                    }
+                },
+                None => {
+                    let _ = writeln!(stderr, "bind: -f: option requires an argument");
+                    status = 2;
                 }
+            },
+            option if option.starts_with('-') => {
+                let _ = writeln!(stderr, "bind: {}: invalid option", option);
+                let _ = writeln!(
+                    stderr,
+                    "bind: usage: bind [-lpX] [-f filename] [-r keyseq] [-x keyseq:shell-command] [keyseq:readline-function]"
+                );
+                return 2;
+            }
+            line => status = status.max(bind_inputrc_line(line, stderr, shell)),
+        }
+    }
+    status
+}
 
-                // Parse optional limit argument
-                let limit = args.first().and_then(|n_str| n_str.parse::<usize>().ok());
+/// Applies one line of an inputrc file read with `bind -f`: a key binding,
+/// `set editing-mode vi|emacs`, or a comment.
+fn bind_inputrc_line<E: Write>(line: &str, stderr: &mut E, shell: &mut Shell) -> i32 {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') || line.starts_with('$') {
+        return 0;
+    }
+    if let Some(setting) = line.strip_prefix("set ") {
+        let mut words = setting.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("editing-mode"), Some(mode)) if shell.options.set(mode, true).is_err() => {
+                let _ = writeln!(stderr, "bind: {}: invalid editing mode", mode);
+                return 1;
+            }
+            (Some("completion-query-items"), Some(value)) => match value.parse() {
+                Ok(items) => shell.options.completion_query_items = Some(items),
+                // readline treats negative values as never asking
+                Err(_) if value.starts_with('-') => shell.options.completion_query_items = Some(0),
+                Err(_) => {
+                    let _ = writeln!(stderr, "bind: {}: invalid number", value);
+                    return 1;
+                }
+            },
+            _ => {}
+        }
+        return 0;
+    }
+    bind_line(line, stderr, shell)
+}
 
-                // Calculate starting index
-                let start_idx = if let Some(n) = limit {
-                    if history.len() > n {
-                        history.len() - n
-                    } else {
-                        0
-                    }
-                } else {
-                    0
-                };
+/// Binds keys to a line-editing command given as `"keyseq": command-name`.
+fn bind_line<E: Write>(line: &str, stderr: &mut E, shell: &mut Shell) -> i32 {
+    let Some((keys, name)) = keymap::parse_binding(line) else {
+        let _ = writeln!(stderr, "bind: {}: invalid key binding", line);
+        return 1;
+    };
+    match EditCommand::from_name(&name) {
+        Some(command) => {
+            shell.keymap.bind(keys, Binding::Command(command));
+            0
+        }
+        None => {
+            let _ = writeln!(stderr, "bind: {}: unknown function name", name);
+            1
+        }
+    }
+}
 
-                // Display command history with line numbers
-                for (i, cmd) in history[start_idx..].iter().enumerate() {
-                    let _ = writeln!(stdout, "{:>5}  {}", start_idx + i + 1, cmd);
-                }
-                ShellStatus::Continue
+/// Looks for a relative `cd` target under the directories in `CDPATH`.
+///
+/// Empty `CDPATH` entries stand for the current directory, which `cd`
+/// tries anyway, so they are skipped.
+fn find_in_cdpath(path: &str, shell: &Shell) -> Option<PathBuf> {
+    let cdpath = shell.variables.get("CDPATH")?;
+    std::env::split_paths(&cdpath)
+        .filter(|base| !base.as_os_str().is_empty())
+        .map(|base| base.join(path))
+        .find(|dir| shell.cwd.join(dir).is_dir())
+}
+
+/// The escape sequence that clears the terminal's scrollback, `E3` in
+/// terminfo.
+const CLEAR_SCROLLBACK: &str = "\x1b[3J";
+
+/// Implementation of the `clear` command.
+///
+/// Clears the screen and the scrollback, as ncurses' `clear` does, without
+/// running it. With `-x`, the scrollback is kept.
+pub fn clear_cmd<W: Write, E: Write>(args: Vec<String>, stdout: &mut W, stderr: &mut E) -> i32 {
+    let mut scrollback = true;
+    for arg in &args {
+        match arg.as_str() {
+            "-x" => scrollback = false,
+            _ => {
+                let _ = writeln!(stderr, "clear: {}: invalid option", arg);
+                let _ = writeln!(stderr, "clear: usage: clear [-x]");
+                return 2;
             }
         }
     }
+    let _ = write!(stdout, "{}{}", cursor::Goto(1, 1), clear::All);
+    if scrollback {
+        let _ = write!(stdout, "{}", CLEAR_SCROLLBACK);
+    }
+    let _ = stdout.flush();
+    0
 }
 
 /// Implementation of the `echo` command.
 ///
 /// Prints the arguments to stdout, separated by spaces.
-pub fn echo_cmd<W: Write>(args: Vec<String>, writer: &mut W) {
-    let _ = writeln!(writer, "{}", args.join(" "));
+pub fn echo_cmd<W: Write>(args: Vec<String>, writer: &mut W) -> i32 {
+    match writeln!(writer, "{}", args.join(" ")) {
+        Ok(()) => 0,
+        Err(_) => 1,
+    }
 }
 
 /// Implementation of the `type` command.
 ///
 /// Identifies whether a command is a builtin or an executable in the PATH.
-pub fn type_cmd<W: Write, E: Write>(args: Vec<String>, stdout: &mut W, stderr: &mut E) {
-    let command = match args.first() {
+/// With `-a`, lists every builtin and executable the name could mean, in
+/// the order the shell looks for them.
+pub fn type_cmd<W: Write, E: Write>(
+    args: Vec<String>,
+    stdout: &mut W,
+    stderr: &mut E,
+    shell: &Shell,
+) -> i32 {
+    let all = args.first().is_some_and(|arg| arg == "-a");
+    let command = match args.get(usize::from(all)) {
         Some(cmd) => cmd,
         None => {
-            return;
+            return 0;
         }
     };
+    let mut found = false;
     // 1. Check if it's a builtin
     if Builtin::from_str(command).is_ok() {
         let _ = writeln!(stdout, "{} is a shell builtin", command);
-        return;
+        if !all {
+            return 0;
+        }
+        found = true;
     }
 
     // 2. External command check
-    match get_executable_path(command) {
-        Some(path) => {
-            let _ = writeln!(stdout, "{} is {}", command, path.display());
+    let paths = if all && !command.contains('/') {
+        let path = shell.variables.get("PATH").unwrap_or_default();
+        crate::executables::with_index(&path, |index| {
+            index
+                .find_all(command)
+                .map(|entry| entry.path.clone())
+                .collect()
+        })
+    } else {
+        crate::exec::resolve_command(command, shell)
+            .into_iter()
+            .collect::<Vec<_>>()
+    };
+    for path in &paths {
+        let _ = writeln!(stdout, "{} is {}", command, path.display());
+    }
+    if !found && paths.is_empty() {
+        let _ = writeln!(stderr, "{}: not found", command);
+        return 1;
+    }
+    0
+}
+
+/// Implementation of the `source` (or `.`) command.
+///
+/// Runs the commands of a file in the current shell, so that options and
+/// the like it sets stay in effect. An `exit` in the file exits the shell.
+pub fn source_cmd<E: Write>(args: Vec<String>, stderr: &mut E, shell: &mut Shell) -> CommandResult {
+    let Some(path) = args.first() else {
+        let _ = writeln!(stderr, "source: filename argument required");
+        let _ = writeln!(stderr, "source: usage: source filename [arguments]");
+        return CommandResult::finished(2);
+    };
+    match crate::source_file(Path::new(path), shell) {
+        Ok(ShellStatus::Exit(code)) => CommandResult::exit(code),
+        Ok(ShellStatus::Continue) => CommandResult::finished(shell.variables.status()),
+        Err(e) => {
+            let _ = writeln!(
+                stderr,
+                "source: {}: {}",
+                path,
+                crate::redirect::describe_io_error(&e)
+            );
+            CommandResult::finished(1)
+        }
+    }
+}
+
+/// Implementation of the `eval` command.
+///
+/// Joins its arguments with spaces and runs the result as commands of the
+/// current shell. The status is that of the last command run, or 0 if
+/// there was none.
+pub fn eval_cmd(args: Vec<String>, shell: &mut Shell) -> CommandResult {
+    shell.variables.set_status(0);
+    match crate::exec::eval_script(shell, &args.join(" ")) {
+        ShellStatus::Exit(code) => CommandResult::exit(code),
+        ShellStatus::Continue => CommandResult::finished(shell.variables.status()),
+    }
+}
+
+/// Implementation of the `export` command.
+///
+/// Exports variables to the commands the shell runs, assigning them first
+/// with `export name=value`. `export -n name` stops exporting a variable,
+/// and `export` or `export -p` lists the exported variables.
+pub fn export_cmd<W: Write, E: Write>(
+    args: Vec<String>,
+    stdout: &mut W,
+    stderr: &mut E,
+    shell: &mut Shell,
+) -> i32 {
+    let unexport = args.first().is_some_and(|arg| arg == "-n");
+    let names = match args.first().map(|s| s.as_str()) {
+        None | Some("-p") => {
+            for (name, value) in shell.variables.exported() {
+                let _ = writeln!(stdout, "export {}='{}'", name, value.replace('\'', "'\\''"));
+            }
+            return 0;
+        }
+        Some("-n") => &args[1..],
+        Some(option) if option.starts_with('-') => {
+            let _ = writeln!(stderr, "export: {}: invalid option", option);
+            let _ = writeln!(
+                stderr,
+                "export: usage: export [-n] [name[=value] ...] or export -p"
+            );
+            return 2;
+        }
+        Some(_) => &args[..],
+    };
+
+    let mut status = 0;
+    for arg in names {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if !variables::is_name(name) {
+            let _ = writeln!(stderr, "export: `{}': not a valid identifier", arg);
+            status = 1;
+            continue;
+        }
+        if let Err(message) = shell.options.check_assignment(name) {
+            let _ = writeln!(stderr, "export: {}", message);
+            status = 1;
+            continue;
+        }
+        if unexport {
+            if let Some(value) = value {
+                shell.variables.set(name, value);
+            }
+            shell.variables.unexport(name);
+        } else {
+            shell.variables.export(name, value);
+        }
+    }
+    status
+}
+
+/// Implementation of the `unset` command.
+///
+/// Removes shell variables, and exported variables from the environment.
+pub fn unset_cmd<E: Write>(args: Vec<String>, stderr: &mut E, shell: &mut Shell) -> i32 {
+    let names = match args.first().map(|s| s.as_str()) {
+        Some("-v") => &args[1..],
+        Some(option) if option.starts_with('-') => {
+            let _ = writeln!(stderr, "unset: {}: invalid option", option);
+            let _ = writeln!(stderr, "unset: usage: unset [-v] [name ...]");
+            return 2;
         }
+        _ => &args[..],
+    };
+    let mut status = 0;
+    for name in names {
+        if !variables::is_name(name) {
+            let _ = writeln!(stderr, "unset: `{}': not a valid identifier", name);
+            status = 1;
+        } else if let Err(message) = shell.options.check_assignment(name) {
+            let _ = writeln!(stderr, "unset: {}", message);
+            status = 1;
+        } else {
+            shell.variables.unset(name);
+        }
+    }
+    status
+}
+
+/// Implementation of the `history` command.
+///
+/// Lists the history, or its last `N` entries with `history N`. `-c`
+/// clears it and `-d offset` deletes one entry (counted from the end if
+/// negative). `-r`, `-a` and `-w` read a file into the history (which may
+/// also be a bash or zsh history file with timestamps), append the
+/// new entries to a file, and write the whole history to a file; the file
+/// defaults to the history file. `history search pattern` lists the
+/// entries containing the pattern, and `history --json` prints the history
+/// as JSON.
+pub fn history_cmd<W: Write, E: Write>(
+    args: Vec<String>,
+    stdout: &mut W,
+    stderr: &mut E,
+    shell: &mut Shell,
+) -> i32 {
+    let history = &mut shell.history;
+    let flag = args.first().map(|s| s.as_str());
+    match flag {
+        Some("-c") => history.clear(),
+        Some("--json") => {
+            let _ = writeln!(stdout, "{}", history.to_json());
+        }
+        Some("search") => {
+            if args.len() < 2 {
+                let _ = writeln!(stderr, "history: usage: history search pattern");
+                return 2;
+            }
+            // Several words are searched for as one phrase
+            let pattern = args[1..].join(" ");
+            for (i, cmd) in history.iter().enumerate() {
+                if cmd.contains(&pattern) {
+                    let _ = writeln!(stdout, "{:>5}  {}", history.number(i), cmd);
+                }
+            }
+        }
+        Some("-d") => {
+            let Some(offset) = args.get(1) else {
+                let _ = writeln!(stderr, "history: -d: option requires an argument");
+                return 2;
+            };
+            // Positions count from 1; negative ones from the end
+            let index = match offset.parse::<isize>() {
+                Ok(n) if n > 0 => history.index_of(n as usize),
+                Ok(n) if n < 0 => history.len().checked_sub(n.unsigned_abs()),
+                _ => None,
+            };
+            if index.and_then(|index| history.remove(index)).is_none() {
+                let _ = writeln!(stderr, "history: {}: history position out of range", offset);
+                return 1;
+            }
+        }
+        Some(flag @ ("-r" | "-a" | "-w")) => {
+            let Some(path) = args
+                .get(1)
+                .map(PathBuf::from)
+                .or_else(|| History::file(&shell.variables))
+            else {
+                let _ = writeln!(stderr, "history: {} requires a filename argument", flag);
+                return 1;
+            };
+            let filepath = path.display();
+            let path = shell.cwd.join(&path);
+            history.set_timestamps(History::timestamps(&shell.variables));
+            match flag {
+                "-r" => match fs::read(&path) {
+                    Ok(contents) => {
+                        let entries = history::parse_history(&contents);
+                        history.extend(entries);
+                    }
+                    Err(e) => {
+                        let _ = writeln!(stderr, "history: {}: {}", filepath, e);
+                        return 1;
+                    }
+                },
+                "-a" => match OpenOptions::new().create(true).append(true).open(&path) {
+                    Ok(mut file) => {
+                        // Only append entries that haven't been saved yet
+                        let lines = history.formatted(history.saved());
+                        if let Err(e) = file.write_all(lines.as_bytes()) {
+                            let _ =
+                                writeln!(stderr, "history: error appending to {}: {}", filepath, e);
+                            return 1;
+                        }
+                        history.set_saved(history.len());
+                    }
+                    Err(e) => {
+                        let _ = writeln!(stderr, "history: {}: {}", filepath, e);
+                        return 1;
+                    }
+                },
+                _ => match File::create(&path) {
+                    Ok(mut file) => {
+                        if let Err(e) = file.write_all(history.formatted(0).as_bytes()) {
+                            let _ =
+                                writeln!(stderr, "history: error writing to {}: {}", filepath, e);
+                            return 1;
+                        }
+                        history.set_saved(history.len());
+                    }
+                    Err(e) => {
+                        let _ = writeln!(stderr, "history: {}: {}", filepath, e);
+                        return 1;
+                    }
+                },
+            }
+        }
+        _ => {
+            // Parse optional limit argument
+            let limit = flag.and_then(|n_str| n_str.parse::<usize>().ok());
+            let start_idx = limit.map_or(0, |n| history.len().saturating_sub(n));
+
+            // Display command history with line numbers
+            for (i, cmd) in history.iter().enumerate().skip(start_idx) {
+                let _ = writeln!(stdout, "{:>5}  {}", history.number(i), cmd);
+            }
+        }
+    }
+    0
+}
+
+/// Implementation of the `set` command.
+///
+/// Supports `set -o name` / `set +o name` to turn options on and off, and
+/// lists the options with `set -o` (as a table) or `set +o` (as commands).
+/// `set -n` and `set +n` are short for `set -o noexec` and `set +o noexec`.
+/// With no arguments, lists the shell variables and their values.
+pub fn set_cmd<W: Write, E: Write>(
+    args: Vec<String>,
+    stdout: &mut W,
+    stderr: &mut E,
+    shell: &mut Shell,
+) -> i32 {
+    let enable = match args.first().map(|s| s.as_str()) {
+        Some("-o" | "-n") => true,
+        Some("+o" | "+n") => false,
+        Some(other) => {
+            let _ = writeln!(stderr, "set: {}: invalid option", other);
+            let _ = writeln!(stderr, "set: usage: set [-n] [-o option] [+o option]");
+            return 2;
+        }
+        None => {
+            for (name, value) in shell.variables.all() {
+                let _ = writeln!(stdout, "{}='{}'", name, value.replace('\'', "'\\''"));
+            }
+            return 0;
+        }
+    };
+
+    let name = match args[0].as_str() {
+        "-n" | "+n" => Some("noexec"),
+        _ => args.get(1).map(|name| name.as_str()),
+    };
+    match name {
+        Some(name) => {
+            if let Err(message) = shell.options.set(name, enable) {
+                let _ = writeln!(stderr, "set: {}", message);
+                return 1;
+            }
+        }
+        None => {
+            for name in crate::ShellOptions::NAMES {
+                let on = shell.options.get(name).unwrap_or(false);
+                if enable {
+                    let _ = writeln!(stdout, "{:<15}\t{}", name, if on { "on" } else { "off" });
+                } else {
+                    let _ = writeln!(stdout, "set {}o {}", if on { '-' } else { '+' }, name);
+                }
+            }
+        }
+    }
+    0
+}
+
+/// Implementation of the `shopt` command.
+///
+/// `shopt -s name` and `shopt -u name` turn shell options on and off, and
+/// `-o` works on the `set -o` options instead. Without names, lists the
+/// options (only those on with `-s`, off with `-u`); with `-p`, as the
+/// commands that restore them, so that `shopt -p` and `shopt -op` can be
+/// saved to the options file read at startup.
+pub fn shopt_cmd<W: Write, E: Write>(
+    args: Vec<String>,
+    stdout: &mut W,
+    stderr: &mut E,
+    shell: &mut Shell,
+) -> i32 {
+    let usage = "shopt: usage: shopt [-pou] [-s] [optname ...]";
+    let mut enable = None;
+    let mut print = false;
+    let mut set_options = false;
+    let mut names = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let Some(flags) = arg.strip_prefix('-').filter(|flags| !flags.is_empty()) else {
+            names.push(arg);
+            names.extend(args.by_ref());
+            break;
+        };
+        for flag in flags.chars() {
+            match flag {
+                's' => enable = Some(true),
+                'u' => enable = Some(false),
+                'p' => print = true,
+                'o' => set_options = true,
+                _ => {
+                    let _ = writeln!(stderr, "shopt: -{}: invalid option", flag);
+                    let _ = writeln!(stderr, "{}", usage);
+                    return 2;
+                }
+            }
+        }
+    }
+
+    let get = |shell: &Shell, name: &str| {
+        if set_options {
+            shell.options.get(name)
+        } else {
+            shell.options.shopt(name)
+        }
+    };
+
+    let mut status = 0;
+    if let Some(enabled) = enable
+        && !names.is_empty()
+    {
+        for name in &names {
+            let result = if set_options {
+                shell.options.set(name, enabled)
+            } else {
+                shell.options.set_shopt(name, enabled)
+            };
+            if let Err(message) = result {
+                let _ = writeln!(stderr, "shopt: {}", message);
+                status = 1;
+            }
+        }
+        return status;
+    }
+
+    // Asking about options by name succeeds only if they are all on
+    let queried = !names.is_empty();
+    if names.is_empty() {
+        let all = if set_options {
+            crate::ShellOptions::NAMES
+        } else {
+            crate::ShellOptions::SHOPT_NAMES
+        };
+        names = all.iter().map(|name| name.to_string()).collect();
+    }
+    for name in &names {
+        let Some(on) = get(shell, name) else {
+            let _ = writeln!(stderr, "shopt: {}: invalid shell option name", name);
+            status = 1;
+            continue;
+        };
+        if queried && !on {
+            status = 1;
+        }
+        if enable.is_some_and(|enabled| enabled != on) {
+            continue;
+        }
+        if !print {
+            let _ = writeln!(stdout, "{:<15}\t{}", name, if on { "on" } else { "off" });
+        } else if set_options {
+            let _ = writeln!(stdout, "set {}o {}", if on { '-' } else { '+' }, name);
+        } else {
+            let _ = writeln!(stdout, "shopt {} {}", if on { "-s" } else { "-u" }, name);
+        }
+    }
+    status
+}
+
+/// Implementation of the `plugin` command.
+///
+/// `plugin load path` loads a plugin library, adding the builtins,
+/// completers and prompt segments it provides (see [`crate::plugin`]).
+/// `plugin list` (or no arguments) lists the loaded plugins and the
+/// builtins they provide.
+pub fn plugin_cmd<W: Write, E: Write>(
+    args: Vec<String>,
+    stdout: &mut W,
+    stderr: &mut E,
+    shell: &mut Shell,
+) -> i32 {
+    match args.first().map(|s| s.as_str()) {
+        None | Some("list") => {
+            for path in shell.plugins.loaded() {
+                let _ = writeln!(stdout, "{}", path.display());
+            }
+            let names: Vec<&str> = shell.plugins.builtin_names().collect();
+            if !names.is_empty() {
+                let _ = writeln!(stdout, "builtins: {}", names.join(" "));
+            }
+        }
+        Some("load") => {
+            let Some(path) = args.get(1) else {
+                let _ = writeln!(stderr, "plugin: load: filename argument required");
+                return 2;
+            };
+            if let Err(message) = shell.plugins.load(&shell.cwd.join(path)) {
+                let _ = writeln!(stderr, "plugin: {}: {}", path, message);
+                return 1;
+            }
+        }
+        Some(other) => {
+            let _ = writeln!(stderr, "plugin: {}: invalid subcommand", other);
+            let _ = writeln!(stderr, "plugin: usage: plugin load path | plugin list");
+            return 2;
+        }
+    }
+    0
+}
+
+/// Implementation of the `j` command.
+///
+/// `j pattern...` changes to the visited directory with the highest
+/// frecency whose path contains each pattern in order, and prints it.
+/// `j -l pattern...` (or no arguments) lists the matching directories with
+/// their scores instead, best last, as z does.
+pub fn jump_cmd<W: Write, E: Write>(
+    args: Vec<String>,
+    stdout: &mut W,
+    stderr: &mut E,
+    shell: &mut Shell,
+) -> i32 {
+    let list = args.first().is_some_and(|arg| arg == "-l");
+    let patterns: Vec<&str> = args
+        .iter()
+        .skip(list as usize)
+        .map(|s| s.as_str())
+        .collect();
+    let dirs = match Frecency::file(&shell.variables) {
+        Some(path) => match Frecency::load(&path) {
+            Ok(dirs) => dirs,
+            Err(e) => {
+                let _ = writeln!(stderr, "j: {}: {}", path.display(), e);
+                return 1;
+            }
+        },
+        None => Frecency::default(),
+    };
+    let now = jump::now();
+
+    if list || patterns.is_empty() {
+        for entry in dirs.matching(&patterns, now).iter().rev() {
+            let _ = writeln!(
+                stdout,
+                "{:<10.1} {}",
+                entry.frecency(now),
+                entry.path.display()
+            );
+        }
+        return 0;
+    }
+
+    let Some(dir) = dirs.best(&patterns, now) else {
+        let _ = writeln!(stderr, "j: no match for {}", patterns.join(" "));
+        return 1;
+    };
+    if let Err(e) = shell.change_dir(dir) {
+        let _ = writeln!(stderr, "j: {}: {}", dir.display(), e);
+        return 1;
+    }
+    let _ = writeln!(stdout, "{}", dir.display());
+    0
+}
+
+/// Implementation of the `trap` command.
+///
+/// `trap command EXIT` runs the command as the shell exits, whether by
+/// `exit`, the end of its input, or SIGTERM or SIGHUP. `trap - EXIT` (or
+/// `trap EXIT`) removes it, and `trap` or `trap -p` lists it. `0` is
+/// another name for `EXIT`. Traps on signals aren't supported.
+pub fn trap_cmd<W: Write, E: Write>(
+    args: Vec<String>,
+    stdout: &mut W,
+    stderr: &mut E,
+    shell: &mut Shell,
+) -> i32 {
+    let show = |stdout: &mut W, condition: &str, command: &str| {
+        let _ = writeln!(
+            stdout,
+            "trap -- '{}' {}",
+            command.replace('\'', "'\\''"),
+            condition
+        );
+    };
+    let mut status = 0;
+    match args.first().map(|s| s.as_str()) {
         None => {
-            let _ = writeln!(stderr, "{}: not found", command);
+            for (condition, command) in &shell.traps {
+                show(stdout, condition, command);
+            }
         }
+        Some("-p") => {
+            let names = &args[1..];
+            for (name, command) in &shell.traps {
+                if names.is_empty() || names.iter().any(|n| trap_condition(n) == Some(name)) {
+                    show(stdout, name, command);
+                }
+            }
+        }
+        Some(_) => {
+            // A lone condition, like `-`, resets it
+            let (command, names) = match args.split_first() {
+                Some((command, names)) if !names.is_empty() => (command.as_str(), names),
+                _ => ("-", &args[..]),
+            };
+            for name in names {
+                let Some(name) = trap_condition(name) else {
+                    let _ = writeln!(stderr, "trap: {}: only EXIT can be trapped", name);
+                    status = 1;
+                    continue;
+                };
+                if command == "-" {
+                    shell.traps.remove(name);
+                } else {
+                    shell.traps.insert(name.to_string(), command.to_string());
+                }
+            }
+        }
+    }
+    status
+}
+
+/// Returns the condition a `trap` argument names, or `None` if it isn't
+/// one that can be trapped.
+fn trap_condition(name: &str) -> Option<&'static str> {
+    match name {
+        "EXIT" | "0" => Some("EXIT"),
+        _ => None,
+    }
+}
+
+/// Implementation of the `read` command.
+///
+/// Reads a line from stdin and splits it at the characters of `IFS` into
+/// the named variables, the last one getting the rest of the line, or
+/// puts all of it in `REPLY` if none are named. A backslash quotes the
+/// next character, and joins lines before a newline, unless `-r` is given.
+/// `-d delim` reads up to the first character of `delim` instead of a
+/// newline, up to a NUL byte if it is empty, `-n count` stops after
+/// `count` characters, and `-a name` assigns every field to the array
+/// `name`. Returns 1 if the end of input came first.
+pub fn read_cmd<E: Write>(args: Vec<String>, stderr: &mut E, shell: &mut Shell) -> i32 {
+    let mut raw = false;
+    let mut delimiter = "\n".to_string();
+    let mut limit = None;
+    let mut array = None;
+    let mut args = args.into_iter();
+    let mut names = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-r" => raw = true,
+            "-a" | "-d" | "-n" => {
+                let Some(value) = args.next() else {
+                    let _ = writeln!(stderr, "read: {}: option requires an argument", arg);
+                    return 2;
+                };
+                if arg == "-a" {
+                    array = Some(value);
+                } else if arg == "-d" {
+                    delimiter = value.chars().next().unwrap_or('\0').to_string();
+                } else {
+                    match value.parse::<usize>() {
+                        Ok(count) => limit = Some(count),
+                        Err(_) => {
+                            let _ = writeln!(stderr, "read: {}: invalid number", value);
+                            return 1;
+                        }
+                    }
+                }
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                let _ = writeln!(stderr, "read: {}: invalid option", arg);
+                let _ = writeln!(
+                    stderr,
+                    "read: usage: read [-r] [-a array] [-d delim] [-n nchars] [name ...]"
+                );
+                return 2;
+            }
+            _ => names.push(arg),
+        }
+    }
+    for name in array.iter().chain(&names) {
+        if !variables::is_name(name) {
+            let _ = writeln!(stderr, "read: `{}': not a valid identifier", name);
+            return 1;
+        }
+        if let Err(message) = shell.options.check_assignment(name) {
+            let _ = writeln!(stderr, "read: {}", message);
+            return 1;
+        }
+    }
+
+    // Read a byte at a time, so that nothing after the delimiter is taken
+    // from input that other commands read next, and gather the bytes of
+    // each character to compare it with the delimiter
+    let mut input = shell.streams.stdin();
+    let mut line = Vec::new();
+    let mut character = Vec::with_capacity(4);
+    let mut escaped = false;
+    let mut eof = false;
+    let mut byte = [0u8];
+    while limit.is_none_or(|count| count > 0) {
+        match input.read(&mut byte) {
+            Ok(0) => {
+                line.append(&mut character);
+                eof = true;
+                break;
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                let _ = writeln!(stderr, "read: read error: {}", e);
+                return 1;
+            }
+        }
+        character.push(byte[0]);
+        let length = match character[0] {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
+        };
+        if character.len() < length {
+            continue;
+        }
+        match character.as_slice() {
+            b"\\" if !raw && !escaped => {
+                character.clear();
+                escaped = true;
+                continue;
+            }
+            // A backslash before the delimiter continues the line
+            c if c == delimiter.as_bytes() && escaped => character.clear(),
+            c if c == delimiter.as_bytes() => break,
+            _ => line.append(&mut character),
+        }
+        escaped = false;
+        if let Some(count) = limit
+            && std::str::from_utf8(&line).is_ok_and(|text| text.chars().count() >= count)
+        {
+            break;
+        }
+    }
+    let line = String::from_utf8_lossy(&line);
+    // What was read before the end of input is still assigned
+    let status = i32::from(eof);
+
+    let ifs = shell
+        .variables
+        .get("IFS")
+        .unwrap_or_else(|| DEFAULT_IFS.to_string());
+    if let Some(name) = array {
+        let fields = read_fields(&line, &ifs, usize::MAX);
+        shell.variables.set_array(&name, fields);
+        return status;
+    }
+    if names.is_empty() {
+        shell.variables.set("REPLY", &line);
+        return status;
+    }
+    let mut fields = read_fields(&line, &ifs, names.len()).into_iter();
+    for name in &names {
+        shell
+            .variables
+            .set(name, &fields.next().unwrap_or_default());
+    }
+    status
+}
+
+/// Splits a line `read` read into at most `count` fields at the characters
+/// of `ifs`, the last field keeping the rest of the line. As in field
+/// splitting, a run of `ifs` whitespace is one separator, and is trimmed
+/// from both ends.
+fn read_fields(line: &str, ifs: &str, count: usize) -> Vec<String> {
+    let is_space = |c: char| c.is_whitespace() && ifs.contains(c);
+    let is_separator = |c: char| ifs.contains(c);
+    let mut fields = Vec::new();
+    let mut rest = line.trim_start_matches(is_space);
+    while fields.len() + 1 < count
+        && let Some(end) = rest.find(is_separator)
+    {
+        fields.push(rest[..end].to_string());
+        // Whitespace around a separator such as `:` belongs to it
+        let after = rest[end..].trim_start_matches(is_space);
+        let after = after
+            .strip_prefix(|c: char| is_separator(c) && !is_space(c))
+            .unwrap_or(after);
+        rest = after.trim_start_matches(is_space);
+    }
+    let rest = rest.trim_end_matches(is_space);
+    if !rest.is_empty() {
+        fields.push(rest.to_string());
     }
+    fields
 }
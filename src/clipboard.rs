@@ -0,0 +1,83 @@
+/// The characters of base64, in the order of their values.
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The OSC 52 sequence asking the terminal for the clipboard's contents,
+/// which it answers with a [`copy_sequence`] holding them, if it allows it.
+pub const PASTE_QUERY: &str = "\x1b]52;c;?\x07";
+
+/// Returns the OSC 52 sequence that puts `text` on the system clipboard.
+///
+/// The terminal, rather than the shell, sets the clipboard, so this works
+/// over SSH too. Terminals may ignore it, or limit its length.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::clipboard::copy_sequence;
+/// assert_eq!(copy_sequence("ls -la"), "\x1b]52;c;bHMgLWxh\x07");
+/// assert_eq!(copy_sequence("hi"), "\x1b]52;c;aGk=\x07");
+/// ```
+pub fn copy_sequence(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", encode(text.as_bytes()))
+}
+
+/// Parses the terminal's answer to [`PASTE_QUERY`], returning the text on
+/// the clipboard. The answer ends with BEL or ESC `\`.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::clipboard::parse_reply;
+/// assert_eq!(parse_reply(b"\x1b]52;c;aGVsbG8=\x1b\\").as_deref(), Some("hello"));
+/// assert_eq!(parse_reply(b"\x1b]52;c;?\x07"), None);
+/// ```
+pub fn parse_reply(reply: &[u8]) -> Option<String> {
+    let reply = reply.strip_prefix(b"\x1b]52;")?;
+    let end = reply
+        .iter()
+        .position(|&b| b == 0x07 || b == 0x1b)
+        .unwrap_or(reply.len());
+    let (_, data) = reply[..end].split_at(reply.iter().position(|&b| b == b';')? + 1);
+    String::from_utf8(decode(data)?).ok()
+}
+
+/// Encodes bytes as base64, padded with `=`.
+fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes base64, with or without padding. Returns `None` for anything
+/// that isn't base64.
+fn decode(data: &[u8]) -> Option<Vec<u8>> {
+    let data = data
+        .strip_suffix(b"==")
+        .or(data.strip_suffix(b"="))
+        .unwrap_or(data);
+    let mut decoded = Vec::with_capacity(data.len() * 3 / 4);
+    for chunk in data.chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut n = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let value = BASE64.iter().position(|&b| b == c)? as u32;
+            n |= value << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            decoded.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(decoded)
+}
@@ -0,0 +1,101 @@
+use std::cmp::Ordering;
+use std::env;
+
+/// Compares two strings in the order names are listed to the user.
+///
+/// Names compare case-insensitively, with accented Latin letters sorting
+/// beside their base letter, so that `Äpfel` and `apple` come before
+/// `Banana` and `zebra`. Names equal apart from that keep a stable byte order.
+///
+/// If the collation locale (`LC_ALL`, `LC_COLLATE` or `LANG`, the first one
+/// set) is `C` or `POSIX`, names compare byte by byte instead, as in `ls`.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::collate;
+///
+/// let mut names = vec!["zebra", "Banana", "apple", "Apple", "Äpfel"];
+/// names.sort_by(|a, b| collate::compare_folded(a, b));
+/// assert_eq!(names, vec!["Äpfel", "Apple", "apple", "Banana", "zebra"]);
+/// ```
+pub fn compare(a: &str, b: &str) -> Ordering {
+    if byte_order() {
+        a.cmp(b)
+    } else {
+        compare_folded(a, b)
+    }
+}
+
+/// Compares two strings ignoring case and accents, falling back to byte
+/// order for names that are otherwise equal. This is the order `compare`
+/// uses outside the C locale.
+pub fn compare_folded(a: &str, b: &str) -> Ordering {
+    sort_key(a).cmp(&sort_key(b)).then_with(|| a.cmp(b))
+}
+
+/// Sorts names with `compare`.
+pub fn sort(names: &mut [String]) {
+    if byte_order() {
+        names.sort();
+    } else {
+        // Ties on the key keep byte order, as in `compare_folded`
+        names.sort();
+        names.sort_by_cached_key(|name| sort_key(name));
+    }
+}
+
+/// Checks whether the collation locale asks for plain byte order.
+fn byte_order() -> bool {
+    let locale = ["LC_ALL", "LC_COLLATE", "LANG"]
+        .into_iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty());
+    locale.is_some_and(|locale| locale == "C" || locale == "POSIX" || locale.starts_with("C."))
+}
+
+/// Returns the key a name sorts by: lowercase, with the accents of Latin
+/// letters removed and ligatures spelled out.
+fn sort_key(name: &str) -> String {
+    let mut key = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            'Æ' | 'æ' => key.push_str("ae"),
+            'Œ' | 'œ' => key.push_str("oe"),
+            'Ĳ' | 'ĳ' => key.push_str("ij"),
+            'Þ' | 'þ' => key.push_str("th"),
+            'ß' => key.push_str("ss"),
+            _ => match base_letter(c) {
+                Some(base) => key.push(base),
+                None => key.extend(c.to_lowercase()),
+            },
+        }
+    }
+    key
+}
+
+/// Returns the unaccented lowercase letter of an accented Latin letter.
+fn base_letter(c: char) -> Option<char> {
+    let base = match c {
+        'À'..='Å' | 'à'..='å' | '\u{100}'..='\u{105}' => 'a',
+        'Ç' | 'ç' | '\u{106}'..='\u{10D}' => 'c',
+        'Ð' | 'ð' | '\u{10E}'..='\u{111}' => 'd',
+        'È'..='Ë' | 'è'..='ë' | '\u{112}'..='\u{11B}' => 'e',
+        '\u{11C}'..='\u{123}' => 'g',
+        '\u{124}'..='\u{127}' => 'h',
+        'Ì'..='Ï' | 'ì'..='ï' | '\u{128}'..='\u{131}' => 'i',
+        '\u{134}'..='\u{135}' => 'j',
+        '\u{136}'..='\u{138}' => 'k',
+        '\u{139}'..='\u{142}' => 'l',
+        'Ñ' | 'ñ' | '\u{143}'..='\u{14B}' => 'n',
+        'Ò'..='Ö' | 'Ø' | 'ò'..='ö' | 'ø' | '\u{14C}'..='\u{151}' => 'o',
+        '\u{154}'..='\u{159}' => 'r',
+        '\u{15A}'..='\u{161}' | 'ſ' => 's',
+        '\u{162}'..='\u{167}' => 't',
+        'Ù'..='Ü' | 'ù'..='ü' | '\u{168}'..='\u{173}' => 'u',
+        '\u{174}'..='\u{175}' => 'w',
+        'Ý' | 'ý' | 'ÿ' | '\u{176}'..='\u{178}' => 'y',
+        '\u{179}'..='\u{17E}' => 'z',
+        _ => return None,
+    };
+    Some(base)
+}
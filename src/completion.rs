@@ -0,0 +1,831 @@
+use crate::builtins::Builtin;
+use crate::collate;
+use crate::executables;
+use crate::history::History;
+use crate::shell::Shell;
+use crate::terminal;
+use crate::variables::Variables;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::{env, fmt, fs};
+
+/// Generates the completions for the arguments of a command, registered
+/// with [`Completions::register`].
+pub trait Completer: fmt::Debug + Send + Sync {
+    /// Returns the possible completions of the word of `context`, under
+    /// the current shell `options`.
+    ///
+    /// Those not starting with the word are dropped, so a completer may
+    /// simply return everything it knows of.
+    fn complete(&self, context: &CompletionContext, shell: &Shell) -> Vec<String>;
+}
+
+/// Completions defined with the `complete` builtin.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompleteSpec {
+    /// Words given with `-W`.
+    pub words: Vec<String>,
+    /// Complete directory names (`-d`).
+    pub directories: bool,
+    /// Complete file names (`-f`).
+    pub files: bool,
+    /// Complete command names (`-c`).
+    pub commands: bool,
+}
+
+impl CompleteSpec {
+    /// Returns the `complete` command that defines this spec for `name`,
+    /// as printed by `complete -p`.
+    pub fn to_command(&self, name: &str) -> String {
+        let mut command = String::from("complete");
+        for (enabled, flag) in [
+            (self.commands, " -c"),
+            (self.directories, " -d"),
+            (self.files, " -f"),
+        ] {
+            if enabled {
+                command.push_str(flag);
+            }
+        }
+        if !self.words.is_empty() {
+            command.push_str(&format!(
+                " -W '{}'",
+                self.words.join(" ").replace('\'', "'\\''")
+            ));
+        }
+        format!("{} {}", command, name)
+    }
+}
+
+impl Completer for CompleteSpec {
+    fn complete(&self, context: &CompletionContext, shell: &Shell) -> Vec<String> {
+        let mut found = self.words.clone();
+        if self.commands {
+            found.extend(command_names(&context.word, shell));
+        }
+        if self.files {
+            found.extend(paths(Path::new(""), &context.word, shell));
+        } else if self.directories {
+            found.extend(directories(&context.word, shell));
+        }
+        found
+    }
+}
+
+/// The completions registered for specific commands, consulted before
+/// falling back to file names. Clones share them until one of them is
+/// changed.
+#[derive(Debug, Clone, Default)]
+pub struct Completions {
+    specs: Arc<BTreeMap<String, Spec>>,
+    options: Arc<BTreeMap<String, Vec<String>>>,
+}
+
+#[derive(Debug, Clone)]
+enum Spec {
+    Builtin(CompleteSpec),
+    Custom(Arc<dyn Completer>),
+}
+
+impl Completions {
+    /// Defines the completions for `command`, as `complete` does.
+    pub fn define(&mut self, command: &str, spec: CompleteSpec) {
+        Arc::make_mut(&mut self.specs).insert(command.to_string(), Spec::Builtin(spec));
+    }
+
+    /// Registers a completer for `command`, replacing any previous one.
+    pub fn register<C: Completer + 'static>(&mut self, command: &str, completer: C) {
+        Arc::make_mut(&mut self.specs)
+            .insert(command.to_string(), Spec::Custom(Arc::new(completer)));
+    }
+
+    /// Removes the completions for `command`, returning false if there
+    /// were none.
+    pub fn remove(&mut self, command: &str) -> bool {
+        self.specs.contains_key(command) && Arc::make_mut(&mut self.specs).remove(command).is_some()
+    }
+
+    /// Removes every command's completions.
+    pub fn clear(&mut self) {
+        self.specs = Arc::default();
+    }
+
+    /// Returns the completer for `command`.
+    pub fn get(&self, command: &str) -> Option<&dyn Completer> {
+        self.specs.get(command).map(|spec| match spec {
+            Spec::Builtin(spec) => spec as &dyn Completer,
+            Spec::Custom(completer) => completer.as_ref(),
+        })
+    }
+
+    /// Adds to the options offered when completing a word starting with
+    /// `-` as an argument of the external command `command`, such as
+    /// `--version`.
+    pub fn add_options<I, S>(&mut self, command: &str, options: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Arc::make_mut(&mut self.options)
+            .entry(command.to_string())
+            .or_default()
+            .extend(options.into_iter().map(Into::into));
+    }
+
+    /// Returns the options added for `command` with `add_options`.
+    pub fn options(&self, command: &str) -> &[String] {
+        self.options.get(command).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the specs defined with `complete`, by command name.
+    /// Completers registered from Rust are not included.
+    pub fn specs(&self) -> impl Iterator<Item = (&str, &CompleteSpec)> {
+        self.specs.iter().filter_map(|(name, spec)| match spec {
+            Spec::Builtin(spec) => Some((name.as_str(), spec)),
+            Spec::Custom(_) => None,
+        })
+    }
+}
+
+/// How many matches Tab lists without asking `Display all N
+/// possibilities?` first, as in readline.
+pub const QUERY_ITEMS: usize = 100;
+
+/// The word before the cursor that Tab completes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionContext {
+    /// Byte offset in the line where the word starts.
+    pub start: usize,
+    /// The word typed so far, with quotes and escapes removed.
+    pub word: String,
+    /// The quote character if the word has an unterminated quote, as in
+    /// `"my fi`.
+    pub quote: Option<char>,
+    /// Whether the word is a command name rather than an argument.
+    pub command_position: bool,
+    /// The command the word is an argument of.
+    pub command: Option<String>,
+    /// Whether a variable name after `$` is being completed, in which case
+    /// the word is just the `$NAME` part.
+    pub variable: bool,
+    /// Whether the whole line is being completed to a history entry, in
+    /// which case the word is the line and completions are used verbatim.
+    pub whole_line: bool,
+}
+
+impl CompletionContext {
+    /// Finds the word being completed in `line`, the text before the
+    /// cursor.
+    ///
+    /// Words are split as `tokenize` splits them, so `my\ fi` and `"my fi`
+    /// are both the single word `my fi`. Only the last command of a
+    /// pipeline or list counts, so after `ls | gre` the word is a command
+    /// name again.
+    pub fn parse(line: &str) -> Self {
+        let mut words = Vec::new();
+        let mut start = None;
+        let mut word = String::new();
+        let mut quote = None;
+        // Whether the current word is the target of a redirection
+        let mut redirect = false;
+        let mut chars = line.char_indices().peekable();
+
+        while let Some((i, c)) = chars.next() {
+            match quote {
+                Some('\'') if c == '\'' => quote = None,
+                Some('"') if c == '"' => quote = None,
+                Some('"') if c == '\\' => {
+                    match chars.next_if(|&(_, next)| matches!(next, '\\' | '$' | '"' | '\n')) {
+                        Some((_, escaped)) => word.push(escaped),
+                        None => word.push('\\'),
+                    }
+                }
+                Some(_) => word.push(c),
+                None if c.is_whitespace() || is_operator_char(c) => {
+                    if start.take().is_some() && !std::mem::take(&mut redirect) {
+                        words.push(std::mem::take(&mut word));
+                    }
+                    word.clear();
+                    match c {
+                        '<' | '>' => redirect = true,
+                        '|' | '&' | ';' => {
+                            words.clear();
+                            redirect = false;
+                        }
+                        _ => {}
+                    }
+                }
+                None => {
+                    start.get_or_insert(i);
+                    match c {
+                        '\'' | '"' => quote = Some(c),
+                        '\\' => {
+                            if let Some((_, escaped)) = chars.next() {
+                                word.push(escaped);
+                            }
+                        }
+                        _ => word.push(c),
+                    }
+                }
+            }
+        }
+
+        let mut context = Self {
+            start: start.unwrap_or(line.len()),
+            word,
+            quote,
+            command_position: words.is_empty() && !redirect,
+            command: words.into_iter().next(),
+            variable: false,
+            whole_line: false,
+        };
+
+        // `$NAME` at the end of the word, unless quoted or escaped
+        if context.quote != Some('\'')
+            && let Some(dollar) = line[context.start..].rfind('$').map(|i| context.start + i)
+            && !line[..dollar].ends_with('\\')
+            && line[dollar + 1..]
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            context.start = dollar;
+            context.word = line[dollar..].to_string();
+            context.variable = true;
+        }
+        context
+    }
+
+    /// Makes a context for completing all of `line` to a previous command
+    /// line, with `history_candidates`.
+    pub fn whole_line(line: &str) -> Self {
+        Self {
+            start: 0,
+            word: line.to_string(),
+            quote: None,
+            command_position: false,
+            command: None,
+            variable: false,
+            whole_line: true,
+        }
+    }
+
+    /// Returns the history entries that extend the line, most recent
+    /// first and without duplicates.
+    pub fn history_candidates(&self, history: &History) -> Vec<String> {
+        if self.word.trim().is_empty() {
+            return Vec::new();
+        }
+        let mut found: Vec<String> = Vec::new();
+        for entry in history.iter().rev() {
+            if entry.len() > self.word.len()
+                && entry.starts_with(&self.word)
+                && !found.iter().any(|seen| seen == entry)
+            {
+                found.push(entry.to_string());
+            }
+        }
+        found
+    }
+
+    /// Quotes a completion of the word so it tokenizes back to `text`,
+    /// continuing the word's open quote if it has one and escaping special
+    /// characters with backslashes otherwise.
+    ///
+    /// A `finished` completion also closes the open quote.
+    pub fn quote(&self, text: &str, finished: bool) -> String {
+        if self.variable || self.whole_line {
+            // Variable names never need quoting, and history entries are
+            // already quoted
+            return text.to_string();
+        }
+        let mut quoted: String = self.quote.into_iter().collect();
+        for c in text.chars() {
+            match self.quote {
+                // A single quote cannot appear inside single quotes
+                Some('\'') if c == '\'' => quoted.push_str("'\\''"),
+                Some('"') if matches!(c, '\\' | '$' | '"') => {
+                    quoted.push('\\');
+                    quoted.push(c);
+                }
+                None if needs_escape(c) => {
+                    quoted.push('\\');
+                    quoted.push(c);
+                }
+                _ => quoted.push(c),
+            }
+        }
+        if finished {
+            quoted.extend(self.quote);
+        }
+        quoted
+    }
+
+    /// Returns the possible completions of the word, sorted (see
+    /// [`collate::compare`]) and without duplicates.
+    ///
+    /// Command names complete to builtins, abbreviations and executables on
+    /// the `PATH`, ranked in that order with each name listed once;
+    /// arguments, and commands given as a path, complete to files and
+    /// directories, with a trailing `/` on directories. Arguments of `cd`
+    /// and `pushd` complete to directories only, including those found
+    /// through `CDPATH`. `$NAME` completes to environment variables.
+    ///
+    /// Arguments of commands with completions registered in
+    /// `shell.completions` complete to those instead, falling back to
+    /// files when none match. Arguments starting with `-` complete to the
+    /// command's options, as declared by builtins or added with
+    /// [`Completions::add_options`].
+    pub fn candidates(&self, shell: &Shell) -> Vec<String> {
+        let mut candidates = self.gather(&self.word, shell);
+        // Command names come ranked and without duplicates already
+        if !self.completes_command(&self.word) {
+            collate::sort(&mut candidates);
+            candidates.dedup();
+        }
+        candidates
+    }
+
+    /// Whether `word`, the word or a prefix of it, completes to command
+    /// names.
+    fn completes_command(&self, word: &str) -> bool {
+        !self.variable && self.command_position && !word.contains('/')
+    }
+
+    /// Returns the completions whose last component contains the typed
+    /// characters in order, such as `git-credential-osxkeychain` for
+    /// `gco`, best matches first.
+    ///
+    /// Meant as a fallback when `candidates` finds nothing.
+    pub fn fuzzy_candidates(&self, shell: &Shell) -> Vec<String> {
+        let split = match self.word.rfind('/') {
+            Some(i) => i + 1,
+            None if self.variable => 1,
+            None => 0,
+        };
+        let (dir, pattern) = self.word.split_at(split);
+        if pattern.is_empty() {
+            return Vec::new();
+        }
+
+        let mut scored: Vec<(usize, String)> = self
+            .gather(dir, shell)
+            .into_iter()
+            .filter_map(|candidate| {
+                let name = candidate[split..].trim_end_matches('/');
+                Some((fuzzy_score(pattern, name)?, candidate))
+            })
+            .collect();
+        scored.sort_by(|(a_score, a), (b_score, b)| {
+            b_score.cmp(a_score).then_with(|| collate::compare(a, b))
+        });
+        scored.dedup_by(|(_, a), (_, b)| a == b);
+        scored.into_iter().map(|(_, candidate)| candidate).collect()
+    }
+
+    /// Collects the completions starting with `word` from the source that
+    /// fits the context.
+    fn gather(&self, word: &str, shell: &Shell) -> Vec<String> {
+        if !self.variable
+            && !self.command_position
+            && let Some(completer) = self
+                .command
+                .as_deref()
+                .and_then(|command| shell.completions.get(command))
+        {
+            let found: Vec<String> = completer
+                .complete(self, shell)
+                .into_iter()
+                .filter(|candidate| candidate.starts_with(word))
+                .collect();
+            if !found.is_empty() {
+                return found;
+            }
+        }
+
+        if !self.variable
+            && !self.command_position
+            && let Some(found) = self
+                .command
+                .as_deref()
+                .and_then(|command| shell.plugins.complete(command, word))
+        {
+            let found: Vec<String> = found
+                .into_iter()
+                .filter(|candidate| candidate.starts_with(word))
+                .collect();
+            if !found.is_empty() {
+                return found;
+            }
+        }
+
+        if word.starts_with('-')
+            && !self.command_position
+            && let Some(command) = self.command.as_deref()
+        {
+            let found: Vec<String> = option_names(command, shell)
+                .into_iter()
+                .filter(|option| option.starts_with(word))
+                .collect();
+            if !found.is_empty() {
+                return found;
+            }
+        }
+
+        if self.variable {
+            variables(&word[1..], shell)
+        } else if self.completes_command(word) {
+            command_names(word, shell)
+        } else if matches!(self.command.as_deref(), Some("cd" | "pushd")) {
+            directories(word, shell)
+        } else {
+            paths(Path::new(""), word, shell)
+        }
+    }
+
+    /// Works out what one of the word's candidates names, in the shell of
+    /// `options`.
+    pub fn kind(&self, candidate: &str, shell: &Shell) -> CandidateKind {
+        if self.variable {
+            return CandidateKind::Variable;
+        }
+        if self.whole_line {
+            return CandidateKind::History;
+        }
+        if self.command_position && !self.word.contains('/') {
+            return if Builtin::NAMES.contains(&candidate) {
+                CandidateKind::Builtin
+            } else {
+                CandidateKind::Executable
+            };
+        }
+        if candidate.ends_with('/') {
+            return CandidateKind::Directory;
+        }
+        let path = match (candidate.strip_prefix("~/"), shell.variables.get("HOME")) {
+            (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+            _ => shell.cwd.join(candidate),
+        };
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => CandidateKind::Symlink,
+            Ok(metadata) if metadata.permissions().mode() & 0o111 != 0 => CandidateKind::Executable,
+            _ => CandidateKind::File,
+        }
+    }
+}
+
+/// What a completion candidate names, which decides its color in the
+/// match listing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CandidateKind {
+    Builtin,
+    Executable,
+    Directory,
+    Symlink,
+    File,
+    Variable,
+    History,
+}
+
+/// Colors for the match listing, in the format of `LS_COLORS`.
+#[derive(Debug, Clone, Default)]
+pub struct ListColors {
+    codes: HashMap<String, String>,
+}
+
+impl ListColors {
+    /// Reads the colors from the `LS_COLORS` variable, or returns `None` if
+    /// colors are turned off (see [`terminal::colors_enabled`]).
+    pub fn from_variables(variables: &Variables) -> Option<Self> {
+        if !terminal::colors_enabled() {
+            return None;
+        }
+        Some(Self::parse(&variables.get("LS_COLORS").unwrap_or_default()))
+    }
+
+    /// Parses an `LS_COLORS` value such as `di=01;34:ex=01;32:*.rs=33`.
+    ///
+    /// Directories, executables and symlinks keep the colors `ls` gives
+    /// them unless the value overrides them. Builtins, which `LS_COLORS`
+    /// has no entry for, are magenta.
+    pub fn parse(value: &str) -> Self {
+        let mut codes: HashMap<String, String> = [
+            ("di", "01;34"),
+            ("ex", "01;32"),
+            ("ln", "01;36"),
+            ("bi", "35"),
+        ]
+        .into_iter()
+        .map(|(key, code)| (key.to_string(), code.to_string()))
+        .collect();
+        for entry in value.split(':') {
+            if let Some((key, code)) = entry.split_once('=') {
+                codes.insert(key.to_string(), code.to_string());
+            }
+        }
+        Self { codes }
+    }
+
+    /// Wraps `name` in the escape sequences for a candidate of `kind`.
+    ///
+    /// Plain files may be colored by extension with `*.ext` entries, the
+    /// longest matching suffix winning.
+    pub fn paint(&self, kind: CandidateKind, name: &str) -> String {
+        let code = match kind {
+            CandidateKind::Builtin => self.codes.get("bi"),
+            CandidateKind::Executable => self.codes.get("ex"),
+            CandidateKind::Directory => self.codes.get("di"),
+            CandidateKind::Symlink => self.codes.get("ln"),
+            CandidateKind::File => self
+                .codes
+                .iter()
+                .filter(|(key, _)| {
+                    key.strip_prefix('*')
+                        .is_some_and(|suffix| name.ends_with(suffix))
+                })
+                .max_by_key(|(key, _)| key.len())
+                .map(|(_, code)| code)
+                .or_else(|| self.codes.get("fi")),
+            CandidateKind::Variable | CandidateKind::History => None,
+        };
+        match code {
+            Some(code) if !code.is_empty() => format!("\x1b[{}m{}\x1b[0m", code, name),
+            _ => name.to_string(),
+        }
+    }
+}
+
+/// Scores how well `name` matches `pattern` as a case-insensitive
+/// subsequence, or returns `None` if it does not match at all.
+///
+/// Matches at the start of the name or of a word within it, and runs of
+/// consecutive characters, score higher; unmatched characters lower the
+/// score.
+fn fuzzy_score(pattern: &str, name: &str) -> Option<usize> {
+    let mut pattern_chars = pattern.chars().map(|c| c.to_ascii_lowercase()).peekable();
+    let mut score: usize = 0;
+    let mut last_match = None;
+    let mut before = None;
+    for (i, c) in name.chars().enumerate() {
+        if let Some(&wanted) = pattern_chars.peek()
+            && c.to_ascii_lowercase() == wanted
+        {
+            pattern_chars.next();
+            score += 2;
+            if last_match.is_some_and(|last| last + 1 == i) {
+                score += 4;
+            }
+            if before.is_none_or(|b| matches!(b, '-' | '_' | '.' | ' ')) {
+                score += 6;
+            }
+            last_match = Some(i);
+        }
+        before = Some(c);
+    }
+    if pattern_chars.peek().is_some() {
+        return None;
+    }
+    Some((score * 16).saturating_sub(name.chars().count()))
+}
+
+/// Returns true for characters that separate commands or start a
+/// redirection.
+fn is_operator_char(c: char) -> bool {
+    matches!(c, '|' | '&' | ';' | '<' | '>')
+}
+
+/// Returns true for characters that must be escaped in an unquoted word.
+fn needs_escape(c: char) -> bool {
+    c.is_whitespace()
+        || matches!(
+            c,
+            '\'' | '"'
+                | '\\'
+                | '$'
+                | '`'
+                | '&'
+                | '|'
+                | ';'
+                | '<'
+                | '>'
+                | '('
+                | ')'
+                | '*'
+                | '?'
+                | '['
+                | ']'
+                | '!'
+        )
+}
+
+/// Returns how a completion is shown when listing the matches: the last
+/// path component, keeping the `/` of a directory.
+pub fn display_name(candidate: &str) -> &str {
+    let trimmed = candidate.strip_suffix('/').unwrap_or(candidate);
+    match trimmed.rfind('/') {
+        Some(i) => &candidate[i + 1..],
+        None => candidate,
+    }
+}
+
+/// Calculates the longest common prefix of a list of strings.
+///
+/// Compares bytes, then backs off to a character boundary, so it runs in
+/// time linear in the total length of the strings.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::completion::longest_common_prefix;
+/// let names = ["caféine".to_string(), "café".to_string(), "cafés".to_string()];
+/// assert_eq!(longest_common_prefix(&names), "café");
+/// assert_eq!(longest_common_prefix(&["é".to_string(), "è".to_string()]), "");
+/// ```
+pub fn longest_common_prefix(strings: &[String]) -> String {
+    let Some((first, rest)) = strings.split_first() else {
+        return String::new();
+    };
+    let mut len = first.len();
+    for s in rest {
+        len = first.as_bytes()[..len]
+            .iter()
+            .zip(s.as_bytes())
+            .take_while(|(a, b)| a == b)
+            .count();
+    }
+    // Two characters can share their first bytes
+    while !first.is_char_boundary(len) {
+        len -= 1;
+    }
+    first[..len].to_string()
+}
+
+/// Finds the command names starting with `prefix`: builtins first, then
+/// abbreviations, then `PATH` executables, each group sorted.
+///
+/// A name is only listed the first time it appears, so executables
+/// shadowed by a builtin or by an earlier `PATH` directory are dropped.
+fn command_names(prefix: &str, shell: &Shell) -> Vec<String> {
+    let mut builtins: Vec<String> = Builtin::NAMES
+        .iter()
+        .copied()
+        .chain(shell.plugins.builtin_names())
+        .filter(|name| name.starts_with(prefix))
+        .map(|name| name.to_string())
+        .collect();
+    collate::sort(&mut builtins);
+    let mut abbreviations: Vec<String> = shell
+        .abbreviations
+        .keys()
+        .filter(|name| name.starts_with(prefix))
+        .cloned()
+        .collect();
+    collate::sort(&mut abbreviations);
+    let path = shell.variables.get("PATH").unwrap_or_default();
+    let names = |index: &executables::ExecutableIndex| -> Vec<String> {
+        index
+            .starting_with(prefix)
+            .map(|entry| entry.name.clone())
+            .collect()
+    };
+    // The repl's index worker checks the index's directories after each
+    // prompt, so Tab doesn't read them again unless there's no index yet
+    let mut executables = executables::with_latest_index(&path, names)
+        .unwrap_or_else(|| executables::with_index(&path, names));
+    collate::sort(&mut executables);
+
+    let mut seen = HashSet::new();
+    builtins
+        .into_iter()
+        .chain(abbreviations)
+        .chain(executables)
+        .filter(|name| seen.insert(name.clone()))
+        .collect()
+}
+
+/// Returns the options `command` accepts: those a builtin declares, or
+/// those in the command's option table.
+fn option_names(command: &str, shell: &Shell) -> Vec<String> {
+    match Builtin::from_str(command) {
+        Ok(builtin) => builtin.options().iter().map(|o| o.to_string()).collect(),
+        Err(()) => shell.completions.options(command).to_vec(),
+    }
+}
+
+/// Finds the shell's variables whose name starts with `prefix`, as
+/// `$NAME`.
+fn variables(prefix: &str, shell: &Shell) -> Vec<String> {
+    shell
+        .variables
+        .all()
+        .into_iter()
+        .map(|(name, _)| name)
+        .filter(|name| name.starts_with(prefix))
+        .map(|name| format!("${}", name))
+        .collect()
+}
+
+/// Finds directories for `cd`: those under the current directory and,
+/// as `cd` searches `CDPATH` for relative names, those under each `CDPATH`
+/// entry.
+fn directories(word: &str, shell: &Shell) -> Vec<String> {
+    let mut found: Vec<String> = paths(Path::new(""), word, shell)
+        .into_iter()
+        .filter(|path| path.ends_with('/'))
+        .collect();
+    if !word.starts_with(['/', '.', '~'])
+        && let Some(cdpath) = shell.variables.get("CDPATH")
+    {
+        for base in env::split_paths(&cdpath) {
+            if !base.as_os_str().is_empty() {
+                found.extend(
+                    paths(&base, word, shell)
+                        .into_iter()
+                        .filter(|path| path.ends_with('/')),
+                );
+            }
+        }
+    }
+    found
+}
+
+/// Finds files and directories whose path, relative to `base` unless
+/// absolute, starts with `word`. A relative `base` is taken from the
+/// shell's working directory.
+///
+/// Hidden files are only offered when the name being completed starts
+/// with a `.`, or with `set -o hiddencomplete`.
+fn paths(base: &Path, word: &str, shell: &Shell) -> Vec<String> {
+    let hidden = shell.options.hidden_complete;
+    let (dir, prefix) = match word.rfind('/') {
+        Some(i) => word.split_at(i + 1),
+        None => ("", word),
+    };
+    let search_dir = match (dir.strip_prefix("~/"), shell.variables.get("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => shell.cwd.join(base).join(dir),
+    };
+
+    let Ok(entries) = std::fs::read_dir(&search_dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix)
+                || (name.starts_with('.') && !prefix.starts_with('.') && !hidden)
+            {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", dir, name, slash))
+        })
+        .collect()
+}
+
+/// Cycles through the completions of a word in place, as readline's
+/// `menu-complete` does.
+#[derive(Debug, Clone)]
+pub struct Menu {
+    start: usize,
+    completions: Vec<String>,
+    index: Option<usize>,
+}
+
+impl Menu {
+    /// Creates a menu of `matches`, quoted to replace the word of
+    /// `context`.
+    pub fn new(context: &CompletionContext, matches: &[String]) -> Self {
+        let completions = matches
+            .iter()
+            .map(|m| context.quote(m, !m.ends_with('/')))
+            .collect();
+        Self {
+            start: context.start,
+            completions,
+            index: None,
+        }
+    }
+
+    /// Byte offset in the line where the word being replaced starts.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Moves to the next completion, wrapping around after the last.
+    pub fn forward(&mut self) -> &str {
+        let index = self.index.map_or(0, |i| (i + 1) % self.completions.len());
+        self.index = Some(index);
+        &self.completions[index]
+    }
+
+    /// Moves to the previous completion, wrapping around before the first.
+    pub fn backward(&mut self) -> &str {
+        let len = self.completions.len();
+        let index = self.index.map_or(len - 1, |i| (i + len - 1) % len);
+        self.index = Some(index);
+        &self.completions[index]
+    }
+}
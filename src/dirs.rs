@@ -0,0 +1,56 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// The name of the shell's directories under the XDG base directories.
+pub const APP_NAME: &str = "codecrafters-shell";
+
+/// Returns the base directory for configuration: `XDG_CONFIG_HOME`, or
+/// `~/.config` if it is unset or not an absolute path.
+pub fn config_home() -> Option<PathBuf> {
+    base_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// Returns the base directory for state kept between sessions, such as
+/// history: `XDG_STATE_HOME`, or `~/.local/state` if it is unset or not an
+/// absolute path.
+pub fn state_home() -> Option<PathBuf> {
+    base_dir("XDG_STATE_HOME", ".local/state")
+}
+
+/// Returns the shell's configuration directory,
+/// `$XDG_CONFIG_HOME/codecrafters-shell`.
+pub fn config_dir() -> Option<PathBuf> {
+    config_home().map(|dir| dir.join(APP_NAME))
+}
+
+/// Returns the shell's state directory, `$XDG_STATE_HOME/codecrafters-shell`.
+pub fn state_dir() -> Option<PathBuf> {
+    state_home().map(|dir| dir.join(APP_NAME))
+}
+
+/// Returns a file in the home directory, such as a dotfile from before
+/// the shell used the XDG directories.
+pub fn home_file(name: &str) -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| Path::new(&home).join(name))
+}
+
+/// Chooses between a file's XDG location and its legacy dotfile: the
+/// legacy file is only used if it exists and the XDG one doesn't, so that
+/// existing setups keep working and new ones use the XDG directories.
+pub fn resolve(xdg: Option<PathBuf>, legacy: Option<PathBuf>) -> Option<PathBuf> {
+    match (xdg, legacy) {
+        (Some(xdg), _) if xdg.exists() => Some(xdg),
+        (xdg, Some(legacy)) if legacy.exists() || xdg.is_none() => Some(legacy),
+        (xdg, _) => xdg,
+    }
+}
+
+/// Reads a base directory from `variable`, falling back to `default` in
+/// the home directory. The XDG specification says relative paths are
+/// invalid and to be ignored.
+fn base_dir(variable: &str, default: &str) -> Option<PathBuf> {
+    match env::var_os(variable).map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => Some(dir),
+        _ => home_file(default),
+    }
+}
@@ -0,0 +1,1079 @@
+use crate::builtins::Builtin;
+use crate::executables;
+use crate::history::History;
+use crate::keymap::EditCommand;
+use crate::parser::{self, HighlightKind};
+use crate::prompt;
+use crate::redirect::describe_io_error;
+use crate::shell::Shell;
+use crate::terminal;
+use crate::variables::Variables;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{File, OpenOptions};
+use std::hash::{BuildHasher, RandomState};
+use std::io::{self, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::{env, fs, process};
+use termion::color;
+use termion::event::Key;
+use termion::{clear, cursor, style};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Terminal width assumed until the real size is known.
+const DEFAULT_WIDTH: usize = 80;
+
+/// Maximum number of entries kept in the kill ring, as in readline.
+const KILL_RING_SIZE: usize = 10;
+
+/// Text removed by kill commands, kept for later yanking.
+///
+/// Consecutive kills accumulate into a single entry, so e.g. two Ctrl-W
+/// presses followed by Ctrl-Y restore both words.
+#[derive(Default)]
+pub struct KillRing {
+    /// Killed text, oldest first.
+    entries: Vec<String>,
+    /// Whether the previous command was a kill that the next one extends.
+    accumulating: bool,
+}
+
+impl KillRing {
+    /// Adds killed text, dropping the oldest entry once the ring is full.
+    ///
+    /// If the previous command was also a kill, the text is merged into the
+    /// latest entry instead: prepended for backward kills, appended otherwise.
+    pub fn push(&mut self, text: String, backward: bool) {
+        match self.entries.last_mut() {
+            Some(last) if self.accumulating => {
+                if backward {
+                    last.insert_str(0, &text);
+                } else {
+                    last.push_str(&text);
+                }
+            }
+            _ => {
+                if self.entries.len() == KILL_RING_SIZE {
+                    self.entries.remove(0);
+                }
+                self.entries.push(text);
+            }
+        }
+        self.accumulating = true;
+    }
+
+    /// Ends a run of consecutive kills, so the next kill starts a new entry.
+    pub fn end_sequence(&mut self) {
+        self.accumulating = false;
+    }
+
+    /// Returns the most recently killed text.
+    pub fn last(&self) -> Option<&str> {
+        self.entries.last().map(|s| s.as_str())
+    }
+}
+
+/// The two states of vi-style editing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ViMode {
+    /// Typed characters are inserted, as in emacs mode.
+    #[default]
+    Insert,
+    /// Keys are editing commands (`h`, `dd`, `cw`, ...).
+    Normal,
+}
+
+/// What the caller should do after a key pressed in vi normal mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ViAction {
+    /// The editor handled the key; redraw the line.
+    Redraw,
+    /// Handle this key as in emacs mode instead (e.g. `k` becomes Up).
+    Key(Key),
+    /// Start a `/` history search.
+    Search,
+    /// The key began a command that needs another key (`d`, `c`).
+    Pending,
+    /// The key is not bound; ring the bell.
+    Beep,
+}
+
+/// What the line editor colors command names by: the `PATH`, working
+/// directory, abbreviations and plugin builtins of the shell whose commands
+/// are being typed.
+#[derive(Debug, Clone, Default)]
+pub struct Commands {
+    path: String,
+    cwd: PathBuf,
+    /// Abbreviations and plugin builtins, which also run as commands.
+    names: BTreeSet<String>,
+}
+
+impl Commands {
+    /// Takes the commands that would run in `shell`.
+    pub fn new(shell: &Shell) -> Self {
+        let abbreviations = shell.abbreviations.keys().cloned();
+        let plugins = shell.plugins.builtin_names().map(str::to_string);
+        Commands {
+            path: shell.variables.get("PATH").unwrap_or_default(),
+            cwd: shell.cwd.to_path_buf(),
+            names: abbreviations.chain(plugins).collect(),
+        }
+    }
+
+    /// Checks whether `name` would run as a builtin, abbreviation, plugin
+    /// builtin or executable.
+    ///
+    /// Executables are only looked up in the shell's index, as last built
+    /// for this `PATH`, since this runs for every key typed. Returns `None`
+    /// if there's no such index yet, when it can't tell.
+    fn contains(&self, name: &str) -> Option<bool> {
+        if name.contains('/') {
+            return Some(self.cwd.join(name).metadata().is_ok_and(|metadata| {
+                metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+            }));
+        }
+        if Builtin::from_str(name).is_ok() || self.names.contains(name) {
+            return Some(true);
+        }
+        executables::with_latest_index(&self.path, |index| index.find(name).is_some())
+    }
+}
+
+/// The line currently being edited, with a cursor position.
+///
+/// The cursor is a byte offset into the buffer and always sits on a
+/// grapheme cluster boundary, so `é` typed as `e` plus a combining accent
+/// is moved over and deleted as one character. The editor lives for the whole session, so the kill ring
+/// carries over from one line to the next.
+pub struct LineEditor {
+    buffer: String,
+    cursor: usize,
+    /// Terminal width in columns, used to place the cursor on wrapped lines.
+    width: usize,
+    /// The row, relative to the prompt's row, where the last redraw left
+    /// the terminal cursor.
+    cursor_row: usize,
+    kill_ring: KillRing,
+    /// Snapshots of `(buffer, cursor)` taken before each edit to the line.
+    undo_stack: Vec<(String, usize)>,
+    /// Whether the previous key inserted a character, so a run of typed
+    /// characters is undone as one step.
+    inserting: bool,
+    vi_mode: ViMode,
+    /// A vi operator (`d` or `c`) waiting for its motion.
+    vi_operator: Option<char>,
+    /// The history entry offered as an autosuggestion. Only the part after
+    /// the buffer is drawn, and only while the entry still extends it.
+    suggestion: Option<String>,
+    /// The commands that command names are colored by.
+    commands: Commands,
+}
+
+impl Default for LineEditor {
+    fn default() -> Self {
+        Self {
+            buffer: String::new(),
+            cursor: 0,
+            width: DEFAULT_WIDTH,
+            cursor_row: 0,
+            kill_ring: KillRing::default(),
+            undo_stack: Vec::new(),
+            inserting: false,
+            vi_mode: ViMode::default(),
+            vi_operator: None,
+            suggestion: None,
+            commands: Commands::default(),
+        }
+    }
+}
+
+impl LineEditor {
+    /// Creates an empty line.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the commands that command names are colored by, e.g. after
+    /// `cd` or a change to `PATH`.
+    pub fn set_commands(&mut self, commands: Commands) {
+        self.commands = commands;
+    }
+
+    /// Sets the terminal width, e.g. after the window is resized. A width
+    /// of 0, as a terminal that doesn't know its size reports, keeps the
+    /// default of 80 columns.
+    pub fn set_width(&mut self, width: usize) {
+        self.width = if width == 0 { DEFAULT_WIDTH } else { width };
+    }
+
+    /// Returns the whole line.
+    pub fn buffer(&self) -> &str {
+        &self.buffer
+    }
+
+    /// Returns the text before the cursor.
+    pub fn before_cursor(&self) -> &str {
+        &self.buffer[..self.cursor]
+    }
+
+    /// Returns true if the line is empty.
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Returns true if the cursor is at the end of the line.
+    pub fn at_end(&self) -> bool {
+        self.cursor == self.buffer.len()
+    }
+
+    /// Starts editing a fresh line, forgetting the previous line's undo
+    /// history.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.cursor = 0;
+        self.cursor_row = 0;
+        self.undo_stack.clear();
+        self.inserting = false;
+        self.vi_mode = ViMode::Insert;
+        self.vi_operator = None;
+        self.suggestion = None;
+    }
+
+    /// Prepares for the next command, ending kill and typing runs unless
+    /// the command continues them.
+    pub fn before_command(&mut self, command: EditCommand) {
+        if !matches!(
+            command,
+            EditCommand::KillLine
+                | EditCommand::UnixLineDiscard
+                | EditCommand::UnixWordRubout
+                | EditCommand::KillWord
+        ) {
+            // Only consecutive kills accumulate into one kill-ring entry
+            self.kill_ring.end_sequence();
+        }
+        if command != EditCommand::SelfInsert {
+            self.inserting = false;
+        }
+    }
+
+    /// Replaces the whole line, leaving the cursor at the end.
+    pub fn set(&mut self, text: &str) {
+        self.record_undo();
+        self.buffer = text.to_string();
+        self.cursor = self.buffer.len();
+    }
+
+    /// Empties the line.
+    pub fn clear(&mut self) {
+        self.record_undo();
+        self.buffer.clear();
+        self.cursor = 0;
+    }
+
+    /// Inserts a character at the cursor.
+    pub fn insert(&mut self, c: char) {
+        if !self.inserting {
+            self.record_undo();
+            self.inserting = true;
+        }
+        self.buffer.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    /// Inserts a string at the cursor.
+    pub fn insert_str(&mut self, text: &str) {
+        self.record_undo();
+        self.buffer.insert_str(self.cursor, text);
+        self.cursor += text.len();
+    }
+
+    /// Replaces the text from byte offset `start` up to the cursor, e.g. a
+    /// word being completed.
+    pub fn replace_before_cursor(&mut self, start: usize, text: &str) {
+        self.record_undo();
+        self.buffer.replace_range(start..self.cursor, text);
+        self.cursor = start + text.len();
+    }
+
+    /// Reverts the most recent edit to the line (Ctrl-_ or Ctrl-X Ctrl-U).
+    ///
+    /// Returns false if there is nothing left to undo.
+    pub fn undo(&mut self) -> bool {
+        match self.undo_stack.pop() {
+            Some((buffer, cursor)) => {
+                self.buffer = buffer;
+                self.cursor = cursor;
+                self.inserting = false;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Saves the current line so the next edit can be undone.
+    fn record_undo(&mut self) {
+        self.inserting = false;
+        let snapshot = (self.buffer.clone(), self.cursor);
+        if self.undo_stack.last() != Some(&snapshot) {
+            self.undo_stack.push(snapshot);
+        }
+    }
+
+    /// Deletes the character before the cursor.
+    ///
+    /// Returns false if the cursor is already at the start of the line.
+    pub fn backspace(&mut self) -> bool {
+        match self.previous_grapheme_len() {
+            Some(len) => {
+                self.record_undo();
+                self.cursor -= len;
+                self.buffer.drain(self.cursor..self.cursor + len);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Deletes the character under the cursor.
+    ///
+    /// Returns false if the cursor is at the end of the line.
+    pub fn delete_forward(&mut self) -> bool {
+        match self.next_grapheme_len() {
+            Some(len) => {
+                self.record_undo();
+                self.buffer.drain(self.cursor..self.cursor + len);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves the cursor one character left, returning false at the start.
+    pub fn move_left(&mut self) -> bool {
+        match self.previous_grapheme_len() {
+            Some(len) => {
+                self.cursor -= len;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves the cursor one character right, returning false at the end.
+    pub fn move_right(&mut self) -> bool {
+        match self.next_grapheme_len() {
+            Some(len) => {
+                self.cursor += len;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the length in bytes of the grapheme cluster before the cursor.
+    fn previous_grapheme_len(&self) -> Option<usize> {
+        self.before_cursor()
+            .graphemes(true)
+            .next_back()
+            .map(|g| g.len())
+    }
+
+    /// Returns the length in bytes of the grapheme cluster under the cursor.
+    fn next_grapheme_len(&self) -> Option<usize> {
+        self.buffer[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map(|g| g.len())
+    }
+
+    /// Moves the cursor to the start of the line.
+    pub fn move_home(&mut self) {
+        self.cursor = 0;
+    }
+
+    /// Moves the cursor to the end of the line.
+    pub fn move_end(&mut self) {
+        self.cursor = self.buffer.len();
+    }
+
+    /// Kills from the cursor to the end of the line (Ctrl-K).
+    ///
+    /// Returns false if there was nothing to kill.
+    pub fn kill_to_end(&mut self) -> bool {
+        if self.at_end() {
+            return false;
+        }
+        self.record_undo();
+        let killed = self.buffer.split_off(self.cursor);
+        self.kill_ring.push(killed, false);
+        true
+    }
+
+    /// Kills from the start of the line to the cursor (Ctrl-U).
+    ///
+    /// Returns false if there was nothing to kill.
+    pub fn kill_to_start(&mut self) -> bool {
+        if self.cursor == 0 {
+            return false;
+        }
+        self.record_undo();
+        let killed: String = self.buffer.drain(..self.cursor).collect();
+        self.cursor = 0;
+        self.kill_ring.push(killed, true);
+        true
+    }
+
+    /// Kills the whitespace-delimited word before the cursor (Ctrl-W).
+    ///
+    /// Returns false if there was nothing to kill.
+    pub fn kill_word_before(&mut self) -> bool {
+        let before = self.before_cursor();
+        let word_end = before.trim_end().len();
+        let start = before[..word_end]
+            .rfind(char::is_whitespace)
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        if start == self.cursor {
+            return false;
+        }
+        self.record_undo();
+        let killed: String = self.buffer.drain(start..self.cursor).collect();
+        self.cursor = start;
+        self.kill_ring.push(killed, true);
+        true
+    }
+
+    /// Kills from the cursor to the end of the next word (Alt-d).
+    ///
+    /// Returns false if there was nothing to kill.
+    pub fn kill_word_after(&mut self) -> bool {
+        let end = self.next_word_end();
+        if end == self.cursor {
+            return false;
+        }
+        self.record_undo();
+        let killed: String = self.buffer.drain(self.cursor..end).collect();
+        self.kill_ring.push(killed, false);
+        true
+    }
+
+    /// Swaps the characters around the cursor and moves past them (Ctrl-T).
+    ///
+    /// At the end of the line the last two characters are swapped instead,
+    /// as in readline. Returns false if there are not two characters to swap.
+    pub fn transpose_chars(&mut self) -> bool {
+        let mut pivot = self.cursor;
+        if self.at_end() {
+            match self.previous_grapheme_len() {
+                Some(len) => pivot -= len,
+                None => return false,
+            }
+        }
+        let Some(before) = self.buffer[..pivot].graphemes(true).next_back() else {
+            return false;
+        };
+        let Some(after) = self.buffer[pivot..].graphemes(true).next() else {
+            return false;
+        };
+        let start = pivot - before.len();
+        let end = pivot + after.len();
+        let swapped = format!("{}{}", after, before);
+        self.record_undo();
+        self.buffer.replace_range(start..end, &swapped);
+        self.cursor = end;
+        true
+    }
+
+    /// Moves the cursor to the start of the previous word (Alt-b).
+    pub fn move_word_left(&mut self) -> bool {
+        let start = self.previous_word_start();
+        let moved = start != self.cursor;
+        self.cursor = start;
+        moved
+    }
+
+    /// Moves the cursor to the end of the next word (Alt-f).
+    pub fn move_word_right(&mut self) -> bool {
+        let end = self.next_word_end();
+        let moved = end != self.cursor;
+        self.cursor = end;
+        moved
+    }
+
+    /// Finds the start of the word before the cursor.
+    ///
+    /// Words are runs of alphanumeric characters, so `/`, `.`, `-` and `_`
+    /// split paths into their components, as in readline.
+    fn previous_word_start(&self) -> usize {
+        let mut graphemes = self.before_cursor().grapheme_indices(true).rev().peekable();
+        while graphemes.next_if(|&(_, g)| !is_word(g)).is_some() {}
+        let mut start = graphemes.peek().map(|&(i, g)| i + g.len()).unwrap_or(0);
+        while let Some((i, _)) = graphemes.next_if(|&(_, g)| is_word(g)) {
+            start = i;
+        }
+        start
+    }
+
+    /// Finds the end of the word after the cursor.
+    fn next_word_end(&self) -> usize {
+        let mut graphemes = self.buffer[self.cursor..].grapheme_indices(true).peekable();
+        while graphemes.next_if(|&(_, g)| !is_word(g)).is_some() {}
+        while graphemes.next_if(|&(_, g)| is_word(g)).is_some() {}
+        self.cursor
+            + graphemes
+                .peek()
+                .map(|&(i, _)| i)
+                .unwrap_or(self.buffer.len() - self.cursor)
+    }
+
+    /// Returns the most recently killed text.
+    pub fn last_kill(&self) -> Option<&str> {
+        self.kill_ring.last()
+    }
+
+    /// Inserts the most recently killed text at the cursor (Ctrl-Y).
+    ///
+    /// Returns false if the kill ring is empty.
+    pub fn yank(&mut self) -> bool {
+        match self.kill_ring.last().map(|s| s.to_string()) {
+            Some(text) => {
+                self.insert_str(&text);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the current vi editing state.
+    pub fn vi_mode(&self) -> ViMode {
+        self.vi_mode
+    }
+
+    /// Leaves vi insert mode (Esc). Like vi, the cursor steps back onto
+    /// the last character typed.
+    pub fn vi_enter_normal(&mut self) {
+        self.vi_mode = ViMode::Normal;
+        self.vi_operator = None;
+        self.move_left();
+    }
+
+    /// Handles a key pressed in vi normal mode.
+    pub fn vi_key(&mut self, key: &Key) -> ViAction {
+        self.kill_ring.end_sequence();
+        self.inserting = false;
+        let c = match key {
+            Key::Char(c) => *c,
+            Key::Esc => {
+                self.vi_operator = None;
+                return ViAction::Pending;
+            }
+            // Arrows and control keys behave as in emacs mode
+            other => return ViAction::Key(*other),
+        };
+
+        if let Some(operator) = self.vi_operator.take() {
+            return self.vi_operator_motion(operator, c);
+        }
+
+        match c {
+            '\n' | '\r' => return ViAction::Key(Key::Char('\n')),
+            'h' => return ViAction::Key(Key::Left),
+            'k' => return ViAction::Key(Key::Up),
+            'j' => return ViAction::Key(Key::Down),
+            '/' => return ViAction::Search,
+            'l' => {
+                // The cursor stays on the last character in normal mode
+                if self.buffer[self.cursor..].graphemes(true).nth(1).is_none() {
+                    return ViAction::Beep;
+                }
+                self.move_right();
+            }
+            '0' | '^' => self.move_home(),
+            '$' => {
+                self.move_end();
+                self.move_left();
+            }
+            'w' => {
+                if !self.vi_word_forward() {
+                    return ViAction::Beep;
+                }
+            }
+            'b' => {
+                if !self.move_word_left() {
+                    return ViAction::Beep;
+                }
+            }
+            'x' => {
+                if !self.delete_forward() {
+                    return ViAction::Beep;
+                }
+                if self.at_end() {
+                    self.move_left();
+                }
+            }
+            'u' => {
+                if !self.undo() {
+                    return ViAction::Beep;
+                }
+            }
+            'p' => {
+                self.move_right();
+                self.yank();
+                self.move_left();
+            }
+            'i' => self.vi_mode = ViMode::Insert,
+            'a' => {
+                self.move_right();
+                self.vi_mode = ViMode::Insert;
+            }
+            'I' => {
+                self.move_home();
+                self.vi_mode = ViMode::Insert;
+            }
+            'A' => {
+                self.move_end();
+                self.vi_mode = ViMode::Insert;
+            }
+            'd' | 'c' => {
+                self.vi_operator = Some(c);
+                return ViAction::Pending;
+            }
+            _ => return ViAction::Beep,
+        }
+        ViAction::Redraw
+    }
+
+    /// Applies a pending `d` or `c` operator to the motion key `motion`.
+    fn vi_operator_motion(&mut self, operator: char, motion: char) -> ViAction {
+        let killed = match motion {
+            // `dd` and `cc` act on the whole line
+            m if m == operator => {
+                self.move_home();
+                self.kill_to_end() || self.is_empty()
+            }
+            // Like vi, `cw` changes to the end of the word rather than
+            // including the whitespace after it
+            'w' if operator == 'c' => self.kill_word_after(),
+            'w' => {
+                let start = self.cursor;
+                // On the last word, `dw` deletes to the end of the line
+                let end = if self.vi_word_forward() {
+                    self.cursor
+                } else {
+                    self.buffer.len()
+                };
+                self.cursor = start;
+                end > start && {
+                    self.record_undo();
+                    let killed: String = self.buffer.drain(start..end).collect();
+                    self.kill_ring.push(killed, false);
+                    true
+                }
+            }
+            '$' => self.kill_to_end(),
+            _ => false,
+        };
+        if !killed {
+            return ViAction::Beep;
+        }
+        if operator == 'c' {
+            self.vi_mode = ViMode::Insert;
+        } else if self.at_end() {
+            self.move_left();
+        }
+        ViAction::Redraw
+    }
+
+    /// Moves to the start of the next word, as vi's `w` does.
+    fn vi_word_forward(&mut self) -> bool {
+        let rest = &self.buffer[self.cursor..];
+        let mut graphemes = rest.grapheme_indices(true).peekable();
+        let on_word = graphemes.peek().is_some_and(|&(_, g)| is_word(g));
+        while graphemes
+            .next_if(|&(_, g)| is_word(g) == on_word && !is_blank(g))
+            .is_some()
+        {}
+        while graphemes.next_if(|&(_, g)| is_blank(g)).is_some() {}
+        match graphemes.peek() {
+            Some(&(i, _)) if i > 0 => {
+                self.cursor += i;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Expands the command name just before the cursor if it is an
+    /// abbreviation (fish's `abbr`), so the full command is what gets run
+    /// and saved to history.
+    ///
+    /// Returns false if there was nothing to expand.
+    pub fn expand_abbreviation(&mut self, abbreviations: &BTreeMap<String, String>) -> bool {
+        let Some((range, HighlightKind::Command)) = parser::highlight(self.before_cursor()).pop()
+        else {
+            return false;
+        };
+        if range.end != self.cursor {
+            return false;
+        }
+        let Some(expansion) = abbreviations.get(&self.buffer[range.clone()]) else {
+            return false;
+        };
+        self.record_undo();
+        self.buffer.replace_range(range.clone(), expansion);
+        self.cursor = range.start + expansion.len();
+        true
+    }
+
+    /// Offers the most recent history entry that extends the line as an
+    /// autosuggestion, fish-style.
+    ///
+    /// Returns true if the visible suggestion changed and the line needs
+    /// redrawing.
+    pub fn suggest(&mut self, history: &History) -> bool {
+        let before = self.ghost().to_string();
+        self.suggestion = if self.buffer.is_empty() {
+            None
+        } else {
+            history
+                .iter()
+                .rev()
+                .find(|entry| entry.len() > self.buffer.len() && entry.starts_with(&self.buffer))
+                .map(str::to_string)
+        };
+        self.ghost() != before
+    }
+
+    /// Replaces the line with the autosuggestion (Right or Ctrl-F at the end
+    /// of the line).
+    ///
+    /// Returns false if no suggestion is showing.
+    pub fn accept_suggestion(&mut self) -> bool {
+        if self.ghost().is_empty() {
+            return false;
+        }
+        if let Some(entry) = self.suggestion.take() {
+            self.set(&entry);
+        }
+        true
+    }
+
+    /// Returns the part of the autosuggestion drawn after the line, which is
+    /// empty unless the cursor is at the end of a line the suggestion extends.
+    fn ghost(&self) -> &str {
+        match &self.suggestion {
+            Some(entry) if self.at_end() && entry.starts_with(&self.buffer) => {
+                &entry[self.buffer.len()..]
+            }
+            _ => "",
+        }
+    }
+
+    /// Redraws the prompt and the whole line, then places the terminal
+    /// cursor at the edit position.
+    ///
+    /// Lines longer than the terminal width wrap onto several rows, so the
+    /// redraw starts by moving up from the row the previous redraw left the
+    /// cursor on to the prompt's row, and clears every row below it in case
+    /// the line got shorter.
+    pub fn redraw<W: Write>(&mut self, out: &mut W, prompt: &str) -> io::Result<()> {
+        let ghost = self.ghost();
+        let (cursor_row, column) = self.screen_position(prompt, self.before_cursor());
+        let (end_row, _, filled) = self.layout(prompt, &format!("{}{}", self.buffer, ghost));
+
+        if self.cursor_row > 0 {
+            write!(out, "{}", cursor::Up(self.cursor_row as u16))?;
+        }
+        write!(out, "\r{}", printable_prompt(prompt))?;
+        write_highlighted(
+            out,
+            &self.buffer,
+            &line_colors(&self.buffer, &self.commands),
+        )?;
+        if !ghost.is_empty() {
+            write!(out, "{}{}{}", style::Faint, ghost, style::Reset)?;
+        }
+        if filled {
+            // A line that exactly fills its last row leaves the terminal
+            // cursor pending on that row; move it to the next one
+            write!(out, "\r\n")?;
+        }
+        write!(out, "{}", clear::AfterCursor)?;
+
+        if end_row > cursor_row {
+            write!(out, "{}", cursor::Up((end_row - cursor_row) as u16))?;
+        }
+        write!(out, "\r")?;
+        if column > 0 {
+            write!(out, "{}", cursor::Right(column as u16))?;
+        }
+        self.cursor_row = cursor_row;
+        out.flush()
+    }
+
+    /// Inserts a typed character at the cursor and updates the display.
+    ///
+    /// Typing at the end of the line just echoes the character rather
+    /// than redrawing the whole line.
+    pub fn type_char<W: Write>(&mut self, out: &mut W, prompt: &str, c: char) -> io::Result<()> {
+        if !self.at_end() || c.width() == Some(0) || !self.ghost().is_empty() {
+            // Combining marks change how the previous character is drawn
+            self.insert(c);
+            return self.redraw(out, prompt);
+        }
+        let old_colors = line_colors(&self.buffer, &self.commands);
+        self.insert(c);
+        let colors = line_colors(&self.buffer, &self.commands);
+        if colors[..old_colors.len()] != old_colors[..] {
+            // e.g. the command name became resolvable and turns green
+            return self.redraw(out, prompt);
+        }
+        let (row, column) = self.screen_position(prompt, &self.buffer);
+        write_highlighted(out, &c.to_string(), &colors[old_colors.len()..])?;
+        if column == 0 {
+            write!(out, "\r\n")?;
+        }
+        self.cursor_row = row;
+        out.flush()
+    }
+
+    /// Moves the terminal cursor to a new row below the whole line, so
+    /// output such as the command's or a completion list can follow it.
+    pub fn finish_line<W: Write>(&mut self, out: &mut W, prompt: &str) -> io::Result<()> {
+        if !self.ghost().is_empty() {
+            self.suggestion = None;
+            self.redraw(out, prompt)?;
+        }
+        let (end_row, _, filled) = self.layout(prompt, &self.buffer);
+        if end_row > self.cursor_row {
+            write!(out, "{}", cursor::Down((end_row - self.cursor_row) as u16))?;
+        }
+        // The end position already wrapped onto a fresh row
+        if !filled {
+            write!(out, "\r\n")?;
+        } else {
+            write!(out, "\r")?;
+        }
+        self.cursor_row = 0;
+        out.flush()
+    }
+
+    /// Clears the screen and redraws the line at the top (Ctrl-L).
+    pub fn clear_screen<W: Write>(&mut self, out: &mut W, prompt: &str) -> io::Result<()> {
+        write!(out, "{}{}", clear::All, cursor::Goto(1, 1))?;
+        self.cursor_row = 0;
+        self.redraw(out, prompt)
+    }
+
+    /// Returns the row and column, relative to the start of the prompt,
+    /// just after `prompt` followed by `text` is written.
+    ///
+    /// Positions are measured in display columns: wide characters such as
+    /// CJK take two, combining marks none. A wide character that does not
+    /// fit at the end of a row wraps to the next one, as terminals do.
+    /// Escape sequences and `\[`...`\]` parts of the prompt take no space,
+    /// and newlines in it start new rows.
+    fn screen_position(&self, prompt: &str, text: &str) -> (usize, usize) {
+        let (row, column, _) = self.layout(prompt, text);
+        (row, column)
+    }
+
+    /// Like `screen_position`, but also returns whether the text exactly
+    /// fills its last row, leaving the terminal cursor pending at its end
+    /// rather than on the reported next row.
+    fn layout(&self, prompt: &str, text: &str) -> (usize, usize, bool) {
+        let (mut row, mut column) = (0, 0);
+        let visible = visible_prompt(prompt);
+        for grapheme in visible.graphemes(true).chain(text.graphemes(true)) {
+            if grapheme == "\n" || grapheme == "\r\n" {
+                row += 1;
+                column = 0;
+                continue;
+            }
+            if grapheme == "\r" {
+                column = 0;
+                continue;
+            }
+            let width = grapheme.width();
+            if column + width > self.width {
+                row += 1;
+                column = 0;
+            }
+            column += width;
+        }
+        if column >= self.width {
+            return (row + 1, 0, true);
+        }
+        (row, column, false)
+    }
+}
+
+/// Returns the part of a prompt that takes space on screen, without escape
+/// sequences or text between the `\[` and `\]` markers.
+fn visible_prompt(prompt: &str) -> String {
+    let mut visible = String::new();
+    let mut chars = prompt.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            prompt::START_IGNORE => {
+                chars.by_ref().find(|&c| c == prompt::END_IGNORE);
+            }
+            prompt::END_IGNORE => {}
+            '\x1b' => match chars.next() {
+                // CSI, such as a color: ends with a byte in @..~
+                Some('[') => {
+                    chars.by_ref().find(|c| ('@'..='~').contains(c));
+                }
+                // OSC, such as a window title: ends with BEL or ESC \
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next() == Some('\\')) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\x07' => {}
+            c => visible.push(c),
+        }
+    }
+    visible
+}
+
+/// Returns a prompt as written to the raw-mode terminal: without the
+/// `\[`/`\]` markers, and with newlines also returning the cursor to the
+/// first column.
+fn printable_prompt(prompt: &str) -> String {
+    prompt
+        .replace([prompt::START_IGNORE, prompt::END_IGNORE], "")
+        .replace('\n', "\r\n")
+}
+
+/// Returns the color escape sequence for each byte of the line, or `""`
+/// for plain text.
+///
+/// Command names are green if they name a command and red otherwise, or
+/// left plain until the executables are known; quoted strings are yellow
+/// and operators cyan. The line is left plain if colors are turned off.
+fn line_colors(line: &str, commands: &Commands) -> Vec<&'static str> {
+    let mut colors = vec![""; line.len()];
+    if !terminal::colors_enabled() {
+        return colors;
+    }
+    for (range, kind) in parser::highlight(line) {
+        let code = match kind {
+            HighlightKind::Command => {
+                let name = parser::tokenize(&line[range.clone()])
+                    .into_iter()
+                    .next()
+                    .unwrap_or_default();
+                match commands.contains(&name) {
+                    Some(true) => color::Green.fg_str(),
+                    Some(false) => color::Red.fg_str(),
+                    None => "",
+                }
+            }
+            HighlightKind::Quoted => color::Yellow.fg_str(),
+            HighlightKind::Operator => color::Cyan.fg_str(),
+        };
+        colors[range].fill(code);
+    }
+    colors
+}
+
+/// Writes `text` colored by `colors`, which holds one entry per byte as
+/// returned by `line_colors`.
+fn write_highlighted<W: Write>(out: &mut W, text: &str, colors: &[&str]) -> io::Result<()> {
+    let mut current = "";
+    for (i, c) in text.char_indices() {
+        if colors[i] != current {
+            current = colors[i];
+            if current.is_empty() {
+                write!(out, "{}", style::Reset)?;
+            } else {
+                write!(out, "{}", current)?;
+            }
+        }
+        write!(out, "{}", c)?;
+    }
+    if !current.is_empty() {
+        write!(out, "{}", style::Reset)?;
+    }
+    Ok(())
+}
+
+/// Opens `text` in the user's editor for Ctrl-X Ctrl-E and returns what was
+/// saved.
+///
+/// The editor is `$VISUAL`, then `$EDITOR`, then `vi`, and may include
+/// arguments (e.g. `code --wait`). Fails if the editor cannot be started or
+/// exits unsuccessfully, in which case the edit should be discarded.
+pub fn edit_in_external_editor(text: &str, variables: &Variables) -> io::Result<String> {
+    let command = variables
+        .get("VISUAL")
+        .or_else(|| variables.get("EDITOR"))
+        .unwrap_or_else(|| "vi".to_string());
+    let mut words = command.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+
+    let (path, mut file) = create_edit_file()?;
+    let written = writeln!(file, "{}", text);
+    drop(file);
+    if let Err(e) = written {
+        let _ = fs::remove_file(&path);
+        return Err(e);
+    }
+    let result = match process::Command::new(program)
+        .args(words)
+        .arg(&path)
+        .status()
+    {
+        Ok(status) if status.success() => fs::read_to_string(&path),
+        Ok(status) => Err(io::Error::other(format!("{}: {}", program, status))),
+        Err(e) => Err(io::Error::new(
+            e.kind(),
+            format!("{}: {}", program, describe_io_error(&e)),
+        )),
+    };
+    let _ = fs::remove_file(&path);
+    result
+}
+
+/// Creates the file `edit_in_external_editor` edits, in the temporary
+/// directory and readable only by the user. Its name is random and it must
+/// not exist yet, so another user can't have put a symbolic link there for
+/// the shell to write the command line through.
+fn create_edit_file() -> io::Result<(PathBuf, File)> {
+    const ATTEMPTS: usize = 100;
+    for _ in 0..ATTEMPTS {
+        let name = RandomState::new().hash_one(process::id());
+        let path = env::temp_dir().join(format!("shell-edit-{:016x}.sh", name));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+        {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::from(io::ErrorKind::AlreadyExists))
+}
+
+/// Returns true for grapheme clusters that make up a word for
+/// Alt-b/Alt-f/Alt-d.
+fn is_word(grapheme: &str) -> bool {
+    grapheme.chars().next().is_some_and(char::is_alphanumeric)
+}
+
+/// Returns true for whitespace grapheme clusters.
+fn is_blank(grapheme: &str) -> bool {
+    grapheme.chars().all(char::is_whitespace)
+}
@@ -0,0 +1,98 @@
+use std::env;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::ops::Deref;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+
+/// The shell's working directory.
+///
+/// The shell keeps it rather than changing the process's, in the same way
+/// as its exported variables: `cd` changes only this, and the commands the
+/// shell starts are started in it. A subshell, such as a command
+/// substitution, changes its own copy, so nothing needs undoing after it.
+///
+/// Like bash's, the directory is kept as the path it was reached by,
+/// through any symbolic links, rather than as `getcwd` resolves it.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::environment::WorkingDir;
+///
+/// let mut cwd = WorkingDir::default();
+/// let before = std::env::current_dir().unwrap();
+/// cwd.change("/").unwrap();
+/// assert_eq!(cwd.join("tmp"), std::path::Path::new("/tmp"));
+/// assert_eq!(std::env::current_dir().unwrap(), before);
+/// assert!(cwd.change("/no/such/directory").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkingDir(PathBuf);
+
+impl Default for WorkingDir {
+    /// The process's working directory, or `/` if it has been removed.
+    /// `PWD` is used for it if it names the same directory, so that the
+    /// path it was reached by is kept.
+    fn default() -> Self {
+        let Ok(dir) = env::current_dir() else {
+            return WorkingDir(PathBuf::from("/"));
+        };
+        let logical = env::var_os("PWD")
+            .map(PathBuf::from)
+            .filter(|pwd| pwd.is_absolute() && same_file(pwd, &dir));
+        WorkingDir(logical.unwrap_or(dir))
+    }
+}
+
+/// Checks whether two paths lead to the same file.
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Removes the `.` and `..` components of an absolute path without
+/// resolving symbolic links, so that `..` goes back the way the path came.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normal.pop();
+            }
+            component => normal.push(component),
+        }
+    }
+    normal
+}
+
+impl Deref for WorkingDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl WorkingDir {
+    /// Changes to `path`, taken relative to the current directory, as
+    /// `chdir` would: it must be a directory the user may search. The new
+    /// directory is its full path with `.` and `..` removed, keeping any
+    /// symbolic links in it, as `cd` does in bash.
+    pub fn change(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let dir = normalize(&self.join(path));
+        if !fs::metadata(&dir)?.is_dir() {
+            return Err(io::Error::from_raw_os_error(libc::ENOTDIR));
+        }
+        let name = CString::new(dir.as_os_str().as_encoded_bytes())
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        if unsafe { libc::access(name.as_ptr(), libc::X_OK) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        self.0 = dir;
+        Ok(())
+    }
+}
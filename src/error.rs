@@ -0,0 +1,223 @@
+use crate::expand::ExpansionError;
+use crate::redirect::{RedirectionError, describe_io_error};
+use crate::shell::Shell;
+use crate::terminal;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// An error the shell reports instead of running, or while running, a
+/// command.
+///
+/// Displays as bash would print it, prefixed with the shell's name where
+/// bash prefixes its own.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::error::ShellError;
+/// let error = ShellError::CommandNotFound("gti".to_string());
+/// assert_eq!(error.to_string(), "gti: command not found");
+/// ```
+#[derive(Debug, thiserror::Error)]
+pub enum ShellError {
+    /// No builtin, executable or plugin has the command's name.
+    #[error("{0}: command not found")]
+    CommandNotFound(String),
+    /// A redirection target could not be opened, so the command didn't run.
+    #[error("shell: {0}")]
+    RedirectionFailed(#[from] RedirectionError),
+    /// The command line isn't valid syntax.
+    #[error("shell: {0}")]
+    ParseError(String),
+    /// The command isn't allowed, such as in a restricted shell or by
+    /// assigning a readonly variable.
+    #[error("shell: {0}")]
+    NotPermitted(String),
+    /// An executable was found but could not be started.
+    #[error("shell: {command}: {}", describe_io_error(.source))]
+    ExecFailed { command: String, source: io::Error },
+    /// A system call the shell needed failed: `context` names the file or
+    /// operation.
+    #[error("shell: {context}: {}", describe_io_error(.source))]
+    IoError { context: String, source: io::Error },
+    /// A word of the command could not be expanded, so it didn't run.
+    #[error("shell: {0}")]
+    ExpansionFailed(#[from] ExpansionError),
+}
+
+impl ShellError {
+    /// The category of the error, which stays the same as messages change.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ShellError::CommandNotFound(_) => ErrorCode::CommandNotFound,
+            ShellError::RedirectionFailed(_) => ErrorCode::Redirection,
+            ShellError::ParseError(_) => ErrorCode::Syntax,
+            ShellError::NotPermitted(_) => ErrorCode::NotPermitted,
+            ShellError::ExecFailed { .. } => ErrorCode::Exec,
+            ShellError::IoError { .. } => ErrorCode::Io,
+            ShellError::ExpansionFailed(_) => ErrorCode::Expansion,
+        }
+    }
+
+    /// The exit status of a command that failed with this error, as bash
+    /// gives it.
+    pub fn status(&self) -> i32 {
+        self.code().status()
+    }
+
+    /// An I/O error while working on `context`, such as a file name.
+    pub fn io(context: impl Into<String>, source: io::Error) -> Self {
+        ShellError::IoError {
+            context: context.into(),
+            source,
+        }
+    }
+}
+
+/// A stable code for each category of [`ShellError`], for tools that
+/// check why a command failed without matching on its message.
+///
+/// The names and numbers never change meaning; new categories get new
+/// ones.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::error::{ErrorCode, ShellError};
+/// let error = ShellError::ParseError("syntax error near unexpected token `|'".to_string());
+/// assert_eq!(error.code(), ErrorCode::Syntax);
+/// assert_eq!(error.code().as_str(), "E_SYNTAX");
+/// assert_eq!(error.code().number(), 3);
+/// assert_eq!(error.status(), 2);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// `E_CMD_NOT_FOUND`: no builtin, executable or plugin has the name.
+    CommandNotFound,
+    /// `E_REDIR`: a redirection target could not be opened.
+    Redirection,
+    /// `E_SYNTAX`: the command line isn't valid syntax.
+    Syntax,
+    /// `E_NOT_PERMITTED`: the command isn't allowed.
+    NotPermitted,
+    /// `E_EXEC`: an executable could not be started.
+    Exec,
+    /// `E_IO`: a system call the shell needed failed.
+    Io,
+    /// `E_EXPANSION`: a word could not be expanded.
+    Expansion,
+}
+
+impl ErrorCode {
+    /// Every code, in order of their numbers.
+    pub const ALL: [ErrorCode; 7] = [
+        ErrorCode::CommandNotFound,
+        ErrorCode::Redirection,
+        ErrorCode::Syntax,
+        ErrorCode::NotPermitted,
+        ErrorCode::Exec,
+        ErrorCode::Io,
+        ErrorCode::Expansion,
+    ];
+
+    /// The code's name, such as `E_SYNTAX`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::CommandNotFound => "E_CMD_NOT_FOUND",
+            ErrorCode::Redirection => "E_REDIR",
+            ErrorCode::Syntax => "E_SYNTAX",
+            ErrorCode::NotPermitted => "E_NOT_PERMITTED",
+            ErrorCode::Exec => "E_EXEC",
+            ErrorCode::Io => "E_IO",
+            ErrorCode::Expansion => "E_EXPANSION",
+        }
+    }
+
+    /// The code's number, from 1.
+    pub fn number(&self) -> u16 {
+        match self {
+            ErrorCode::CommandNotFound => 1,
+            ErrorCode::Redirection => 2,
+            ErrorCode::Syntax => 3,
+            ErrorCode::NotPermitted => 4,
+            ErrorCode::Exec => 5,
+            ErrorCode::Io => 6,
+            ErrorCode::Expansion => 7,
+        }
+    }
+
+    /// The exit status, `$?`, of a command that failed this way, as bash
+    /// gives it: 127 when not found, 126 when it can't be run, 2 for
+    /// syntax errors and 1 otherwise. Several codes share a status.
+    pub fn status(&self) -> i32 {
+        match self {
+            ErrorCode::CommandNotFound => 127,
+            ErrorCode::Exec => 126,
+            ErrorCode::Syntax => 2,
+            ErrorCode::Redirection
+            | ErrorCode::NotPermitted
+            | ErrorCode::Io
+            | ErrorCode::Expansion => 1,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ErrorCode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ErrorCode::ALL
+            .into_iter()
+            .find(|code| code.as_str() == s)
+            .ok_or(())
+    }
+}
+
+/// The name the shell prefixes its own messages with, where bash puts
+/// `bash`.
+pub const SHELL_NAME: &str = "shell";
+
+/// The escape sequences errors are colored with.
+const ERROR_COLOR: &str = "\x1b[31m";
+const RESET: &str = "\x1b[m";
+
+/// Formats a message as the shell prints its errors: in red if `color` is
+/// set. Every error the shell prints itself goes through here.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::error;
+/// assert_eq!(error::format("gti: command not found", false), "gti: command not found");
+/// assert_eq!(error::format("oops", true), "\x1b[31moops\x1b[m");
+/// ```
+pub fn format(message: &str, color: bool) -> String {
+    if color {
+        format!("{}{}{}", ERROR_COLOR, message, RESET)
+    } else {
+        message.to_string()
+    }
+}
+
+/// Prints an error to the shell's stderr. This is where every error the
+/// shell itself reports is printed.
+pub fn report(error: &ShellError, shell: &Shell) {
+    print(&error.to_string(), shell);
+}
+
+/// Prints a message of the shell's own, such as a warning, to its stderr,
+/// prefixed with [`SHELL_NAME`] as [`report`] prints errors.
+pub fn report_message(message: impl fmt::Display, shell: &Shell) {
+    print(&format!("{}: {}", SHELL_NAME, message), shell);
+}
+
+/// Writes a line to the shell's stderr, colored if that is a terminal
+/// which may be colored.
+fn print(message: &str, shell: &Shell) {
+    let color = shell.streams.inherits_stderr() && terminal::stderr_colors_enabled();
+    let _ = writeln!(shell.streams.stderr(), "{}", format(message, color));
+}
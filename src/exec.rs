@@ -0,0 +1,810 @@
+use crate::builtins::Builtin;
+use crate::error::{self, ShellError};
+use crate::executor::Spawn;
+use crate::jobs::Coproc;
+use crate::redirect::{Redirection, StdioTargets, open_redirections, parse_redirections};
+use crate::shell::{CommandLine, Shell};
+use crate::streams::{Buffer, Connection};
+use crate::{CommandResult, ShellStatus, expand, hooks, parser, signals, tokenize, variables};
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
+
+/// Evaluates a command line in `shell`: runs a foreground pipeline and
+/// waits for it, or starts a background job or coprocess in the job table.
+/// With `set -n`, prints the command line it would run instead.
+///
+/// This is what runs every command, whether typed at the prompt, given
+/// with `-c`, read from a script, or passed to `eval`. The exit status is
+/// kept for `$?`.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::{Shell, exec};
+/// use codecrafters_shell::shell::CommandLine;
+///
+/// let mut shell = Shell::default();
+/// shell.variables.set("assignment", "name=world");
+/// exec::eval(&mut shell, &CommandLine::parse("eval $assignment").unwrap());
+/// assert_eq!(shell.variables.get("name").as_deref(), Some("world"));
+///
+/// exec::eval(&mut shell, &CommandLine::parse("eval 'sleep 1 &'").unwrap());
+/// assert!(shell.jobs.has_unfinished());
+/// ```
+pub fn eval(shell: &mut Shell, command: &CommandLine) -> CommandResult {
+    let result = match command {
+        CommandLine::Foreground(line) => return execute_pipeline(line, shell),
+        CommandLine::Background(line) | CommandLine::Coproc(line) if shell.options.no_exec => {
+            let explained = explain_pipeline(line, shell);
+            let explained = match command {
+                CommandLine::Coproc(_) => CommandLine::Coproc(explained),
+                _ => CommandLine::Background(explained),
+            };
+            let _ = writeln!(shell.streams.stdout(), "{}", explained);
+            CommandResult::finished(0)
+        }
+        CommandLine::Background(line) => start_background(line, shell),
+        CommandLine::Coproc(line) => start_coproc(line, shell),
+    };
+    shell.variables.set_status(result.status);
+    result
+}
+
+/// Evaluates the commands of a script in `shell` one at a time, with
+/// [`eval`]. Lines ending in a backslash or inside an open quote continue
+/// on the next line, and empty lines and `#` comments are skipped. Stops
+/// early if a command exits the shell, returning that status.
+pub fn eval_script(shell: &mut Shell, script: &str) -> ShellStatus {
+    for line in script_commands(script) {
+        if let Some(signal) = signals::take_termination() {
+            return ShellStatus::Exit(128 + signal);
+        }
+        if let Some(command) = CommandLine::parse(&line)
+            && let ShellStatus::Exit(code) = eval(shell, &command).shell
+        {
+            return ShellStatus::Exit(code);
+        }
+    }
+    ShellStatus::Continue
+}
+
+/// Expands a command into its words, running the commands of command
+/// substitutions.
+fn expand_words(line: &str, shell: &Shell) -> Result<Vec<String>, ShellError> {
+    let substitute = |command: &str| substitute_command(command, shell);
+    let words = expand::expand(
+        line,
+        &expand::Context::new(&shell.variables)
+            .with_cwd(&shell.cwd)
+            .with_substitution(&substitute),
+    )?;
+    Ok(words)
+}
+
+/// Runs the command of a `$(command)` in a subshell and returns its output
+/// without trailing newlines.
+fn substitute_command(command: &str, shell: &Shell) -> String {
+    let output = Buffer::default();
+    let mut subshell = shell.subshell();
+    subshell.streams = subshell.streams.clone().with_stdout(output.clone());
+    eval_script(&mut subshell, command);
+    output.contents().trim_end_matches('\n').to_string()
+}
+
+/// Orchestrates command execution.
+///
+/// It first attempts to parse the command as a `Builtin`. If that fails,
+/// it searches for an external executable in the `PATH` and runs it.
+/// Errors, such as the command not being found, are reported with
+/// [`error::report`].
+pub fn handle_command(command: &str, args: Vec<String>, shell: &mut Shell) -> CommandResult {
+    let started = Instant::now();
+    let mut result = run_command(command, args, &[], shell).unwrap_or_else(|e| {
+        error::report(&e, shell);
+        CommandResult::finished(e.status())
+    });
+    result.duration = started.elapsed();
+    result
+}
+
+/// Runs a command like `handle_command`, with variables assigned before
+/// it (`name=value command`) added to its environment.
+fn run_command(
+    command: &str,
+    args: Vec<String>,
+    env: &[(String, String)],
+    shell: &mut Shell,
+) -> Result<CommandResult, ShellError> {
+    let (clean_args, redirections) = parse_redirections(args)?;
+    env.iter()
+        .try_for_each(|(name, _)| shell.options.check_assignment(name))
+        .and_then(|()| {
+            shell
+                .options
+                .check_restrictions(command, &clean_args, &redirections)
+        })
+        .map_err(ShellError::NotPermitted)?;
+    // Replaced streams are connected through pipes for external commands,
+    // and for builtins that copy one stream onto the other, as `2>&1` does
+    let builtin = command.parse::<Builtin>();
+    let external = builtin.is_err() && resolve_command(command, shell).is_some();
+    let mut connection = if external {
+        shell.streams.connect()
+    } else if redirections
+        .iter()
+        .any(|redirection| matches!(redirection, Redirection::Duplicate { .. }))
+    {
+        shell.streams.connect_output()
+    } else {
+        Connection::default()
+    };
+    let stdio = StdioTargets {
+        stdin: connection.stdin.take(),
+        stdout: connection.stdout.take(),
+        stderr: connection.stderr.take(),
+    };
+    // The files are closed at the end of the block, before waiting for
+    // everything written to them to be copied
+    let result = {
+        // Open every target before running anything, so a failure skips the command
+        let targets = open_redirections(&redirections, stdio, &shell.cwd)?;
+        let stdin_file = targets.stdin;
+        let stdout_file = targets.stdout;
+        let stderr_file = targets.stderr;
+
+        match builtin {
+            Ok(builtin) => {
+                // Builtins such as `read` take their input from the streams
+                let streams = shell.streams.clone();
+                if let Some(file) = stdin_file {
+                    shell.streams = streams.clone().with_stdin(file);
+                }
+                let mut stdout: Box<dyn Write> = match stdout_file {
+                    Some(f) => Box::new(f),
+                    None => shell.streams.stdout(),
+                };
+                let mut stderr: Box<dyn Write> = match stderr_file {
+                    Some(f) => Box::new(f),
+                    None => shell.streams.stderr(),
+                };
+                let result = with_env(env, shell, |shell| {
+                    builtin.execute(clean_args, &mut *stdout, &mut *stderr, shell)
+                });
+                shell.streams = streams;
+                Ok(result)
+            }
+            Err(_) => {
+                if external {
+                    let spawn = Spawn {
+                        program: command.to_string(),
+                        args: clean_args,
+                        env: env.to_vec(),
+                        environment: shell.variables.exported(),
+                        cwd: shell.cwd.to_path_buf(),
+                        redirections,
+                        stdin: stdin_file,
+                        stdout: stdout_file,
+                        stderr: stderr_file,
+                    };
+                    let executor = &shell.executor;
+                    let finished = executor.spawn(spawn).and_then(|pid| {
+                        trace!(pid, command, "spawned");
+                        Ok((pid, executor.wait(pid)?))
+                    });
+                    let (pid, status) = finished.map_err(|source| ShellError::ExecFailed {
+                        command: command.to_string(),
+                        source,
+                    })?;
+                    trace!(pid, status, "process ended");
+                    Ok(CommandResult {
+                        pid: Some(pid),
+                        ..CommandResult::finished(status)
+                    })
+                } else if shell.plugins.has_builtin(command) {
+                    let mut stdout: Box<dyn Write> = match stdout_file {
+                        Some(f) => Box::new(f),
+                        None => shell.streams.stdout(),
+                    };
+                    let mut stderr: Box<dyn Write> = match stderr_file {
+                        Some(f) => Box::new(f),
+                        None => shell.streams.stderr(),
+                    };
+                    let status =
+                        shell
+                            .plugins
+                            .run_builtin(command, &clean_args, &mut *stdout, &mut *stderr);
+                    Ok(CommandResult::finished(status.unwrap_or(0)))
+                } else if shell.options.auto_cd
+                    && clean_args.is_empty()
+                    && shell.cwd.join(command).is_dir()
+                {
+                    // A restricted shell can't change directory this way either
+                    shell
+                        .options
+                        .check_restrictions("cd", &[], &[])
+                        .map_err(ShellError::NotPermitted)?;
+                    let (stdout, stderr) = (shell.streams.stdout(), shell.streams.stderr());
+                    Ok(Builtin::Cd.execute(vec![command.to_string()], stdout, stderr, shell))
+                } else if let Some(hook) = shell.hooks.command_not_found().cloned() {
+                    hook(command, &clean_args, shell);
+                    Ok(CommandResult::finished(127))
+                } else if let Some(result) =
+                    hooks::run_command_not_found(command, &clean_args, shell)
+                {
+                    Ok(result)
+                } else {
+                    Err(ShellError::CommandNotFound(command.to_string()))
+                }
+            }
+        }
+    };
+    connection.finish();
+    result
+}
+
+/// Runs `f` with variables exported in the shell's variables, restoring
+/// their previous values afterwards.
+fn with_env<R>(env: &[(String, String)], shell: &mut Shell, f: impl FnOnce(&mut Shell) -> R) -> R {
+    let saved: Vec<_> = env
+        .iter()
+        .map(|(name, _)| {
+            let variables = &shell.variables;
+            (name, variables.get(name), variables.is_exported(name))
+        })
+        .collect();
+    for (name, value) in env {
+        shell.variables.export(name, Some(value));
+    }
+    let result = f(shell);
+    for (name, value, exported) in saved.into_iter().rev() {
+        match value {
+            Some(value) if exported => shell.variables.export(name, Some(&value)),
+            Some(value) => {
+                shell.variables.set(name, &value);
+                shell.variables.unexport(name);
+            }
+            None => shell.variables.unset(name),
+        }
+    }
+    result
+}
+
+/// Finds the executable `command` runs: searched for on the shell's `PATH`,
+/// or taken from the working directory if it is a path.
+pub(crate) fn resolve_command(command: &str, shell: &Shell) -> Option<PathBuf> {
+    let path = shell.variables.get("PATH").unwrap_or_default();
+    match command.contains('/') {
+        true => (shell.executor).resolve(&shell.cwd.join(command).to_string_lossy(), &path),
+        false => shell.executor.resolve(command, &path),
+    }
+}
+
+/// Splits the `name=value` words at the start of a command off from the
+/// command and its arguments.
+pub(crate) fn split_assignments(words: Vec<String>) -> (Vec<(String, String)>, Vec<String>) {
+    let count = words
+        .iter()
+        .take_while(|word| variables::parse_assignment(word).is_some())
+        .count();
+    let mut words = words.into_iter();
+    let assignments = words
+        .by_ref()
+        .take(count)
+        .filter_map(|word| {
+            let (name, value) = variables::parse_assignment(&word)?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect();
+    (assignments, words.collect())
+}
+
+/// Splits a script into its commands: lines ending in a backslash or
+/// inside an open quote are joined with the next line, and empty lines and
+/// `#` comments are dropped.
+pub(crate) fn script_commands(script: &str) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut command = String::new();
+    for line in script.lines() {
+        command.push_str(line);
+        match parser::continuation(&command) {
+            Some(parser::Continuation::Backslash) => {
+                command.pop();
+                continue;
+            }
+            Some(parser::Continuation::Quote(_)) => {
+                command.push('\n');
+                continue;
+            }
+            None => {}
+        }
+        let line = std::mem::take(&mut command);
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            commands.push(line.to_string());
+        }
+    }
+    commands
+}
+
+/// Reads a file, relative to the working directory, and runs its commands
+/// in the current shell.
+pub fn source_file(path: &Path, shell: &mut Shell) -> std::io::Result<ShellStatus> {
+    let script = std::fs::read_to_string(shell.cwd.join(path))?;
+    Ok(eval_script(shell, &script))
+}
+
+/// Executes a pipeline of N commands connected by pipes.
+///
+/// Takes the full input string, splits it by '|', and executes the commands
+/// with each command's stdout connected to the next command's stdin.
+/// Supports both built-in and external commands, and each segment may carry
+/// its own redirections, which take precedence over the pipe.
+///
+/// The exit status is kept for `$?`.
+pub fn execute_pipeline(input: &str, shell: &mut Shell) -> CommandResult {
+    let started = Instant::now();
+    let result = try_execute_pipeline(input, shell).unwrap_or_else(|e| {
+        trace!(error = %e, code = %e.code(), "command failed");
+        error::report(&e, shell);
+        CommandResult {
+            duration: started.elapsed(),
+            ..CommandResult::finished(e.status())
+        }
+    });
+    trace!(status = result.status, duration = ?result.duration, "command finished");
+    shell.variables.set_status(result.status);
+    result
+}
+
+/// Executes a pipeline like [`execute_pipeline`], returning the error
+/// that stopped it instead of reporting it.
+///
+/// Errors in one segment of a longer pipeline, such as a redirection that
+/// fails, are still reported there, as the other segments run anyway.
+pub fn try_execute_pipeline(input: &str, shell: &mut Shell) -> Result<CommandResult, ShellError> {
+    let started = Instant::now();
+    let parts: Vec<&str> = input.split('|').map(|s| s.trim()).collect();
+    trace!(input, segments = ?parts, "parsed pipeline");
+
+    // `set` still runs, so that `set +n` can turn this off again
+    if shell.options.no_exec
+        && !(parts.len() == 1 && tokenize(parts[0]).first().is_some_and(|w| w == "set"))
+    {
+        let explained = explain_pipeline(input, shell);
+        let _ = writeln!(shell.streams.stdout(), "{}", explained);
+        return Ok(CommandResult::finished(0));
+    }
+
+    if parts.len() == 1 {
+        // Single command, no pipeline needed
+        let words = expand_words(parts[0], shell)?;
+        trace!(?words, "expanded");
+        let (assignments, words) = split_assignments(words);
+        let mut words = words.into_iter();
+        let result = match words.next() {
+            Some(cmd) => run_command(&cmd, words.collect(), &assignments, shell),
+            // Assignments on their own set shell variables
+            None => {
+                for (name, value) in assignments {
+                    shell
+                        .options
+                        .check_assignment(&name)
+                        .map_err(ShellError::NotPermitted)?;
+                    shell.variables.set(&name, &value);
+                }
+                Ok(CommandResult::finished(0))
+            }
+        };
+        return result.map(|result| CommandResult {
+            duration: started.elapsed(),
+            ..result
+        });
+    }
+
+    // Replaced streams are connected through pipes
+    let mut connection = shell.streams.connect();
+    let (stdin, stdout, stderr) = (
+        connection.stdin.take(),
+        connection.stdout.take(),
+        connection.stderr.take(),
+    );
+    let spawned = spawn_pipeline(&parts, shell, stdin, stdout, stderr);
+    // The pipeline's status is that of its last command
+    let mut last = CommandResult::finished(0);
+    if let Ok(spawned) = &spawned {
+        // Wait for all processes
+        for &pid in &spawned.pids {
+            last.pid = Some(pid);
+            // Already reaped, as by the `wait` builtin
+            last.status = shell.executor.wait(pid).unwrap_or(0);
+        }
+        if spawned.last_skipped {
+            last = CommandResult::finished(1);
+        }
+    }
+    connection.finish();
+    spawned.map(|_| CommandResult {
+        duration: started.elapsed(),
+        ..last
+    })
+}
+
+/// Describes how a pipeline would run, as the command line it expands to:
+/// the assignments, words and redirections of each segment, quoted where
+/// needed.
+///
+/// This is what the shell prints instead of running a command with
+/// `set -n`.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::{Shell, explain_pipeline};
+///
+/// let mut shell = Shell::default();
+/// shell.variables.set("name", "my file");
+/// assert_eq!(
+///     explain_pipeline("LANG=C cat \"$name\" | sort > sorted 2>&1", &shell),
+///     "LANG=C cat 'my file' | sort > sorted 2>&1"
+/// );
+/// ```
+pub fn explain_pipeline(input: &str, shell: &Shell) -> String {
+    let context = expand::Context::new(&shell.variables).with_cwd(&shell.cwd);
+    let segments: Vec<String> = input
+        .split('|')
+        .map(|part| {
+            // Words that don't expand are shown as they are written
+            let words = expand::expand(part.trim(), &context)
+                .unwrap_or_else(|_| expand::split_words(part.trim()));
+            let (env, words) = split_assignments(words);
+            // Words that don't parse are shown as they are
+            let (words, redirections) =
+                parse_redirections(words.clone()).unwrap_or((words, Vec::new()));
+            let env = env
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, parser::quote(value)));
+            let words = words.iter().map(|word| parser::quote(word));
+            let redirections = redirections.iter().map(|r| r.to_string());
+            env.chain(words)
+                .chain(redirections)
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+    segments.join(" | ")
+}
+
+/// Starts `input` as a background job.
+///
+/// Background jobs read stdin from `/dev/null`, so they can never compete
+/// with the line editor for keystrokes.
+///
+/// The result has the PID of the job's last command, and succeeds unless
+/// the job could not be started.
+pub fn start_background(input: &str, shell: &mut Shell) -> CommandResult {
+    let stdin = match File::open("/dev/null") {
+        Ok(file) => file,
+        Err(e) => {
+            let error = ShellError::io("/dev/null", e);
+            error::report(&error, shell);
+            return CommandResult::finished(error.status());
+        }
+    };
+
+    let parts: Vec<&str> = input.split('|').map(|s| s.trim()).collect();
+    match spawn_pipeline(&parts, shell, Some(stdin), None, None) {
+        Ok(Spawned { pids, .. }) if !pids.is_empty() => {
+            let last_pid = *pids.last().unwrap_or(&0);
+            let id = shell.jobs.add(pids, input, None);
+            let _ = writeln!(shell.streams.stdout(), "[{}] {}", id, last_pid);
+            CommandResult {
+                pid: Some(last_pid),
+                ..CommandResult::finished(0)
+            }
+        }
+        Ok(_) => CommandResult::finished(0),
+        Err(e) => {
+            error::report(&e, shell);
+            CommandResult::finished(e.status())
+        }
+    }
+}
+
+/// Starts `input` as a coprocess connected to the shell by two pipes.
+///
+/// The coprocess runs asynchronously; the shell keeps the write end of its
+/// stdin and the read end of its stdout in its job table. As in bash, the
+/// array `COPROC` holds the descriptors to read from and write to, for
+/// redirections such as `<&${COPROC[0]}`, and `COPROC_PID` its process
+/// ID. The result is like that of [`start_background`].
+pub fn start_coproc(input: &str, shell: &mut Shell) -> CommandResult {
+    let name = "COPROC";
+    if let Some(job) = shell.jobs.coproc(name) {
+        error::report_message(
+            format_args!(
+                "warning: execute_coproc: coproc [{}:{}] still exists",
+                job.pids.first().unwrap_or(&0),
+                name
+            ),
+            shell,
+        );
+    }
+
+    let ((child_stdin, shell_write), (shell_read, child_stdout)) =
+        match create_pipe().and_then(|stdin| Ok((stdin, create_pipe()?))) {
+            Ok(pipes) => pipes,
+            Err(e) => {
+                let error = ShellError::io("pipe error", e);
+                error::report(&error, shell);
+                return CommandResult::finished(error.status());
+            }
+        };
+
+    let parts: Vec<&str> = input.split('|').map(|s| s.trim()).collect();
+    match spawn_pipeline(&parts, shell, Some(child_stdin), Some(child_stdout), None) {
+        Ok(Spawned { pids, .. }) if !pids.is_empty() => {
+            let last_pid = *pids.last().unwrap_or(&0);
+            let fds = [shell_read.as_raw_fd(), shell_write.as_raw_fd()];
+            shell
+                .variables
+                .set_array(name, fds.map(|fd| fd.to_string()).to_vec());
+            shell
+                .variables
+                .set(&format!("{}_PID", name), &last_pid.to_string());
+            let coproc = Coproc {
+                name: name.to_string(),
+                read: shell_read,
+                write: shell_write,
+            };
+            let id = shell.jobs.add(pids, input, Some(coproc));
+            let _ = writeln!(shell.streams.stdout(), "[{}] {}", id, last_pid);
+            CommandResult {
+                pid: Some(last_pid),
+                ..CommandResult::finished(0)
+            }
+        }
+        Ok(_) => CommandResult::finished(0),
+        Err(e) => {
+            error::report(&e, shell);
+            CommandResult::finished(e.status())
+        }
+    }
+}
+
+/// The commands of a pipeline that [`spawn_pipeline`] started.
+pub(crate) struct Spawned {
+    /// The PIDs of the commands started, in order; none for an empty
+    /// command.
+    pub pids: Vec<i32>,
+    /// Whether the last command was skipped, as when its redirections
+    /// failed. The pipeline's status is then 1 rather than that of the last
+    /// command that ran.
+    pub last_skipped: bool,
+}
+
+/// Spawns every segment of a pipeline without waiting for them to finish.
+///
+/// `stdin` and `stdout`, when given, connect the first and last segments
+/// instead of the shell's own streams, and `stderr` is where every segment
+/// writes errors unless redirected. Returns the commands started, or the
+/// error that kept the pipeline from starting.
+pub(crate) fn spawn_pipeline(
+    parts: &[&str],
+    shell: &Shell,
+    mut stdin: Option<File>,
+    mut stdout: Option<File>,
+    stderr: Option<File>,
+) -> Result<Spawned, ShellError> {
+    // Parse all commands, rejecting the whole pipeline before anything runs
+    let mut commands = Vec::new();
+    for part in parts {
+        let (env, tokens) = split_assignments(expand_words(part, shell)?);
+        trace!(?env, words = ?tokens, "expanded");
+        if tokens.is_empty() {
+            if parts.len() > 1 {
+                return Err(ShellError::ParseError(
+                    "syntax error near unexpected token `|'".to_string(),
+                ));
+            }
+            return Ok(Spawned {
+                pids: Vec::new(),
+                last_skipped: false,
+            });
+        }
+        let cmd = tokens[0].clone();
+        let (args, redirections) = parse_redirections(tokens[1..].to_vec())?;
+        env.iter()
+            .try_for_each(|(name, _)| shell.options.check_assignment(name))
+            .and_then(|()| shell.options.check_restrictions(&cmd, &args, &redirections))
+            .map_err(ShellError::NotPermitted)?;
+        commands.push((cmd, args, env, redirections));
+    }
+
+    // Create pipes for N-1 connections
+    let num_pipes = commands.len() - 1;
+    let mut pipes: Vec<(File, File)> = Vec::new();
+
+    for _ in 0..num_pipes {
+        // Any pipes already created are closed when dropped
+        pipes.push(create_pipe().map_err(|e| ShellError::io("pipe error", e))?);
+    }
+
+    // Spawn all commands
+    let mut pids: Vec<i32> = Vec::new();
+    let mut last_skipped = false;
+
+    for (i, (cmd, args, env, redirections)) in commands.into_iter().enumerate() {
+        let is_first = i == 0;
+        let is_last = i == num_pipes;
+        let is_builtin = Builtin::from_str(&cmd).is_ok() || shell.plugins.has_builtin(&cmd);
+
+        // Determine stdin for this command
+        let stdin = if is_first {
+            stdin.take()
+        } else {
+            pipes[i - 1].0.try_clone().ok() // Read from previous pipe
+        };
+
+        // Determine stdout for this command, then apply its redirections
+        let stdout = if is_last {
+            stdout.take()
+        } else {
+            pipes[i].1.try_clone().ok() // Write to next pipe
+        };
+        let stdio = StdioTargets {
+            stdin,
+            stdout,
+            stderr: stderr.as_ref().and_then(|f| f.try_clone().ok()),
+        };
+        let targets = match open_redirections(&redirections, stdio, &shell.cwd) {
+            Ok(targets) => targets,
+            Err(e) => {
+                // Like bash, only this segment is skipped; the rest still run
+                error::report(&ShellError::from(e), shell);
+                last_skipped = is_last;
+                continue;
+            }
+        };
+
+        let spawned = if is_builtin {
+            execute_builtin_in_pipeline(
+                &cmd,
+                args,
+                &env,
+                shell,
+                targets.stdin,
+                targets.stdout,
+                targets.stderr,
+            )
+        } else {
+            spawn_external_in_pipeline(
+                shell,
+                Spawn {
+                    program: cmd.clone(),
+                    args,
+                    env,
+                    environment: shell.variables.exported(),
+                    cwd: shell.cwd.to_path_buf(),
+                    redirections,
+                    stdin: targets.stdin,
+                    stdout: targets.stdout,
+                    stderr: targets.stderr,
+                },
+            )
+        };
+
+        match spawned {
+            Ok(pid) => {
+                trace!(pid, command = %cmd, builtin = is_builtin, "spawned");
+                pids.push(pid);
+            }
+            Err(e) => {
+                // Clean up: kill spawned processes; pipes are closed when dropped
+                for spawned_pid in pids {
+                    shell.executor.kill(spawned_pid, libc::SIGKILL);
+                }
+                return Err(e);
+            }
+        }
+    }
+
+    // Pipe fds in the parent are closed when `pipes` is dropped
+    Ok(Spawned { pids, last_skipped })
+}
+
+/// Creates a pipe, returning its `(read, write)` ends.
+///
+/// Both ends are marked close-on-exec so spawned commands only see the
+/// ends explicitly handed to them as stdin/stdout.
+pub(crate) fn create_pipe() -> std::io::Result<(File, File)> {
+    unsafe {
+        let mut fds = [0; 2];
+        if libc::pipe(fds.as_mut_ptr()) == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        for fd in fds {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+        Ok((File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])))
+    }
+}
+
+/// Spawns an external command in a pipeline with redirected I/O.
+///
+/// Returns the PID of the spawned child process.
+fn spawn_external_in_pipeline(shell: &Shell, spawn: Spawn) -> Result<i32, ShellError> {
+    let cmd = spawn.program.clone();
+    match shell.executor.spawn(spawn) {
+        Ok(pid) => Ok(pid),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && !cmd.contains('/') => {
+            Err(ShellError::CommandNotFound(cmd))
+        }
+        Err(source) => Err(ShellError::ExecFailed {
+            command: cmd,
+            source,
+        }),
+    }
+}
+
+/// Executes a built-in command in a forked child process with redirected I/O.
+///
+/// Returns the PID of the forked child process.
+fn execute_builtin_in_pipeline(
+    cmd: &str,
+    args: Vec<String>,
+    env: &[(String, String)],
+    shell: &Shell,
+    stdin: Option<File>,
+    stdout: Option<File>,
+    stderr: Option<File>,
+) -> Result<i32, ShellError> {
+    unsafe {
+        let pid = libc::fork();
+
+        if pid == 0 {
+            // Child process
+
+            // Redirect stdin, stdout and stderr if needed
+            if let Some(file) = &stdin {
+                libc::dup2(file.as_raw_fd(), 0);
+            }
+            if let Some(file) = &stdout {
+                libc::dup2(file.as_raw_fd(), 1);
+            }
+            if let Some(file) = &stderr {
+                libc::dup2(file.as_raw_fd(), 2);
+            }
+            drop((stdin, stdout, stderr));
+
+            // Execute the built-in
+            if let Ok(builtin) = Builtin::from_str(cmd) {
+                use std::io::{stderr, stdout};
+                let mut out = stdout();
+                let mut err = stderr();
+                // Changes in the child don't affect the shell, as in a subshell
+                let mut shell = shell.subshell();
+                for (name, value) in env {
+                    shell.variables.export(name, Some(value));
+                }
+                std::process::exit(builtin.execute(args, &mut out, &mut err, &mut shell).status);
+            }
+            if let Some(status) = shell.plugins.run_builtin(
+                cmd,
+                &args,
+                &mut std::io::stdout(),
+                &mut std::io::stderr(),
+            ) {
+                std::process::exit(status);
+            }
+
+            std::process::exit(1);
+        } else if pid > 0 {
+            // Parent process; the fds passed to the child are closed on drop
+            Ok(pid)
+        } else {
+            Err(ShellError::io("fork", std::io::Error::last_os_error()))
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// An executable found on the `PATH`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Executable {
+    /// The name it runs as.
+    pub name: String,
+    /// Its full path.
+    pub path: PathBuf,
+    /// The position of its directory in the `PATH`, from 0. Of several
+    /// executables with the same name, the one with the lowest position
+    /// runs.
+    pub position: usize,
+}
+
+/// The executables found on the `PATH`, from one scan of its directories.
+///
+/// The shell keeps one index between lookups, see [`with_index`], so that
+/// command lookup, completion and `type -a` don't each rescan every
+/// directory.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::executables::ExecutableIndex;
+///
+/// let index = ExecutableIndex::scan("/usr/bin:/bin".into());
+/// let sh = index.find("sh").unwrap();
+/// assert!(sh.path.ends_with("sh"));
+/// assert!(index.find_all("sh").all(|e| e.position >= sh.position));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ExecutableIndex {
+    /// The `PATH` the index was built from.
+    path: OsString,
+    /// Each `PATH` directory with its modification time when scanned.
+    dirs: Vec<(PathBuf, Option<SystemTime>)>,
+    /// The executables in `PATH` order, including shadowed duplicates.
+    entries: Vec<Executable>,
+    /// The entry each name runs, from the earliest directory.
+    first: HashMap<String, usize>,
+}
+
+/// The shell's index, shared so that the lock is only held to take or
+/// replace it, never while a directory is read.
+static INDEX: Mutex<Option<Arc<ExecutableIndex>>> = Mutex::new(None);
+
+impl ExecutableIndex {
+    /// Scans the directories of `path` for executables.
+    pub fn scan(path: OsString) -> Self {
+        Self::scan_until(path, &AtomicBool::new(false))
+    }
+
+    /// Scans the directories of `path` like [`scan`](Self::scan), stopping
+    /// early once `cancel` is set. The index is then incomplete.
+    fn scan_until(path: OsString, cancel: &AtomicBool) -> Self {
+        let mut index = ExecutableIndex {
+            dirs: Vec::new(),
+            entries: Vec::new(),
+            first: HashMap::new(),
+            path,
+        };
+        for (position, dir) in env::split_paths(&index.path).enumerate() {
+            if cancel.load(Ordering::Relaxed) {
+                break;
+            }
+            let modified = std::fs::metadata(&dir).and_then(|m| m.modified()).ok();
+            if let Ok(entries) = std::fs::read_dir(&dir) {
+                for entry in entries.flatten() {
+                    // Follow symlinks, as running the command would
+                    if let Ok(metadata) = std::fs::metadata(entry.path())
+                        && metadata.is_file()
+                        && metadata.permissions().mode() & 0o111 != 0
+                        && let Some(name) = entry.file_name().to_str()
+                    {
+                        index
+                            .first
+                            .entry(name.to_string())
+                            .or_insert(index.entries.len());
+                        index.entries.push(Executable {
+                            name: name.to_string(),
+                            path: entry.path(),
+                            position,
+                        });
+                    }
+                }
+            }
+            index.dirs.push((dir, modified));
+        }
+        index
+    }
+
+    /// Checks whether the index still matches `path` and the contents of
+    /// its directories, which change a directory's modification time.
+    pub fn is_current(&self, path: &OsStr) -> bool {
+        self.path == path
+            && self.dirs.iter().all(|(dir, modified)| {
+                std::fs::metadata(dir).and_then(|m| m.modified()).ok() == *modified
+            })
+    }
+
+    /// Adds `executable`, found since the scan without its directory
+    /// changing, as when a file is made executable.
+    fn insert(&mut self, executable: Executable) {
+        if self
+            .find_all(&executable.name)
+            .any(|entry| entry.path == executable.path)
+        {
+            return;
+        }
+        let at = self
+            .entries
+            .partition_point(|entry| entry.position <= executable.position);
+        for i in self.first.values_mut().filter(|i| **i >= at) {
+            *i += 1;
+        }
+        let first = self.first.entry(executable.name.clone()).or_insert(at);
+        *first = (*first).min(at);
+        self.entries.insert(at, executable);
+    }
+
+    /// Returns every executable in `PATH` order, including those shadowed
+    /// by one of the same name in an earlier directory.
+    pub fn entries(&self) -> &[Executable] {
+        &self.entries
+    }
+
+    /// Returns the executable that `name` runs.
+    pub fn find(&self, name: &str) -> Option<&Executable> {
+        self.first.get(name).map(|&i| &self.entries[i])
+    }
+
+    /// Returns every executable called `name`, in `PATH` order.
+    pub fn find_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Executable> {
+        self.entries.iter().filter(move |entry| entry.name == name)
+    }
+
+    /// Returns the executables whose names start with `prefix`, in `PATH`
+    /// order and including shadowed ones.
+    pub fn starting_with<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a Executable> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.name.starts_with(prefix))
+    }
+}
+
+/// Returns the shell's index if it was built from `path`, without checking
+/// its directories.
+fn latest_index(path: &str) -> Option<Arc<ExecutableIndex>> {
+    let index = INDEX.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    index.filter(|index| index.path == path)
+}
+
+/// Makes `index` the shell's index.
+fn store_index(index: ExecutableIndex) {
+    *INDEX.lock().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(index));
+}
+
+/// Runs `f` on the shell's index if it was built from `path`, without
+/// checking whether its directories changed since.
+///
+/// The line editor uses this on every key, so that a slow directory never
+/// holds it up; the index worker keeps the index up to date instead, see
+/// [`update_index`].
+pub(crate) fn with_latest_index<R>(path: &str, f: impl FnOnce(&ExecutableIndex) -> R) -> Option<R> {
+    latest_index(path).map(|index| f(&index))
+}
+
+/// Runs `f` on the shell's index if it is up to date with `path` and its
+/// directories.
+pub(crate) fn with_current_index<R>(
+    path: &str,
+    f: impl FnOnce(&ExecutableIndex) -> R,
+) -> Option<R> {
+    latest_index(path)
+        .filter(|index| index.is_current(path.as_ref()))
+        .map(|index| f(&index))
+}
+
+/// Runs `f` on the shell's index of the executables on `path`, the value
+/// of `PATH`, rebuilding it first if `path` or any of its directories
+/// changed since it was built.
+pub fn with_index<R>(path: &str, f: impl FnOnce(&ExecutableIndex) -> R) -> R {
+    if let Some(index) = latest_index(path).filter(|index| index.is_current(path.as_ref())) {
+        return f(&index);
+    }
+
+    let index = ExecutableIndex::scan(path.into());
+    let result = f(&index);
+    store_index(index);
+    result
+}
+
+/// Brings the shell's index up to date with `path` and its directories, as
+/// [`with_index`] would, unless `cancel` is set first.
+///
+/// The index worker runs this after each prompt, so that checking the
+/// directories, and scanning them if they changed, happens off the thread
+/// running the line editor.
+pub fn update_index(path: &str, cancel: &AtomicBool) {
+    if latest_index(path).is_some_and(|index| index.is_current(path.as_ref())) {
+        return;
+    }
+    let index = ExecutableIndex::scan_until(path.into(), cancel);
+    if !cancel.load(Ordering::Relaxed) {
+        store_index(index);
+    }
+}
+
+/// Searches `path`, the value of `PATH`, for an executable with the given
+/// name.
+///
+/// Returns `Some(PathBuf)` if found and executable, otherwise `None`.
+///
+/// Uses the index when it is up to date, but never builds it: running one
+/// command shouldn't wait for a full scan of every directory.
+pub(crate) fn get_executable_path(command: &str, path: &str) -> Option<PathBuf> {
+    if !command.contains('/')
+        && let Some(Some(path)) = with_current_index(path, |index| {
+            index.find(command).map(|entry| entry.path.clone())
+        })
+    {
+        return Some(path);
+    }
+
+    // Making a file executable doesn't change its directory, so the index
+    // can miss new executables; search directly and add the one found
+    for (position, dir) in env::split_paths(path).enumerate() {
+        let full_path = dir.join(command);
+
+        if full_path.is_file()
+            && let Ok(metadata) = full_path.metadata()
+            && metadata.permissions().mode() & 0o111 != 0
+        {
+            if !command.contains('/')
+                && let Some(index) = INDEX.lock().unwrap_or_else(|e| e.into_inner()).as_mut()
+                && index.path == path
+            {
+                Arc::make_mut(index).insert(Executable {
+                    name: command.to_string(),
+                    path: full_path.clone(),
+                    position,
+                });
+            }
+            return Some(full_path);
+        }
+    }
+    None
+}
+
+/// Gets all executable names from the directories of `path`, the value of
+/// `PATH`.
+///
+/// Returns a vector of executable names (not full paths), in `PATH` order
+/// and including shadowed duplicates. Use [`with_index`] for their paths.
+pub fn get_all_executables(path: &str) -> Vec<String> {
+    with_index(path, |index| {
+        index
+            .entries()
+            .iter()
+            .map(|entry| entry.name.clone())
+            .collect()
+    })
+}
@@ -0,0 +1,143 @@
+use crate::redirect::Redirection;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+/// An external command the shell asks an [`Executor`] to start.
+#[derive(Debug)]
+pub struct Spawn {
+    /// The command name as typed, looked up on the `PATH` unless it
+    /// contains a `/`.
+    pub program: String,
+    /// The arguments, without the command name and redirections.
+    pub args: Vec<String>,
+    /// Variables assigned before the command, added to its environment.
+    pub env: Vec<(String, String)>,
+    /// The variables the shell exports, which the command's environment
+    /// is made of before `env` is added.
+    pub environment: Vec<(String, String)>,
+    /// The directory the command runs in: the shell's working directory.
+    /// A program name containing a `/` is taken from it too.
+    pub cwd: PathBuf,
+    /// The redirections of the command, already opened into the streams
+    /// below. Recorded for executors that don't start real processes.
+    pub redirections: Vec<Redirection>,
+    /// Where the command reads and writes; `None` inherits the shell's.
+    pub stdin: Option<File>,
+    pub stdout: Option<File>,
+    pub stderr: Option<File>,
+}
+
+/// Starts and waits for the external commands the shell runs.
+///
+/// The shell uses [`ProcessExecutor`] unless another is set in
+/// [`Shell::executor`](crate::Shell::executor), such as a
+/// fake that records what would run. Builtins in a pipeline still run in
+/// forked children, and background jobs are reaped by the job table.
+pub trait Executor: Send + Sync {
+    /// Finds the executable a command name runs, if any, searching `path`,
+    /// the shell's `PATH`. Commands that aren't found aren't spawned.
+    fn resolve(&self, program: &str, path: &str) -> Option<PathBuf> {
+        crate::get_executable_path(program, path)
+    }
+
+    /// Starts a command without waiting for it, returning its process ID.
+    fn spawn(&self, spawn: Spawn) -> io::Result<i32>;
+
+    /// Waits for a process to exit, returning its status as `$?` shows it:
+    /// 128 plus the signal number for a process killed by a signal.
+    fn wait(&self, pid: i32) -> io::Result<i32>;
+
+    /// Sends `signal` to a process.
+    fn kill(&self, pid: i32, signal: i32);
+}
+
+/// The executor that starts real processes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessExecutor;
+
+impl Executor for ProcessExecutor {
+    fn spawn(&self, spawn: Spawn) -> io::Result<i32> {
+        // A relative path would be ambiguous once the directory changes
+        let program = match spawn.program.contains('/') {
+            true => spawn.cwd.join(&spawn.program),
+            false => PathBuf::from(&spawn.program),
+        };
+        let mut command = Command::new(program);
+        command.args(&spawn.args);
+        command.current_dir(&spawn.cwd);
+        command.env_clear();
+        command.envs(spawn.environment.iter().map(|(name, value)| (name, value)));
+        command.envs(spawn.env.iter().map(|(name, value)| (name, value)));
+        if let Some(file) = spawn.stdin {
+            command.stdin(Stdio::from(file));
+        }
+        if let Some(file) = spawn.stdout {
+            command.stdout(Stdio::from(file));
+        }
+        if let Some(file) = spawn.stderr {
+            command.stderr(Stdio::from(file));
+        }
+        // The child is reaped by `wait`, by its process ID
+        Ok(command.spawn()?.id() as i32)
+    }
+
+    fn wait(&self, pid: i32) -> io::Result<i32> {
+        let mut status: i32 = 0;
+        // Retry if a signal such as SIGWINCH interrupts the wait
+        while unsafe { libc::waitpid(pid, &mut status, 0) } == -1 {
+            let error = io::Error::last_os_error();
+            if error.kind() != io::ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+        let status = std::process::ExitStatus::from_raw(status);
+        match status.signal() {
+            Some(signal) => {
+                trace!(pid, signal, "command killed by a signal");
+                Ok(128 + signal)
+            }
+            None => Ok(status.code().unwrap_or(0)),
+        }
+    }
+
+    fn kill(&self, pid: i32, signal: i32) {
+        unsafe { libc::kill(pid, signal) };
+    }
+}
+
+/// The executor a shell starts commands with, shared by its subshells.
+#[derive(Clone)]
+pub struct SharedExecutor(Arc<dyn Executor>);
+
+impl SharedExecutor {
+    /// Shares `executor`.
+    pub fn new(executor: impl Executor + 'static) -> Self {
+        SharedExecutor(Arc::new(executor))
+    }
+}
+
+impl Default for SharedExecutor {
+    fn default() -> Self {
+        SharedExecutor::new(ProcessExecutor)
+    }
+}
+
+impl fmt::Debug for SharedExecutor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedExecutor")
+    }
+}
+
+impl Deref for SharedExecutor {
+    type Target = dyn Executor;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
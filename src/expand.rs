@@ -0,0 +1,980 @@
+use crate::parser::quote;
+use crate::variables::{Variables, is_name, parse_assignment};
+use std::ffi::CStr;
+use std::fs;
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Chars;
+
+/// The field separators used when `IFS` is unset.
+pub(crate) const DEFAULT_IFS: &str = " \t\n";
+
+/// An expansion that failed, so that the command it is part of doesn't
+/// run. Displays as bash reports it, with the expression at fault.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::expand::ExpansionError;
+/// let error = ExpansionError::DivisionByZero("7 / 0".to_string());
+/// assert_eq!(error.to_string(), "7 / 0: division by 0");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ExpansionError {
+    /// An arithmetic expression divides by zero.
+    #[error("{0}: division by 0")]
+    DivisionByZero(String),
+    /// An arithmetic expression isn't valid.
+    #[error("{0}: syntax error in expression")]
+    InvalidExpression(String),
+}
+
+/// What expansions need from the shell: its variables, its working
+/// directory and, optionally, a way to run the commands of a command
+/// substitution.
+#[derive(Clone, Copy)]
+pub struct Context<'a> {
+    pub variables: &'a Variables,
+    /// The directory relative patterns are matched in.
+    pub cwd: &'a Path,
+    /// Runs the command of `$(command)` and returns its output. Without
+    /// it, command substitutions are left as they were written.
+    pub substitute: Option<&'a dyn Fn(&str) -> String>,
+}
+
+impl<'a> Context<'a> {
+    /// Expansions with `variables` in the process's working directory, and
+    /// no command substitution.
+    pub fn new(variables: &'a Variables) -> Self {
+        Context {
+            variables,
+            cwd: Path::new("."),
+            substitute: None,
+        }
+    }
+
+    /// Matches relative patterns in `cwd`.
+    pub fn with_cwd(mut self, cwd: &'a Path) -> Self {
+        self.cwd = cwd;
+        self
+    }
+
+    /// Runs command substitutions with `substitute`.
+    pub fn with_substitution(mut self, substitute: &'a dyn Fn(&str) -> String) -> Self {
+        self.substitute = Some(substitute);
+        self
+    }
+}
+
+/// A piece of a word after parameter expansion: text that was quoted or
+/// not, and that was written out or came from an expansion. This decides
+/// which parts field splitting and pathname expansion apply to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub text: String,
+    /// Inside quotes or escaped by a backslash.
+    pub quoted: bool,
+    /// Produced by an expansion rather than written out.
+    pub expanded: bool,
+}
+
+/// Expands a command line into the words of the command, in the POSIX
+/// order: brace expansion, tilde expansion, parameter, arithmetic and
+/// command expansion, field splitting, pathname expansion, and quote
+/// removal.
+///
+/// Assignments before the command only go through parameter expansion
+/// and quote removal, so `x=$value` assigns the whole value. Returns the
+/// first expansion that fails, such as a division by zero.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::expand::{Context, expand};
+/// use codecrafters_shell::variables::Variables;
+///
+/// let mut variables = Variables::default();
+/// variables.set("files", "a.txt b.txt");
+/// let context = Context::new(&variables);
+/// assert_eq!(
+///     expand("x=$files echo {1..3} $files \"$files\" $((2 * 3))", &context).unwrap(),
+///     vec!["x=a.txt b.txt", "echo", "1", "2", "3", "a.txt", "b.txt", "a.txt b.txt", "6"]
+/// );
+/// assert!(expand("echo $(( 7 / 0 ))", &context).is_err());
+/// ```
+pub fn expand(input: &str, context: &Context) -> Result<Vec<String>, ExpansionError> {
+    let mut words = Vec::new();
+    let mut assignments = true;
+    for word in split_words(input) {
+        if assignments && parse_assignment(&word).is_some() {
+            words.push(remove_quotes(&expand_parameters(&word, context)?));
+            continue;
+        }
+        assignments = false;
+        words.extend(expand_word(&word, context)?);
+    }
+    Ok(words)
+}
+
+/// Expands one word of a command line, as written, into the words it
+/// stands for, with every stage of [`expand`].
+pub fn expand_word(word: &str, context: &Context) -> Result<Vec<String>, ExpansionError> {
+    let ifs = context
+        .variables
+        .get("IFS")
+        .unwrap_or_else(|| DEFAULT_IFS.to_string());
+    let mut words = Vec::new();
+    for word in expand_braces(word) {
+        let word = expand_tilde(&word, context.variables);
+        for field in split_fields(expand_parameters(&word, context)?, &ifs) {
+            words.extend(expand_pathname(&field, context.cwd));
+        }
+    }
+    Ok(words)
+}
+
+/// Splits a command line into words at unquoted whitespace, leaving the
+/// words as written: quotes, backslashes and expansions such as `$(...)`,
+/// which may contain whitespace, are kept for the later stages.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::expand::split_words;
+/// assert_eq!(
+///     split_words("echo 'a b' \"$(echo c d)\"x  y\\ z"),
+///     vec!["echo", "'a b'", "\"$(echo c d)\"x", "y\\ z"]
+/// );
+/// ```
+pub fn split_words(input: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+                continue;
+            }
+            '\\' => {
+                word.push(c);
+                word.extend(chars.next());
+            }
+            '\'' => {
+                word.push(c);
+                for c in chars.by_ref() {
+                    word.push(c);
+                    if c == '\'' {
+                        break;
+                    }
+                }
+            }
+            '"' => {
+                word.push(c);
+                copy_double_quoted(&mut chars, &mut word);
+            }
+            '$' if matches!(chars.peek(), Some('(' | '{')) => {
+                word.push(c);
+                copy_nested(&mut chars, &mut word);
+            }
+            c => word.push(c),
+        }
+        in_word = true;
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// Copies the rest of a double-quoted string, up to and including its
+/// closing quote.
+fn copy_double_quoted(chars: &mut Peekable<Chars>, word: &mut String) {
+    while let Some(c) = chars.next() {
+        word.push(c);
+        match c {
+            '"' => return,
+            '\\' => word.extend(chars.next()),
+            '$' if matches!(chars.peek(), Some('(' | '{')) => copy_nested(chars, word),
+            _ => {}
+        }
+    }
+}
+
+/// Copies a bracketed expansion starting at its opening `(` or `{`, up to
+/// and including the bracket that closes it.
+fn copy_nested(chars: &mut Peekable<Chars>, word: &mut String) {
+    let Some(open) = chars.next() else {
+        return;
+    };
+    let close = if open == '(' { ')' } else { '}' };
+    word.push(open);
+    let mut depth = 1;
+    while let Some(c) = chars.next() {
+        word.push(c);
+        match c {
+            '\\' => word.extend(chars.next()),
+            '\'' => {
+                for c in chars.by_ref() {
+                    word.push(c);
+                    if c == '\'' {
+                        break;
+                    }
+                }
+            }
+            '"' => copy_double_quoted(chars, word),
+            c if c == open => depth += 1,
+            c if c == close => {
+                depth -= 1;
+                if depth == 0 {
+                    return;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Brace expansion: a word with an unquoted `{a,b}` becomes one word for
+/// each alternative, and `{1..3}` or `{a..c}` one for each number or
+/// letter of the sequence. Braces that are neither are left as they are.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::expand::expand_braces;
+/// assert_eq!(expand_braces("f{a,b{1..2}}.txt"), vec!["fa.txt", "fb1.txt", "fb2.txt"]);
+/// assert_eq!(expand_braces("'{a,b}' ${x} {}"), vec!["'{a,b}' ${x} {}"]);
+/// ```
+pub fn expand_braces(word: &str) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '\'' | '"' => i = skip_quoted(&chars, i),
+            // `${name}` and `$(...)` aren't braces to expand
+            '$' if matches!(chars.get(i + 1), Some('{' | '(')) => {
+                i = matching(&chars, i + 1).unwrap_or(chars.len());
+            }
+            '{' => {
+                if let Some(close) = matching(&chars, i)
+                    && let Some(items) = brace_items(&chars[i + 1..close])
+                {
+                    let prefix: String = chars[..i].iter().collect();
+                    let suffix: String = chars[close + 1..].iter().collect();
+                    return items
+                        .iter()
+                        .flat_map(|item| expand_braces(&format!("{}{}{}", prefix, item, suffix)))
+                        .collect();
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    vec![word.to_string()]
+}
+
+/// Returns the index of the quote that closes the one at `start`.
+fn skip_quoted(chars: &[char], start: usize) -> usize {
+    let quote = chars[start];
+    let mut i = start + 1;
+    while i < chars.len() && chars[i] != quote {
+        if quote == '"' && chars[i] == '\\' {
+            i += 1;
+        }
+        i += 1;
+    }
+    i
+}
+
+/// Returns the index of the bracket that closes the one at `start`,
+/// skipping quoted text.
+fn matching(chars: &[char], start: usize) -> Option<usize> {
+    let open = chars[start];
+    let close = if open == '(' { ')' } else { '}' };
+    let mut depth = 0;
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '\'' | '"' => i = skip_quoted(chars, i),
+            c if c == open => depth += 1,
+            c if c == close => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Returns the words a brace expression's contents stand for: its
+/// comma-separated alternatives, or the sequence it describes.
+fn brace_items(body: &[char]) -> Option<Vec<String>> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut depth = 0;
+    let mut i = 0;
+    while i < body.len() {
+        match body[i] {
+            '\\' => i += 1,
+            '\'' | '"' => i = skip_quoted(body, i),
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(body[start..i].iter().collect());
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    if !items.is_empty() {
+        items.push(body[start..].iter().collect());
+        return Some(items);
+    }
+    sequence(&body.iter().collect::<String>())
+}
+
+/// Returns the items of a sequence such as `1..5` or `a..e`, counting
+/// down if the first is greater than the last.
+fn sequence(body: &str) -> Option<Vec<String>> {
+    let (first, last) = body.split_once("..")?;
+    if let (Ok(first), Ok(last)) = (first.parse::<i64>(), last.parse::<i64>()) {
+        let items: Vec<i64> = if first <= last {
+            (first..=last).collect()
+        } else {
+            (last..=first).rev().collect()
+        };
+        return Some(items.iter().map(i64::to_string).collect());
+    }
+    let (mut first, mut last) = (first.chars(), last.chars());
+    match (first.next(), first.next(), last.next(), last.next()) {
+        (Some(first), None, Some(last), None)
+            if first.is_ascii_alphabetic() && last.is_ascii_alphabetic() =>
+        {
+            let items: Vec<char> = if first <= last {
+                (first..=last).collect()
+            } else {
+                (last..=first).rev().collect()
+            };
+            Some(items.iter().map(char::to_string).collect())
+        }
+        _ => None,
+    }
+}
+
+/// Tilde expansion: a word starting with an unquoted `~` or `~user`,
+/// alone or followed by `/`, starts instead with that user's home
+/// directory, quoted so that the later stages leave it as it is.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::expand::expand_tilde;
+/// use codecrafters_shell::variables::Variables;
+///
+/// let mut variables = Variables::default();
+/// variables.set("HOME", "/home/me");
+/// assert_eq!(expand_tilde("~/notes", &variables), "/home/me/notes");
+/// assert_eq!(expand_tilde("'~'/notes", &variables), "'~'/notes");
+/// ```
+pub fn expand_tilde(word: &str, variables: &Variables) -> String {
+    let Some(rest) = word.strip_prefix('~') else {
+        return word.to_string();
+    };
+    let (user, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let home = if user.is_empty() {
+        variables.get("HOME")
+    } else if user
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+    {
+        home_of(user)
+    } else {
+        None
+    };
+    match home {
+        Some(home) => format!("{}{}", quote(&home), path),
+        None => word.to_string(),
+    }
+}
+
+/// Looks up the home directory of `user` in the password database.
+fn home_of(user: &str) -> Option<String> {
+    let name = std::ffi::CString::new(user).ok()?;
+    // SAFETY: the entry is copied out before anything else can replace it
+    unsafe {
+        let entry = libc::getpwnam(name.as_ptr());
+        if entry.is_null() {
+            return None;
+        }
+        let dir = CStr::from_ptr((*entry).pw_dir);
+        Some(dir.to_string_lossy().into_owned())
+    }
+}
+
+/// Parameter, arithmetic and command expansion, which also takes the
+/// quotes apart: returns the pieces of the word, marked with whether they
+/// were quoted and whether they came from an expansion.
+///
+/// Expands `$name`, `${name}`, `$?`, `$#`, the positional parameters `$0`
+/// to `$9` and `${10}` up, the array elements `${name[index]}` (see
+/// [`element`]) and counts `${#name[@]}`, the length `${#name}`,
+/// `$((expression))` (see [`arithmetic`]), and `$(command)` if the context
+/// can run commands. Nothing is expanded inside single quotes. A `$` not
+/// followed by any of those is kept as written, and an arithmetic
+/// expression that can't be evaluated is an error.
+pub fn expand_parameters(word: &str, context: &Context) -> Result<Vec<Segment>, ExpansionError> {
+    let mut segments = Vec::new();
+    let mut chars = word.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    push(&mut segments, &c.to_string(), true, false);
+                }
+            }
+            '\'' => {
+                let text: String = chars.by_ref().take_while(|&c| c != '\'').collect();
+                push(&mut segments, &text, true, false);
+            }
+            '"' => {
+                // An empty pair of quotes still makes a word
+                push(&mut segments, "", true, false);
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' if matches!(chars.peek(), Some('\\' | '$' | '"' | '\n')) => {
+                            let escaped = chars.next().unwrap_or(c);
+                            push(&mut segments, &escaped.to_string(), true, false);
+                        }
+                        '$' => expand_dollar(&mut chars, context, &mut segments, true)?,
+                        c => push(&mut segments, &c.to_string(), true, false),
+                    }
+                }
+            }
+            '$' => expand_dollar(&mut chars, context, &mut segments, false)?,
+            c => push(&mut segments, &c.to_string(), false, false),
+        }
+    }
+    Ok(segments)
+}
+
+/// Adds text to the segments, extending the last one if it is the same
+/// kind.
+fn push(segments: &mut Vec<Segment>, text: &str, quoted: bool, expanded: bool) {
+    match segments.last_mut() {
+        Some(last) if last.quoted == quoted && last.expanded == expanded => {
+            last.text.push_str(text)
+        }
+        _ => segments.push(Segment {
+            text: text.to_string(),
+            quoted,
+            expanded,
+        }),
+    }
+}
+
+/// Expands what follows a `$`, adding the result to the segments.
+fn expand_dollar(
+    chars: &mut Peekable<Chars>,
+    context: &Context,
+    segments: &mut Vec<Segment>,
+    quoted: bool,
+) -> Result<(), ExpansionError> {
+    let mut lookahead = chars.clone();
+    if lookahead.next_if_eq(&'(').is_some() {
+        let mut inner = String::from("$");
+        copy_nested(chars, &mut inner);
+        let expanded = if let Some(expression) = inner
+            .strip_prefix("$((")
+            .and_then(|rest| rest.strip_suffix("))"))
+        {
+            // Parameters in the expression are expanded first
+            let expression = remove_quotes(&expand_parameters(expression, context)?);
+            Some(arithmetic(&expression, context.variables)?.to_string())
+        } else {
+            let command = &inner[2..inner.len() - 1];
+            context.substitute.map(|run| run(command))
+        };
+        match expanded {
+            Some(value) => push(segments, &value, quoted, true),
+            None => push(segments, &inner, quoted, false),
+        }
+        return Ok(());
+    }
+    match variable(chars, context.variables) {
+        Some(value) => push(segments, &value, quoted, true),
+        None => push(segments, "$", quoted, false),
+    }
+    Ok(())
+}
+
+/// Reads the variable reference after a `$`, `name`, `{name}`, `?`, `#`,
+/// a positional parameter such as `1` or `{10}`, an array element such as
+/// `{name[1]}`, or a length such as `{#name}` or `{#name[@]}`, and returns
+/// its value (empty if unset).
+///
+/// Returns `None` without consuming anything if no name follows, so that
+/// the `$` is taken literally.
+fn variable(chars: &mut Peekable<Chars>, variables: &Variables) -> Option<String> {
+    let mut lookahead = chars.clone();
+    let braced = lookahead.next_if_eq(&'{').is_some();
+    if lookahead.next_if_eq(&'?').is_some() {
+        if braced && lookahead.next_if_eq(&'}').is_none() {
+            return None;
+        }
+        *chars = lookahead;
+        return Some(variables.status().to_string());
+    }
+    if lookahead.next_if_eq(&'#').is_some() {
+        if braced && lookahead.next_if_eq(&'}').is_none() {
+            let (name, subscript) = braced_name(&mut lookahead)?;
+            *chars = lookahead;
+            let length = match subscript.as_deref() {
+                Some("@" | "*") => variables.array(&name).map_or_else(
+                    || usize::from(variables.get(&name).is_some()),
+                    <[String]>::len,
+                ),
+                Some(subscript) => element(variables, &name, subscript).chars().count(),
+                None => variables.get(&name).unwrap_or_default().chars().count(),
+            };
+            return Some(length.to_string());
+        }
+        *chars = lookahead;
+        return Some(variables.argument_count().to_string());
+    }
+    // Positional parameters: one digit, or any number of them in braces
+    if lookahead.peek().is_some_and(char::is_ascii_digit) {
+        let mut digits = String::new();
+        while let Some(c) = lookahead.next_if(char::is_ascii_digit) {
+            digits.push(c);
+            if !braced {
+                break;
+            }
+        }
+        if braced && lookahead.next_if_eq(&'}').is_none() {
+            return None;
+        }
+        *chars = lookahead;
+        let n = digits.parse().unwrap_or(usize::MAX);
+        return Some(variables.argument(n).unwrap_or_default().to_string());
+    }
+    if braced {
+        let (name, subscript) = braced_name(&mut lookahead)?;
+        *chars = lookahead;
+        return Some(match subscript {
+            Some(subscript) => element(variables, &name, &subscript),
+            None => variables.get(&name).unwrap_or_default(),
+        });
+    }
+    let name = read_name(&mut lookahead);
+    if !is_name(&name) {
+        return None;
+    }
+    *chars = lookahead;
+    Some(variables.get(&name).unwrap_or_default())
+}
+
+/// Reads the rest of a braced reference, `name}` or `name[subscript]}`,
+/// returning the name and the subscript if there is one.
+fn braced_name(chars: &mut Peekable<Chars>) -> Option<(String, Option<String>)> {
+    let name = read_name(chars);
+    if !is_name(&name) {
+        return None;
+    }
+    let subscript = match chars.next_if_eq(&'[') {
+        Some(_) => {
+            let subscript: String = chars.by_ref().take_while(|&c| c != ']').collect();
+            Some(subscript)
+        }
+        None => None,
+    };
+    chars.next_if_eq(&'}')?;
+    Some((name, subscript))
+}
+
+/// Reads the letters, digits and underscores of a variable name.
+fn read_name(chars: &mut Peekable<Chars>) -> String {
+    let mut name = String::new();
+    while let Some(c) = chars.next_if(|&c| c.is_ascii_alphanumeric() || c == '_') {
+        name.push(c);
+    }
+    name
+}
+
+/// Returns the element of an array at `subscript`, an arithmetic
+/// expression that counts from the end if it is negative. A subscript of
+/// `@` or `*` gives every element, joined with spaces or with the first
+/// character of `IFS` respectively. A variable that isn't an array is an
+/// array of its value.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::expand::element;
+/// use codecrafters_shell::variables::Variables;
+///
+/// let mut variables = Variables::default();
+/// variables.set_array("list", vec!["a".into(), "b".into(), "c".into()]);
+/// variables.set("i", "1");
+/// assert_eq!(element(&variables, "list", "i + 1"), "c");
+/// assert_eq!(element(&variables, "list", "-3"), "a");
+/// assert_eq!(element(&variables, "list", "3"), "");
+/// assert_eq!(element(&variables, "list", "@"), "a b c");
+/// ```
+pub fn element(variables: &Variables, name: &str, subscript: &str) -> String {
+    let value;
+    let elements = match variables.array(name) {
+        Some(elements) => elements,
+        None => {
+            value = variables.get(name).into_iter().collect::<Vec<_>>();
+            &value
+        }
+    };
+    match subscript {
+        "@" => elements.join(" "),
+        "*" => {
+            let ifs = variables
+                .get("IFS")
+                .unwrap_or_else(|| DEFAULT_IFS.to_string());
+            let separator = ifs.chars().next().map(String::from).unwrap_or_default();
+            elements.join(&separator)
+        }
+        _ => {
+            let index = arithmetic(subscript, variables).unwrap_or(0);
+            let index = if index < 0 {
+                usize::try_from(index + elements.len() as i64).ok()
+            } else {
+                usize::try_from(index).ok()
+            };
+            index
+                .and_then(|index| elements.get(index))
+                .cloned()
+                .unwrap_or_default()
+        }
+    }
+}
+
+/// Evaluates an arithmetic expression of integers, variable names (whose
+/// values count as 0 unless they are integers), `+`, `-`, `*`, `/`, `%`
+/// and parentheses. Returns an error if it isn't valid or divides by
+/// zero.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::expand::{ExpansionError, arithmetic};
+/// use codecrafters_shell::variables::Variables;
+///
+/// let mut variables = Variables::default();
+/// variables.set("n", "7");
+/// assert_eq!(arithmetic("(n + 1) * -2 % 5", &variables), Ok(-1));
+/// assert_eq!(
+///     arithmetic(" 1 / 0 ", &variables),
+///     Err(ExpansionError::DivisionByZero("1 / 0".to_string()))
+/// );
+/// assert!(arithmetic("1 +", &variables).is_err());
+/// ```
+pub fn arithmetic(expression: &str, variables: &Variables) -> Result<i64, ExpansionError> {
+    let mut parser = Arithmetic {
+        chars: expression.chars().peekable(),
+        variables,
+        divided_by_zero: false,
+    };
+    let value = parser.sum();
+    parser.skip_spaces();
+    match value {
+        Some(value) if parser.chars.peek().is_none() => Ok(value),
+        _ if parser.divided_by_zero => Err(ExpansionError::DivisionByZero(
+            expression.trim().to_string(),
+        )),
+        _ => Err(ExpansionError::InvalidExpression(
+            expression.trim().to_string(),
+        )),
+    }
+}
+
+/// A recursive descent parser for [`arithmetic`], evaluating as it goes.
+struct Arithmetic<'a> {
+    chars: Peekable<Chars<'a>>,
+    variables: &'a Variables,
+    /// Set when evaluation stopped at a division by zero.
+    divided_by_zero: bool,
+}
+
+impl Arithmetic<'_> {
+    fn skip_spaces(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    /// Terms joined by `+` and `-`.
+    fn sum(&mut self) -> Option<i64> {
+        let mut value = self.product()?;
+        loop {
+            self.skip_spaces();
+            match self.chars.peek() {
+                Some('+') => {
+                    self.chars.next();
+                    value = value.wrapping_add(self.product()?);
+                }
+                Some('-') => {
+                    self.chars.next();
+                    value = value.wrapping_sub(self.product()?);
+                }
+                _ => return Some(value),
+            }
+        }
+    }
+
+    /// Factors joined by `*`, `/` and `%`.
+    fn product(&mut self) -> Option<i64> {
+        let mut value = self.factor()?;
+        loop {
+            self.skip_spaces();
+            let operator = match self.chars.peek() {
+                Some(&c @ ('*' | '/' | '%')) => c,
+                _ => return Some(value),
+            };
+            self.chars.next();
+            let operand = self.factor()?;
+            if operator != '*' && operand == 0 {
+                self.divided_by_zero = true;
+                return None;
+            }
+            value = match operator {
+                '*' => value.wrapping_mul(operand),
+                '/' => value.wrapping_div(operand),
+                _ => value.wrapping_rem(operand),
+            };
+        }
+    }
+
+    /// A number, a variable, a signed factor or a parenthesized sum.
+    fn factor(&mut self) -> Option<i64> {
+        self.skip_spaces();
+        match self.chars.next()? {
+            '-' => Some(self.factor()?.wrapping_neg()),
+            '+' => self.factor(),
+            '(' => {
+                let value = self.sum()?;
+                self.skip_spaces();
+                self.chars.next_if_eq(&')').map(|_| value)
+            }
+            c if c.is_ascii_digit() => {
+                let mut digits = c.to_string();
+                while let Some(c) = self.chars.next_if(char::is_ascii_digit) {
+                    digits.push(c);
+                }
+                digits.parse().ok()
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut name = c.to_string();
+                while let Some(c) = self
+                    .chars
+                    .next_if(|&c| c.is_ascii_alphanumeric() || c == '_')
+                {
+                    name.push(c);
+                }
+                let value = self.variables.get(&name).unwrap_or_default();
+                Some(value.trim().parse().unwrap_or(0))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Field splitting: splits the unquoted results of expansions at the
+/// characters of `ifs`, a run of them separating two fields. A field left
+/// with nothing in it, not even quotes, is dropped.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::expand::{Context, expand_parameters, remove_quotes, split_fields};
+/// use codecrafters_shell::variables::Variables;
+///
+/// let mut variables = Variables::default();
+/// variables.set("list", " a  b ");
+/// let segments = expand_parameters("x$list\"$list\"", &Context::new(&variables)).unwrap();
+/// let fields: Vec<String> = split_fields(segments, " ").iter().map(|f| remove_quotes(f)).collect();
+/// assert_eq!(fields, vec!["x", "a", "b", " a  b "]);
+/// ```
+pub fn split_fields(segments: Vec<Segment>, ifs: &str) -> Vec<Vec<Segment>> {
+    let mut fields = Vec::new();
+    let mut field: Vec<Segment> = Vec::new();
+    for segment in segments {
+        if segment.quoted || !segment.expanded {
+            field.push(segment);
+            continue;
+        }
+        for c in segment.text.chars() {
+            if ifs.contains(c) {
+                if field.iter().any(|s| s.quoted || !s.text.is_empty()) {
+                    fields.push(std::mem::take(&mut field));
+                }
+                field.clear();
+            } else {
+                push(&mut field, &c.to_string(), false, true);
+            }
+        }
+    }
+    if field.iter().any(|s| s.quoted || !s.text.is_empty()) {
+        fields.push(field);
+    }
+    fields
+}
+
+/// Pathname expansion: a field with unquoted `*`, `?` or `[...]` becomes
+/// the sorted names of the files it matches. A pattern that matches
+/// nothing is kept as it is, and `*` and `?` only match a leading `.` if
+/// the pattern has one. Relative patterns are matched in `cwd`.
+pub fn expand_pathname(field: &[Segment], cwd: &Path) -> Vec<String> {
+    let is_pattern = field
+        .iter()
+        .any(|s| !s.quoted && s.text.contains(['*', '?', '[']));
+    if is_pattern {
+        // Quoted characters match only themselves
+        let mut pattern = Vec::new();
+        for segment in field {
+            for c in segment.text.chars() {
+                let escaped = segment.quoted || c == '\\';
+                pattern.push(PatternChar { c, escaped });
+            }
+        }
+        let mut paths = glob(&pattern, cwd);
+        if !paths.is_empty() {
+            crate::collate::sort(&mut paths);
+            return paths;
+        }
+    }
+    vec![remove_quotes(field)]
+}
+
+/// A character of a pathname pattern; escaped characters aren't
+/// wildcards.
+#[derive(Clone, Copy)]
+struct PatternChar {
+    c: char,
+    escaped: bool,
+}
+
+/// Returns the paths matching a pattern, component by component, relative
+/// to `cwd` unless the pattern is absolute.
+fn glob(pattern: &[PatternChar], cwd: &Path) -> Vec<String> {
+    let components: Vec<&[PatternChar]> = pattern.split(|p| p.c == '/' && !p.escaped).collect();
+    let absolute = components.first().is_some_and(|c| c.is_empty());
+    let mut paths = vec![if absolute {
+        "/".to_string()
+    } else {
+        String::new()
+    }];
+    for (i, component) in components.iter().enumerate() {
+        if component.is_empty() {
+            continue;
+        }
+        let last = i == components.len() - 1;
+        let mut next = Vec::new();
+        for path in &paths {
+            let dir = cwd.join(path);
+            if !component.iter().any(|p| !p.escaped && "*?[".contains(p.c)) {
+                let name: String = component.iter().map(|p| p.c).collect();
+                let joined = join(path, &name);
+                if cwd.join(&joined).exists() {
+                    next.push(joined);
+                }
+                continue;
+            }
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                    continue;
+                };
+                let name_chars: Vec<char> = name.chars().collect();
+                if name.starts_with('.') && component[0].c != '.' {
+                    continue;
+                }
+                if matches(component, &name_chars) && (last || entry.path().is_dir()) {
+                    next.push(join(path, &name));
+                }
+            }
+        }
+        paths = next;
+    }
+    paths.retain(|path| !path.is_empty() && path != "/");
+    paths
+}
+
+/// Appends a name to a path built by [`glob`].
+fn join(path: &str, name: &str) -> String {
+    match path {
+        "" => name.to_string(),
+        "/" => format!("/{}", name),
+        _ => format!("{}/{}", path, name),
+    }
+}
+
+/// Checks whether a name matches a pattern component.
+fn matches(pattern: &[PatternChar], name: &[char]) -> bool {
+    let Some((first, rest)) = pattern.split_first() else {
+        return name.is_empty();
+    };
+    if first.escaped {
+        return name.first() == Some(&first.c) && matches(rest, &name[1..]);
+    }
+    match first.c {
+        '*' => (0..=name.len()).any(|skip| matches(rest, &name[skip..])),
+        '?' => !name.is_empty() && matches(rest, &name[1..]),
+        '[' => match (name.first(), bracket(rest)) {
+            (Some(&c), Some((matcher, after))) => matcher(c) && matches(&rest[after..], &name[1..]),
+            // A `[` that doesn't start a bracket expression is literal
+            (Some(&'['), None) => matches(rest, &name[1..]),
+            _ => false,
+        },
+        c => name.first() == Some(&c) && matches(rest, &name[1..]),
+    }
+}
+
+/// Parses a bracket expression after its `[`, such as `a-z]` or `!0-9]`,
+/// returning what it matches and the length of the rest of it.
+fn bracket(pattern: &[PatternChar]) -> Option<(impl Fn(char) -> bool, usize)> {
+    let negated = pattern
+        .first()
+        .is_some_and(|p| !p.escaped && (p.c == '!' || p.c == '^'));
+    let start = usize::from(negated);
+    // A `]` right at the start is one of the characters
+    let close = pattern[start..]
+        .iter()
+        .enumerate()
+        .skip(1)
+        .find(|(_, p)| p.c == ']' && !p.escaped)?
+        .0
+        + start;
+    let items: Vec<char> = pattern[start..close].iter().map(|p| p.c).collect();
+    let matcher = move |c: char| {
+        let mut found = false;
+        let mut i = 0;
+        while i < items.len() {
+            if i + 2 < items.len() && items[i + 1] == '-' {
+                found |= (items[i]..=items[i + 2]).contains(&c);
+                i += 3;
+            } else {
+                found |= items[i] == c;
+                i += 1;
+            }
+        }
+        found != negated
+    };
+    Some((matcher, close + 1))
+}
+
+/// Quote removal: joins the pieces of a word back into the word they
+/// make, without the quotes that were taken apart.
+pub fn remove_quotes(field: &[Segment]) -> String {
+    field.iter().map(|segment| segment.text.as_str()).collect()
+}
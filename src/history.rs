@@ -0,0 +1,831 @@
+use crate::dirs;
+use crate::variables::Variables;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Index;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The history file in the shell's state directory, used when `HISTFILE`
+/// is not set.
+pub const HISTORY_FILE: &str = "history";
+
+/// The history file in the home directory from before history moved to
+/// the state directory, still used if it exists.
+pub const DEFAULT_HISTFILE: &str = ".codecrafters_shell_history";
+
+/// Parses the lines of a history file into entries, each with the time it
+/// was run in seconds since the epoch if the file records it.
+///
+/// Besides one command per line, this reads the extended formats of bash
+/// (a `#<timestamp>` line before each command, written with
+/// `HISTTIMEFORMAT` set) and zsh (`: <timestamp>:<duration>;command`), as
+/// well as the special encoding zsh uses for non-ASCII bytes. In either,
+/// the lines of a multi-line command end in `\`. This is how
+/// [`format_entry`] writes them, with bash's timestamps.
+///
+/// ```
+/// use codecrafters_shell::history::parse_history;
+///
+/// let bash = b"#1700000000\nls -l\necho hi\nfor x in a\\\ndo echo $x\\\ndone\n";
+/// assert_eq!(parse_history(bash), [
+///     (Some(1700000000), "ls -l".to_string()),
+///     (None, "echo hi".to_string()),
+///     (None, "for x in a\ndo echo $x\ndone".to_string()),
+/// ]);
+/// let zsh = b": 1700000000:0;git status\n: 1700000005:2;echo a\\\nb\n";
+/// assert_eq!(parse_history(zsh), [
+///     (Some(1700000000), "git status".to_string()),
+///     (Some(1700000005), "echo a\nb".to_string()),
+/// ]);
+/// ```
+pub fn parse_history(contents: &[u8]) -> Vec<(Option<u64>, String)> {
+    let contents = match std::str::from_utf8(contents) {
+        Ok(text) => text.to_string(),
+        Err(_) => String::from_utf8_lossy(&unmetafy(contents)).into_owned(),
+    };
+
+    let mut entries = Vec::new();
+    let mut time = None;
+    let mut lines = contents.lines();
+    while let Some(line) = lines.next() {
+        // bash: the time of the command on the next line
+        if let Some(stamp) = line.strip_prefix('#')
+            && !stamp.is_empty()
+            && let Ok(stamp) = stamp.parse()
+        {
+            time = Some(stamp);
+            continue;
+        }
+        // zsh: `: start:elapsed;command`
+        if let Some((stamp, command)) = parse_zsh_line(line) {
+            entries.push((Some(stamp), join_lines(command, &mut lines)));
+            continue;
+        }
+        entries.push((time.take(), join_lines(line, &mut lines)));
+    }
+    entries
+}
+
+/// Reads a command that continues on the next of `lines` while its line
+/// ends in `\`, undoing [`format_entry`].
+fn join_lines<'a>(first: &str, lines: &mut impl Iterator<Item = &'a str>) -> String {
+    let mut command = first.to_string();
+    while command.ends_with('\\') {
+        let Some(next) = lines.next() else {
+            break;
+        };
+        command.pop();
+        command.push('\n');
+        command.push_str(next);
+    }
+    // The space written after a command ending in `\`
+    if command.ends_with("\\ ") {
+        command.pop();
+    }
+    command
+}
+
+/// Formats a command for the history file, as a line ending in a newline.
+/// The time it was run, if known, goes on a `#<timestamp>` line before it,
+/// as bash writes it.
+///
+/// A command of several lines is written with a `\` before each newline,
+/// as zsh writes them, so that it reads back as one entry. A command ending
+/// in `\` has a space added, since recorded commands never end in one, so
+/// that its last line doesn't read as continued.
+///
+/// ```
+/// use codecrafters_shell::history::{format_entry, parse_history};
+///
+/// let command = "for x in a b\ndo echo \\\ndone";
+/// let line = format_entry(command, Some(1700000000));
+/// assert_eq!(line, "#1700000000\nfor x in a b\\\ndo echo \\\\\ndone\n");
+/// assert_eq!(parse_history(line.as_bytes()), [(Some(1700000000), command.to_string())]);
+/// let line = format_entry("echo \\", None);
+/// assert_eq!(parse_history(line.as_bytes()), [(None, "echo \\".to_string())]);
+/// ```
+pub fn format_entry(command: &str, time: Option<u64>) -> String {
+    let mut line = match time {
+        Some(time) => format!("#{}\n", time),
+        None => String::new(),
+    };
+    line.push_str(&command.replace('\n', "\\\n"));
+    if line.ends_with('\\') {
+        line.push(' ');
+    }
+    line.push('\n');
+    line
+}
+
+/// Splits a line of zsh's extended history into its start time and
+/// command.
+fn parse_zsh_line(line: &str) -> Option<(u64, &str)> {
+    let (header, command) = line.strip_prefix(": ")?.split_once(';')?;
+    let (stamp, elapsed) = header.split_once(':')?;
+    if !elapsed.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((stamp.parse().ok()?, command))
+}
+
+/// Undoes zsh's "metafied" encoding, where some bytes of non-ASCII text
+/// are written as 0x83 followed by the byte XOR 32.
+fn unmetafy(bytes: &[u8]) -> Vec<u8> {
+    let mut plain = Vec::with_capacity(bytes.len());
+    let mut bytes = bytes.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            0x83 => plain.extend(bytes.next().map(|next| next ^ 32)),
+            _ => plain.push(byte),
+        }
+    }
+    plain
+}
+
+/// Quotes a string for JSON, escaping quotes, backslashes and control
+/// characters.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A history file opened for appending, with an exclusive lock held until
+/// it is dropped.
+struct LockedFile(File);
+
+impl LockedFile {
+    fn open(path: &Path) -> io::Result<LockedFile> {
+        // The state directory may not exist yet
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        // Retry if a signal such as SIGWINCH interrupts the wait
+        while unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == -1 {
+            let error = io::Error::last_os_error();
+            if error.kind() != io::ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+        Ok(LockedFile(file))
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.0.metadata()?.len())
+    }
+
+    fn write_entries(&mut self, lines: &str) -> io::Result<()> {
+        // One write, so a reader never sees part of an entry
+        self.0.write_all(lines.as_bytes())
+    }
+}
+
+/// Expands history references in a command line, as bash does before
+/// running it.
+///
+/// An event selects an entry: `!!` the previous one, `!n` entry `n`, `!-n`
+/// the `n`th last, `!string` the last starting with `string` and
+/// `!?string?` the last containing it. A word designator after `:` then
+/// picks words of it, counting the command as word 0: `n`, `^` (the first
+/// argument), `$` (the last), `x-y`, `x-` (up to the second last), `-y`,
+/// `*` (all arguments) and `x*`. The `:` may be left out before `^`, `$`
+/// and `*`, and `!$`, `!^`, `!*` and `!:n` refer to the previous entry.
+///
+/// A `!` before a space, `=`, `(` or the end of the line is kept, as is
+/// one escaped with a backslash or inside single quotes. Returns a
+/// bash-style error message for an event that doesn't exist or words it
+/// doesn't have.
+///
+/// ```
+/// use codecrafters_shell::history::{History, expand_history};
+///
+/// let mut history = History::default();
+/// history.push("mkdir -p foo".to_string());
+/// assert_eq!(expand_history("cd !$", &history).unwrap(), "cd foo");
+/// assert_eq!(expand_history("echo !!:0 !*", &history).unwrap(), "echo mkdir -p foo");
+/// assert_eq!(expand_history("echo '!!' !", &history).unwrap(), "echo '!!' !");
+/// assert!(expand_history("!nope", &history).is_err());
+/// ```
+pub fn expand_history(line: &str, history: &History) -> Result<String, String> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut expanded = String::new();
+    let mut quote = None;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            (Some('\''), _) => {}
+            (_, '\\') => {
+                expanded.extend(&chars[i..(i + 2).min(chars.len())]);
+                i += 2;
+                continue;
+            }
+            (_, '!') => {
+                let literal = match chars.get(i + 1) {
+                    None => true,
+                    Some(&next) => next.is_whitespace() || matches!(next, '=' | '(' | '"'),
+                };
+                if !literal {
+                    let (words, end) = expand_reference(&chars, i, history)?;
+                    expanded.push_str(&words);
+                    i = end;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        expanded.push(c);
+        i += 1;
+    }
+    Ok(expanded)
+}
+
+/// Expands the history reference starting with the `!` at `start`,
+/// returning its text and the index just past it.
+fn expand_reference(
+    chars: &[char],
+    start: usize,
+    history: &History,
+) -> Result<(String, usize), String> {
+    let last = history.len().checked_sub(1);
+    let mut j = start + 1;
+    let index = match chars[j] {
+        '!' => {
+            j += 1;
+            last
+        }
+        // Word designators alone refer to the previous entry
+        '$' | '^' | '*' | ':' => last,
+        '?' => {
+            let end = chars[j + 1..]
+                .iter()
+                .position(|&c| c == '?')
+                .map_or(chars.len(), |offset| j + 1 + offset);
+            let needle: String = chars[j + 1..end].iter().collect();
+            j = (end + 1).min(chars.len());
+            history.iter().rposition(|entry| entry.contains(&needle))
+        }
+        c if c.is_ascii_digit()
+            || (c == '-' && chars.get(j + 1).is_some_and(char::is_ascii_digit)) =>
+        {
+            let negative = c == '-';
+            if negative {
+                j += 1;
+            }
+            let digits_end = chars[j..]
+                .iter()
+                .position(|c| !c.is_ascii_digit())
+                .map_or(chars.len(), |offset| j + offset);
+            let number: usize = chars[j..digits_end]
+                .iter()
+                .collect::<String>()
+                .parse()
+                .unwrap_or(usize::MAX);
+            j = digits_end;
+            if negative {
+                history.len().checked_sub(number)
+            } else {
+                history.index_of(number)
+            }
+        }
+        _ => {
+            let end = chars[j..]
+                .iter()
+                .position(|&c| c.is_whitespace() || matches!(c, ':' | '\'' | '"' | ';' | '&' | '|'))
+                .map_or(chars.len(), |offset| j + offset);
+            let prefix: String = chars[j..end].iter().collect();
+            j = end;
+            history.iter().rposition(|entry| entry.starts_with(&prefix))
+        }
+    };
+    let event: String = chars[start..j].iter().collect();
+    let Some(entry) = index.and_then(|index| history.get(index)) else {
+        return Err(format!("{}: event not found", event));
+    };
+
+    // The `:` may be left out before `^`, `$` and `*`
+    let designator_start = j;
+    let has_designator = match chars.get(j) {
+        Some(':')
+            if chars
+                .get(j + 1)
+                .is_some_and(|&c| c.is_ascii_digit() || matches!(c, '^' | '$' | '*' | '-')) =>
+        {
+            j += 1;
+            true
+        }
+        Some('^' | '$' | '*') => true,
+        _ => false,
+    };
+    if !has_designator {
+        return Ok((entry.to_string(), j));
+    }
+
+    let words = split_words(entry);
+    let count = words.len();
+    let (range, end) = parse_designator(chars, j, count);
+    let specifier: String = chars[designator_start..end].iter().collect();
+    match range {
+        Some((first, last)) if last < count && first <= last + 1 => {
+            Ok((words[first..=last].join(" "), end))
+        }
+        // `*` and `x*` may select nothing, such as `!*` after a command
+        // without arguments
+        None => Ok((String::new(), end)),
+        _ => Err(format!("{}: bad word specifier", specifier)),
+    }
+}
+
+/// Parses the word designator at `j` for an entry of `count` words,
+/// returning the first and last word it selects and the index just past
+/// it. The range is `None` if it is empty, and may be out of bounds.
+fn parse_designator(chars: &[char], mut j: usize, count: usize) -> (Option<(usize, usize)>, usize) {
+    let last = count.saturating_sub(1);
+    let number = |j: &mut usize| -> Option<usize> {
+        let end = chars[*j..]
+            .iter()
+            .position(|c| !c.is_ascii_digit())
+            .map_or(chars.len(), |offset| *j + offset);
+        let digits: String = chars[*j..end].iter().collect();
+        *j = end;
+        digits.parse().ok()
+    };
+    let first = match chars.get(j) {
+        Some('^') => {
+            j += 1;
+            1
+        }
+        Some('$') => return (Some((last, last)), j + 1),
+        Some('*') => return (if count > 1 { Some((1, last)) } else { None }, j + 1),
+        Some('-') => 0,
+        _ => number(&mut j).unwrap_or(usize::MAX),
+    };
+    match chars.get(j) {
+        Some('*') => {
+            let range = if first < count {
+                Some((first, last))
+            } else if first == count {
+                None
+            } else {
+                Some((first, first))
+            };
+            (range, j + 1)
+        }
+        Some('-') => {
+            j += 1;
+            let end = match chars.get(j) {
+                Some('$') => {
+                    j += 1;
+                    last
+                }
+                Some(c) if c.is_ascii_digit() => number(&mut j).unwrap_or(usize::MAX),
+                // `x-` stops before the last word
+                _ => {
+                    if count < 2 || first > count - 2 {
+                        return (Some((first, usize::MAX)), j);
+                    }
+                    count - 2
+                }
+            };
+            (Some((first, end)), j)
+        }
+        _ => (Some((first, first)), j),
+    }
+}
+
+/// Splits a history entry into words at unquoted whitespace, keeping
+/// quotes and backslashes as written.
+fn split_words(entry: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quote = None;
+    let mut chars = entry.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, c) if c.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                continue;
+            }
+            (Some(open), c) if c == open => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            (q, '\\') if q != Some('\'') => {
+                word.push(c);
+                word.extend(chars.next());
+                continue;
+            }
+            _ => {}
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Which command lines are left out of the history, from the
+/// colon-separated `HISTCONTROL` variable as in bash.
+///
+/// ```
+/// use codecrafters_shell::history::HistControl;
+///
+/// let control = HistControl::parse("ignoreboth");
+/// assert!(control.ignore_dups && control.ignore_space);
+/// assert_eq!(HistControl::parse("ignorespace:other"), HistControl {
+///     ignore_dups: false,
+///     ignore_space: true,
+/// });
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HistControl {
+    /// Skip a line identical to the previous entry (`ignoredups`).
+    pub ignore_dups: bool,
+    /// Skip lines starting with a space (`ignorespace`).
+    pub ignore_space: bool,
+}
+
+impl HistControl {
+    /// Parses a `HISTCONTROL` value. `ignoreboth` sets both options and
+    /// unknown names are ignored.
+    pub fn parse(value: &str) -> Self {
+        let mut control = HistControl::default();
+        for name in value.split(':') {
+            match name {
+                "ignoredups" => control.ignore_dups = true,
+                "ignorespace" => control.ignore_space = true,
+                "ignoreboth" => {
+                    control.ignore_dups = true;
+                    control.ignore_space = true;
+                }
+                _ => {}
+            }
+        }
+        control
+    }
+
+    /// Reads the `HISTCONTROL` variable; unset means record everything.
+    pub fn from_variables(variables: &Variables) -> Self {
+        variables
+            .get("HISTCONTROL")
+            .map(|value| HistControl::parse(&value))
+            .unwrap_or_default()
+    }
+}
+
+/// How many entries the history keeps when `HISTSIZE` is not set.
+pub const DEFAULT_HISTSIZE: usize = 1000;
+
+/// One command line in the history.
+#[derive(Debug, Clone)]
+struct Entry {
+    command: String,
+    /// When it was run, in seconds since the epoch, if known.
+    time: Option<u64>,
+}
+
+/// The command lines entered in this and earlier sessions, oldest first.
+///
+/// Entries are kept in a ring buffer of at most `HISTSIZE` entries,
+/// dropping the oldest as new ones come in. Indexes count from the oldest
+/// entry kept; [`History::number`] gives the number `history` shows,
+/// which keeps counting from the first entry ever added.
+///
+/// Clones share their entries until one of them changes, so a subshell
+/// gets the history without copying it.
+#[derive(Debug, Default, Clone)]
+pub struct History {
+    entries: Arc<VecDeque<Entry>>,
+    /// How many entries have been dropped to stay within `HISTSIZE`.
+    dropped: usize,
+    /// How many entries are already in the history file, so `history -a`
+    /// appends only the rest.
+    saved: usize,
+    /// How far into the history file this shell has read or written, so
+    /// that [`History::sync`] picks up only what other shells added since.
+    read_to: u64,
+    /// How many entries are kept, as last set from `HISTSIZE`, or `None`
+    /// for [`DEFAULT_HISTSIZE`].
+    limit: Option<usize>,
+    /// Whether the history file gets the time of each entry, as last set
+    /// from `HISTTIMEFORMAT`.
+    timestamps: bool,
+}
+
+impl Index<usize> for History {
+    type Output = str;
+
+    fn index(&self, index: usize) -> &str {
+        &self.entries[index].command
+    }
+}
+
+impl History {
+    /// Returns the history file: the `HISTFILE` variable if it is set,
+    /// otherwise [`History::default_file`]. Returns `None` if `HISTFILE` is
+    /// empty, which turns saving off, or there is no home directory.
+    pub fn file(variables: &Variables) -> Option<PathBuf> {
+        match variables.get("HISTFILE") {
+            Some(path) if path.is_empty() => None,
+            Some(path) => Some(PathBuf::from(path)),
+            None => Self::default_file(),
+        }
+    }
+
+    /// Returns the history file used when `HISTFILE` is not set:
+    /// [`HISTORY_FILE`] in the state directory, or the older
+    /// [`DEFAULT_HISTFILE`] in the home directory if only that exists.
+    pub fn default_file() -> Option<PathBuf> {
+        dirs::resolve(
+            dirs::state_dir().map(|dir| dir.join(HISTORY_FILE)),
+            dirs::home_file(DEFAULT_HISTFILE),
+        )
+    }
+
+    /// Returns how many entries the history keeps: `HISTSIZE`, or
+    /// [`DEFAULT_HISTSIZE`] if it is not a number. A negative `HISTSIZE`
+    /// means no limit, as in bash.
+    pub fn limit(variables: &Variables) -> usize {
+        match variables
+            .get("HISTSIZE")
+            .map(|size| size.trim().parse::<i64>())
+        {
+            Some(Ok(size)) if size < 0 => usize::MAX,
+            Some(Ok(size)) => usize::try_from(size).unwrap_or(usize::MAX),
+            _ => DEFAULT_HISTSIZE,
+        }
+    }
+
+    /// Keeps at most `limit` entries from now on, dropping the oldest
+    /// beyond it straight away.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = Some(limit);
+        self.enforce_limit();
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Checks whether there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the entry at `index`, counting from the oldest.
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(|entry| entry.command.as_str())
+    }
+
+    /// Returns the entries, oldest first.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &str> + ExactSizeIterator {
+        self.entries.iter().map(|entry| entry.command.as_str())
+    }
+
+    /// Returns the number `history` shows for the entry at `index`.
+    pub fn number(&self, index: usize) -> usize {
+        self.dropped + index + 1
+    }
+
+    /// Returns the index of the entry `history` shows as `number`, if it
+    /// is still kept.
+    pub fn index_of(&self, number: usize) -> Option<usize> {
+        let index = number.checked_sub(self.dropped + 1)?;
+        (index < self.entries.len()).then_some(index)
+    }
+
+    /// Returns whether entries go in the history file with the time they
+    /// were run: only when `HISTTIMEFORMAT` is set, as in bash, so that
+    /// otherwise the file has just one command per line.
+    pub fn timestamps(variables: &Variables) -> bool {
+        variables.get("HISTTIMEFORMAT").is_some()
+    }
+
+    /// Sets whether entries are written with the time they were run, see
+    /// [`History::timestamps`].
+    pub fn set_timestamps(&mut self, timestamps: bool) {
+        self.timestamps = timestamps;
+    }
+
+    /// Appends the entries of a history file, as [`parse_history`] reads
+    /// them, counting them as saved.
+    pub fn load(&mut self, path: &Path) -> io::Result<()> {
+        let contents = fs::read(path)?;
+        self.read_to += contents.len() as u64;
+        let entries = Arc::make_mut(&mut self.entries);
+        for (time, command) in parse_history(&contents) {
+            entries.push_back(Entry { command, time });
+        }
+        self.saved = self.entries.len();
+        self.enforce_limit();
+        Ok(())
+    }
+
+    /// Writes every entry to a history file, replacing its contents.
+    pub fn save(&mut self, path: &Path) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(self.formatted(0).as_bytes())?;
+        self.saved = self.entries.len();
+        Ok(())
+    }
+
+    /// Appends the entries not yet in a history file to it, creating the
+    /// file if needed.
+    ///
+    /// The file is locked while writing, so lines from several shells
+    /// sharing it don't interleave. Lines other shells appended meanwhile
+    /// are skipped rather than read; [`History::sync`] reads them.
+    pub fn append(&mut self, path: &Path) -> io::Result<()> {
+        if self.saved >= self.entries.len() {
+            return Ok(());
+        }
+        let mut file = LockedFile::open(path)?;
+        file.write_entries(&self.formatted(self.saved))?;
+        self.saved = self.entries.len();
+        self.read_to = file.len()?;
+        Ok(())
+    }
+
+    /// Shares history with other shells using the same file: reads the
+    /// lines they appended since this shell last read or wrote it, then
+    /// appends the entries this shell hasn't saved yet.
+    ///
+    /// Lines read from the file go before this shell's unsaved entries,
+    /// keeping the order of the file.
+    pub fn sync(&mut self, path: &Path) -> io::Result<()> {
+        let mut file = LockedFile::open(path)?;
+        let len = file.len()?;
+        // A file that shrank was rewritten, e.g. by `history -w`; its
+        // lines can't be told apart from those already read
+        if len < self.read_to {
+            self.read_to = len;
+        } else if len > self.read_to {
+            let mut added = String::new();
+            file.0.seek(SeekFrom::Start(self.read_to))?;
+            (&file.0)
+                .take(len - self.read_to)
+                .read_to_string(&mut added)?;
+            // A line still being written is left for next time
+            let complete = added.rfind('\n').map_or(0, |end| end + 1);
+            self.read_to += complete as u64;
+            for (time, command) in parse_history(&added.as_bytes()[..complete]) {
+                let entry = Entry { command, time };
+                Arc::make_mut(&mut self.entries).insert(self.saved, entry);
+                self.saved += 1;
+            }
+        }
+        if self.saved < self.entries.len() {
+            file.write_entries(&self.formatted(self.saved))?;
+            self.saved = self.entries.len();
+            self.read_to = file.len()?;
+        }
+        self.enforce_limit();
+        Ok(())
+    }
+
+    /// Adds a command line as typed to the end of the history, trimmed,
+    /// unless `control` leaves it out. Returns whether it was added.
+    pub fn record(&mut self, line: &str, control: HistControl) -> bool {
+        if control.ignore_space && line.starts_with(' ') {
+            return false;
+        }
+        let line = line.trim();
+        if control.ignore_dups && self.iter().next_back() == Some(line) {
+            return false;
+        }
+        self.push(line.to_string());
+        true
+    }
+
+    /// Removes every entry, as `history -c` does.
+    pub fn clear(&mut self) {
+        self.entries = Arc::default();
+        self.dropped = 0;
+        self.saved = 0;
+    }
+
+    /// Removes the entry at `index`, returning it, or `None` if there is
+    /// no such entry.
+    pub fn remove(&mut self, index: usize) -> Option<String> {
+        if index >= self.entries.len() {
+            return None;
+        }
+        let entry = Arc::make_mut(&mut self.entries).remove(index)?;
+        if index < self.saved {
+            self.saved -= 1;
+        }
+        Some(entry.command)
+    }
+
+    /// Adds a command line run just now to the end of the history.
+    pub fn push(&mut self, entry: String) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|elapsed| elapsed.as_secs());
+        self.extend([(now, entry)]);
+    }
+
+    /// Adds entries read by `history -r`, with the times they were run if
+    /// known.
+    pub fn extend<I: IntoIterator<Item = (Option<u64>, String)>>(&mut self, entries: I) {
+        let entries = entries
+            .into_iter()
+            .map(|(time, command)| Entry { command, time });
+        Arc::make_mut(&mut self.entries).extend(entries);
+        self.enforce_limit();
+    }
+
+    /// Returns when the entry at `index` was run, in seconds since the
+    /// epoch, if known.
+    pub fn time(&self, index: usize) -> Option<u64> {
+        self.entries.get(index).and_then(|entry| entry.time)
+    }
+
+    /// Returns the history as a JSON array with an object per entry,
+    /// giving its `index` (as `history` numbers it), `timestamp` (or
+    /// `null`) and `command`, for `history --json`.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("[");
+        for (i, entry) in self.entries.iter().enumerate() {
+            let timestamp = entry
+                .time
+                .map_or_else(|| "null".to_string(), |time| time.to_string());
+            json.push_str(if i == 0 { "\n" } else { ",\n" });
+            json.push_str(&format!(
+                "  {{\"index\": {}, \"timestamp\": {}, \"command\": {}}}",
+                self.number(i),
+                timestamp,
+                json_string(&entry.command)
+            ));
+        }
+        json.push_str(if self.entries.is_empty() { "]" } else { "\n]" });
+        json
+    }
+
+    /// Returns how many entries are already in the history file.
+    pub fn saved(&self) -> usize {
+        self.saved
+    }
+
+    /// Records that the first `count` entries are in the history file.
+    pub fn set_saved(&mut self, count: usize) {
+        self.saved = count;
+    }
+
+    /// Formats the entries from index `start` on as they go in the history
+    /// file, with [`format_entry`]. Their times are included only if set
+    /// with [`History::set_timestamps`].
+    ///
+    /// ```
+    /// use codecrafters_shell::history::History;
+    ///
+    /// let mut history = History::default();
+    /// history.push("echo hello".to_string());
+    /// assert_eq!(history.formatted(0), "echo hello\n");
+    /// history.set_timestamps(true);
+    /// assert!(history.formatted(0).starts_with('#'));
+    /// ```
+    pub fn formatted(&self, start: usize) -> String {
+        self.entries
+            .iter()
+            .skip(start)
+            .map(|entry| format_entry(&entry.command, entry.time.filter(|_| self.timestamps)))
+            .collect()
+    }
+
+    /// Drops the oldest entries beyond the limit.
+    fn enforce_limit(&mut self) {
+        let limit = self.limit.unwrap_or(DEFAULT_HISTSIZE);
+        let excess = self.entries.len().saturating_sub(limit);
+        if excess > 0 {
+            Arc::make_mut(&mut self.entries).drain(..excess);
+            self.dropped += excess;
+            self.saved = self.saved.saturating_sub(excess);
+        }
+    }
+}
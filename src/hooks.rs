@@ -0,0 +1,217 @@
+use crate::shell::Shell;
+use crate::{CommandResult, ShellStatus, execute_pipeline};
+use std::fmt;
+use std::sync::Arc;
+
+/// A callback run by the shell at a fixed point, such as before each
+/// prompt. It may change the shell's options.
+pub type Hook = Arc<dyn Fn(&mut Shell) + Send + Sync>;
+
+/// A callback run with a command line just before it runs.
+pub type PreexecHook = Arc<dyn Fn(&str, &mut Shell) + Send + Sync>;
+
+/// A callback run with a command line and its result (exit status, how
+/// long it took and its PID), once it has finished.
+pub type PostexecHook = Arc<dyn Fn(&str, &CommandResult, &mut Shell) + Send + Sync>;
+
+/// A callback run in place of the "command not found" error, with the name
+/// of the missing command and its arguments.
+pub type CommandNotFoundHook = Arc<dyn Fn(&str, &[String], &mut Shell) + Send + Sync>;
+
+/// Callbacks registered by code embedding the shell. Clones share them
+/// until another is registered.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    precmd: Arc<Vec<Hook>>,
+    preexec: Arc<Vec<PreexecHook>>,
+    postexec: Arc<Vec<PostexecHook>>,
+    command_not_found: Option<CommandNotFoundHook>,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("precmd", &self.precmd.len())
+            .field("preexec", &self.preexec.len())
+            .field("postexec", &self.postexec.len())
+            .field("command_not_found", &self.command_not_found.is_some())
+            .finish()
+    }
+}
+
+impl Hooks {
+    /// Registers a callback to run before each primary prompt is drawn,
+    /// ahead of `PROMPT_COMMAND`. Callbacks run in the order they were
+    /// added.
+    pub fn add_precmd<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut Shell) + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.precmd).push(Arc::new(hook));
+    }
+
+    /// Returns the callbacks run before each prompt.
+    pub fn precmd(&self) -> &[Hook] {
+        &self.precmd
+    }
+
+    /// Registers a callback to run with each command line entered, after
+    /// it is read and before it runs, like zsh's `preexec`.
+    pub fn add_preexec<F>(&mut self, hook: F)
+    where
+        F: Fn(&str, &mut Shell) + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.preexec).push(Arc::new(hook));
+    }
+
+    /// Returns the callbacks run before each command line.
+    pub fn preexec(&self) -> &[PreexecHook] {
+        &self.preexec
+    }
+
+    /// Registers a callback to run with each command line entered and its
+    /// result, once it has finished. For background jobs, that is once the
+    /// job has started.
+    pub fn add_postexec<F>(&mut self, hook: F)
+    where
+        F: Fn(&str, &CommandResult, &mut Shell) + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.postexec).push(Arc::new(hook));
+    }
+
+    /// Returns the callbacks run after each command line.
+    pub fn postexec(&self) -> &[PostexecHook] {
+        &self.postexec
+    }
+
+    /// Sets the callback run when a command isn't a builtin or an
+    /// executable on the `PATH`, like bash's `command_not_found_handle`.
+    /// It replaces the default error message, e.g. to suggest a package
+    /// that provides the command. Setting it again replaces the previous
+    /// one. It takes the place of `command_not_found_handle` too.
+    pub fn set_command_not_found<F>(&mut self, hook: F)
+    where
+        F: Fn(&str, &[String], &mut Shell) + Send + Sync + 'static,
+    {
+        self.command_not_found = Some(Arc::new(hook));
+    }
+
+    /// Returns the callback run when a command isn't found, if one is set.
+    pub fn command_not_found(&self) -> Option<&CommandNotFoundHook> {
+        self.command_not_found.as_ref()
+    }
+}
+
+/// Runs what is due before the primary prompt is drawn: the registered
+/// precmd callbacks, then the command in `PROMPT_COMMAND` if it is set.
+///
+/// Returns the status of `PROMPT_COMMAND`, so that an `exit` there ends
+/// the shell as it does in bash. Like bash, `$?` is left as the status of
+/// the last command entered. `PROMPT_COMMAND` runs even with `set -n`.
+pub fn run_precmd(shell: &mut Shell) -> ShellStatus {
+    // The callbacks are cloned since each may change the options
+    for hook in shell.hooks.precmd().to_vec() {
+        hook(shell);
+    }
+    run_hook_variable("PROMPT_COMMAND", shell)
+}
+
+/// The variable naming the command run in place of the "command not
+/// found" error, after bash's function of that name.
+pub const COMMAND_NOT_FOUND_HANDLE: &str = "command_not_found_handle";
+
+/// Runs the command in `command_not_found_handle`, if it is set, for a
+/// command that wasn't found, with the missing command and its arguments
+/// as `$1` and on. Returns its result, whose status is that of the
+/// command, or `None` if the variable isn't set.
+///
+/// The variable is unset while its command runs, so that a command it
+/// can't find gets the usual error rather than running it again.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::{Shell, execute_pipeline};
+///
+/// let mut shell = Shell::default();
+/// shell.variables.set("command_not_found_handle", "missing=$1");
+/// let result = execute_pipeline("no-such-command --help", &mut shell);
+/// assert_eq!(shell.variables.get("missing").as_deref(), Some("no-such-command"));
+/// assert_eq!(result.status, 0);
+/// ```
+pub fn run_command_not_found(
+    command: &str,
+    args: &[String],
+    shell: &mut Shell,
+) -> Option<CommandResult> {
+    let handler = shell
+        .variables
+        .get(COMMAND_NOT_FOUND_HANDLE)
+        .filter(|handler| !handler.trim().is_empty())?;
+    let name = shell.variables.argument(0).unwrap_or_default().to_string();
+    let arguments = [name, command.to_string()].into_iter().chain(args.to_vec());
+    let saved = shell.variables.set_arguments(arguments.collect());
+    let exported = shell.variables.is_exported(COMMAND_NOT_FOUND_HANDLE);
+    shell.variables.unset(COMMAND_NOT_FOUND_HANDLE);
+    let result = execute_pipeline(&handler, shell);
+    match exported {
+        true => (shell.variables).export(COMMAND_NOT_FOUND_HANDLE, Some(&handler)),
+        false => shell.variables.set(COMMAND_NOT_FOUND_HANDLE, &handler),
+    }
+    shell.variables.set_arguments(saved);
+    Some(result)
+}
+
+/// Runs what is due before a command line runs: the registered preexec
+/// callbacks, then the command in `PREEXEC_COMMAND` if it is set, with the
+/// command line about to run as `$1`.
+///
+/// Returns the status of `PREEXEC_COMMAND`, so that an `exit` there ends
+/// the shell before the command line runs. It leaves `$?` and the
+/// positional parameters as they were.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::Shell;
+/// use codecrafters_shell::shell::CommandLine;
+///
+/// let mut shell = Shell::default();
+/// shell.variables.set("PREEXEC_COMMAND", "last=$1");
+/// shell.execute_with_hooks(&CommandLine::parse("echo hi").unwrap());
+/// assert_eq!(shell.variables.get("last").as_deref(), Some("echo hi"));
+/// ```
+pub fn run_preexec(command: &str, shell: &mut Shell) -> ShellStatus {
+    for hook in shell.hooks.preexec().to_vec() {
+        hook(command, shell);
+    }
+    let name = shell.variables.argument(0).unwrap_or_default().to_string();
+    let saved = shell
+        .variables
+        .set_arguments(vec![name, command.to_string()]);
+    let status = run_hook_variable("PREEXEC_COMMAND", shell);
+    shell.variables.set_arguments(saved);
+    status
+}
+
+/// Runs the postexec callbacks with the command line that ran and its
+/// result.
+pub fn run_postexec(command: &str, result: &CommandResult, shell: &mut Shell) {
+    for hook in shell.hooks.postexec().to_vec() {
+        hook(command, result, shell);
+    }
+}
+
+/// Runs the command in the variable `name`, if it is set, even with
+/// `set -n`, leaving `$?` as it was.
+fn run_hook_variable(name: &str, shell: &mut Shell) -> ShellStatus {
+    match shell.variables.get(name) {
+        Some(command) if !command.trim().is_empty() => {
+            let status = shell.variables.status();
+            let no_exec = std::mem::take(&mut shell.options.no_exec);
+            let result = execute_pipeline(&command, shell);
+            shell.options.no_exec |= no_exec;
+            shell.variables.set_status(status);
+            result.shell
+        }
+        _ => ShellStatus::Continue,
+    }
+}
@@ -0,0 +1,119 @@
+use std::fs::File;
+
+/// The shell-side pipe ends of a coprocess.
+pub struct Coproc {
+    /// The coprocess name (`COPROC` unless given).
+    pub name: String,
+    /// Reads from the coprocess's stdout.
+    pub read: File,
+    /// Writes to the coprocess's stdin.
+    pub write: File,
+}
+
+/// State of a job as last observed by the shell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Stopped,
+    Done,
+}
+
+/// A pipeline running asynchronously from the shell.
+pub struct Job {
+    /// The job number shown as `[id]`.
+    pub id: usize,
+    /// PIDs of the job's processes that have not exited yet.
+    pub pids: Vec<i32>,
+    /// The command line that started the job.
+    pub command: String,
+    pub state: JobState,
+    pub coproc: Option<Coproc>,
+}
+
+/// Tracks background jobs and coprocesses.
+#[derive(Default)]
+pub struct JobTable {
+    jobs: Vec<Job>,
+}
+
+impl JobTable {
+    /// Registers a new running job and returns its job number.
+    pub fn add(&mut self, pids: Vec<i32>, command: &str, coproc: Option<Coproc>) -> usize {
+        let id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        self.jobs.push(Job {
+            id,
+            pids,
+            command: command.to_string(),
+            state: JobState::Running,
+            coproc,
+        });
+        id
+    }
+
+    /// Returns the running coprocess with the given name, if any.
+    pub fn coproc(&self, name: &str) -> Option<&Job> {
+        self.jobs
+            .iter()
+            .find(|job| job.coproc.as_ref().is_some_and(|c| c.name == name))
+    }
+
+    /// Returns true if any tracked job has not finished.
+    pub fn has_unfinished(&self) -> bool {
+        self.jobs.iter().any(|job| job.state != JobState::Done)
+    }
+
+    /// Returns true if any tracked job is stopped.
+    pub fn has_stopped(&self) -> bool {
+        self.jobs.iter().any(|job| job.state == JobState::Stopped)
+    }
+
+    /// Polls job processes without blocking, updating their states.
+    pub fn update(&mut self) {
+        for job in &mut self.jobs {
+            job.pids.retain(|&pid| {
+                let mut status: i32 = 0;
+                let flags = libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED;
+                match unsafe { libc::waitpid(pid, &mut status, flags) } {
+                    0 => true,
+                    -1 => false, // Already reaped or not our child
+                    _ if libc::WIFSTOPPED(status) => {
+                        trace!(pid, signal = libc::WSTOPSIG(status), "job stopped");
+                        job.state = JobState::Stopped;
+                        true
+                    }
+                    _ if libc::WIFCONTINUED(status) => {
+                        trace!(pid, "job continued");
+                        job.state = JobState::Running;
+                        true
+                    }
+                    _ if libc::WIFSIGNALED(status) => {
+                        trace!(
+                            pid,
+                            signal = libc::WTERMSIG(status),
+                            "job killed by a signal"
+                        );
+                        false
+                    }
+                    _ => {
+                        trace!(pid, status = libc::WEXITSTATUS(status), "job finished");
+                        false
+                    }
+                }
+            });
+            if job.pids.is_empty() {
+                job.state = JobState::Done;
+            }
+        }
+    }
+
+    /// Polls job processes, removing finished jobs from the table and
+    /// returning them so the caller can report them.
+    pub fn reap(&mut self) -> Vec<Job> {
+        self.update();
+        let (done, running) = std::mem::take(&mut self.jobs)
+            .into_iter()
+            .partition(|job| job.state == JobState::Done);
+        self.jobs = running;
+        done
+    }
+}
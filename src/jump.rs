@@ -0,0 +1,200 @@
+use crate::dirs;
+use crate::variables::Variables;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The file in the shell's state directory, next to the history, that
+/// keeps the directories visited.
+pub const DIRS_FILE: &str = "dirs";
+
+/// Once the ranks add up to more than this, they are all scaled down, so
+/// that directories no longer visited drop out.
+const MAX_TOTAL_RANK: f64 = 9000.0;
+
+/// A directory visited, as `j` remembers it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Visited {
+    pub path: PathBuf,
+    /// How often it was visited, decayed over time.
+    pub rank: f64,
+    /// When it was last visited, in seconds since the epoch.
+    pub time: u64,
+}
+
+impl Visited {
+    /// How likely the directory is to be wanted at `now`: its rank,
+    /// weighted by how recently it was visited, as z weighs it.
+    pub fn frecency(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.time);
+        let weight = match age {
+            0..3600 => 4.0,
+            3600..86400 => 2.0,
+            86400..604800 => 0.5,
+            _ => 0.25,
+        };
+        self.rank * weight
+    }
+}
+
+/// The directories visited in interactive shells, ranked by frecency: how
+/// often and how recently each was visited. The `j` builtin jumps to the
+/// best one matching what it is given.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::jump::Frecency;
+/// use std::path::Path;
+///
+/// let mut dirs = Frecency::default();
+/// dirs.visit(Path::new("/home/me/projects/shell"), 1000);
+/// dirs.visit(Path::new("/home/me/projects/notes"), 1000);
+/// dirs.visit(Path::new("/home/me/projects/notes"), 2000);
+/// assert_eq!(dirs.best(&["proj"], 2000), Some(Path::new("/home/me/projects/notes")));
+/// assert_eq!(dirs.best(&["proj", "sh"], 2000), Some(Path::new("/home/me/projects/shell")));
+/// assert_eq!(dirs.best(&["music"], 2000), None);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Frecency {
+    entries: Vec<Visited>,
+}
+
+impl Frecency {
+    /// Returns the file the directories are kept in: [`DIRS_FILE`] in the
+    /// state directory. Returns `None` if `HISTFILE` is empty, which turns
+    /// saving history off, or there is no home directory.
+    pub fn file(variables: &Variables) -> Option<PathBuf> {
+        if variables
+            .get("HISTFILE")
+            .is_some_and(|path| path.is_empty())
+        {
+            return None;
+        }
+        dirs::state_dir().map(|dir| dir.join(DIRS_FILE))
+    }
+
+    /// Reads the directories from a file of `path|rank|time` lines, the
+    /// format of z. A missing file has none.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let entries = contents
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.rsplitn(3, '|');
+                let time = fields.next()?.parse().ok()?;
+                let rank = fields.next()?.parse().ok()?;
+                let path = PathBuf::from(fields.next()?);
+                Some(Visited { path, rank, time })
+            })
+            .collect();
+        Ok(Frecency { entries })
+    }
+
+    /// Writes the directories to `path`, replacing it.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Written whole and renamed, so another shell never reads half
+        let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+        let mut file = fs::File::create(&temporary)?;
+        for entry in &self.entries {
+            writeln!(
+                file,
+                "{}|{}|{}",
+                entry.path.display(),
+                entry.rank,
+                entry.time
+            )?;
+        }
+        fs::rename(temporary, path)
+    }
+
+    /// Returns the directories, in the order they were first visited.
+    pub fn entries(&self) -> &[Visited] {
+        &self.entries
+    }
+
+    /// Records a visit to `dir` at `now`.
+    pub fn visit(&mut self, dir: &Path, now: u64) {
+        match self.entries.iter_mut().find(|entry| entry.path == dir) {
+            Some(entry) => {
+                entry.rank += 1.0;
+                entry.time = now;
+            }
+            None => self.entries.push(Visited {
+                path: dir.to_path_buf(),
+                rank: 1.0,
+                time: now,
+            }),
+        }
+        if self.entries.iter().map(|entry| entry.rank).sum::<f64>() > MAX_TOTAL_RANK {
+            for entry in &mut self.entries {
+                entry.rank *= 0.99;
+            }
+            self.entries.retain(|entry| entry.rank >= 1.0);
+        }
+    }
+
+    /// Returns the directory with the highest frecency whose path contains
+    /// each of `patterns`, in order. Patterns all in lowercase match
+    /// regardless of case.
+    pub fn best(&self, patterns: &[&str], now: u64) -> Option<&Path> {
+        self.matching(patterns, now)
+            .into_iter()
+            .next()
+            .map(|entry| entry.path.as_path())
+    }
+
+    /// Returns the directories matching `patterns` as for
+    /// [`Frecency::best`], best first.
+    pub fn matching(&self, patterns: &[&str], now: u64) -> Vec<&Visited> {
+        let mut matches: Vec<&Visited> = self
+            .entries
+            .iter()
+            .filter(|entry| matches(&entry.path.to_string_lossy(), patterns))
+            .collect();
+        matches.sort_by(|a, b| b.frecency(now).total_cmp(&a.frecency(now)));
+        matches
+    }
+}
+
+/// Checks whether `path` contains each of `patterns`, in order.
+fn matches(path: &str, patterns: &[&str]) -> bool {
+    let mut rest = path.to_string();
+    for pattern in patterns {
+        let haystack = if pattern.chars().any(char::is_uppercase) {
+            rest.clone()
+        } else {
+            rest.to_lowercase()
+        };
+        match haystack.find(pattern) {
+            Some(i) => rest = haystack[i + pattern.len()..].to_string(),
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Returns the current time in seconds since the epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+/// Records a visit to `dir` in the file of directories, if there is one.
+pub fn record_visit(dir: &Path, variables: &Variables) -> io::Result<()> {
+    let Some(path) = Frecency::file(variables) else {
+        return Ok(());
+    };
+    let mut dirs = Frecency::load(&path)?;
+    dirs.visit(dir, now());
+    dirs.save(&path)
+}
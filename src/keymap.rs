@@ -0,0 +1,395 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use termion::event::Key;
+
+/// A line-editing command that keys can be bound to, named as in readline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditCommand {
+    AcceptLine,
+    /// Discards the line and starts a new one (Ctrl-C).
+    Interrupt,
+    /// Exits the shell on an empty line, otherwise deletes the character
+    /// under the cursor (Ctrl-D).
+    EndOfFile,
+    SelfInsert,
+    BackwardDeleteChar,
+    DeleteChar,
+    BackwardChar,
+    /// Moves right, or accepts the autosuggestion at the end of the line.
+    ForwardChar,
+    BackwardWord,
+    ForwardWord,
+    BeginningOfLine,
+    EndOfLine,
+    PreviousHistory,
+    NextHistory,
+    /// Like `PreviousHistory`, but only visits entries starting with the
+    /// text typed before browsing.
+    HistorySearchBackward,
+    HistorySearchForward,
+    BeginningOfHistory,
+    EndOfHistory,
+    KillLine,
+    UnixLineDiscard,
+    UnixWordRubout,
+    KillWord,
+    Yank,
+    /// Copies the line to the system clipboard with OSC 52.
+    ClipboardCopy,
+    /// Inserts the system clipboard's contents, if the terminal answers
+    /// an OSC 52 query for them.
+    ClipboardPaste,
+    TransposeChars,
+    Undo,
+    ClearScreen,
+    Complete,
+    /// Replaces the word with the next of its completions, cycling through
+    /// them on repeated presses.
+    MenuComplete,
+    MenuCompleteBackward,
+    EditAndExecuteCommand,
+}
+
+impl EditCommand {
+    /// Every command with its readline name, as listed by `bind -l`.
+    pub const ALL: &'static [(&'static str, EditCommand)] = &[
+        ("accept-line", EditCommand::AcceptLine),
+        ("backward-char", EditCommand::BackwardChar),
+        ("backward-delete-char", EditCommand::BackwardDeleteChar),
+        ("backward-word", EditCommand::BackwardWord),
+        ("beginning-of-history", EditCommand::BeginningOfHistory),
+        ("beginning-of-line", EditCommand::BeginningOfLine),
+        ("clear-screen", EditCommand::ClearScreen),
+        ("clipboard-copy", EditCommand::ClipboardCopy),
+        ("clipboard-paste", EditCommand::ClipboardPaste),
+        ("complete", EditCommand::Complete),
+        ("delete-char", EditCommand::DeleteChar),
+        (
+            "edit-and-execute-command",
+            EditCommand::EditAndExecuteCommand,
+        ),
+        ("end-of-file", EditCommand::EndOfFile),
+        ("end-of-history", EditCommand::EndOfHistory),
+        ("end-of-line", EditCommand::EndOfLine),
+        ("forward-char", EditCommand::ForwardChar),
+        ("forward-word", EditCommand::ForwardWord),
+        (
+            "history-search-backward",
+            EditCommand::HistorySearchBackward,
+        ),
+        ("history-search-forward", EditCommand::HistorySearchForward),
+        ("interrupt", EditCommand::Interrupt),
+        ("kill-line", EditCommand::KillLine),
+        ("kill-word", EditCommand::KillWord),
+        ("menu-complete", EditCommand::MenuComplete),
+        ("menu-complete-backward", EditCommand::MenuCompleteBackward),
+        ("next-history", EditCommand::NextHistory),
+        ("previous-history", EditCommand::PreviousHistory),
+        ("self-insert", EditCommand::SelfInsert),
+        ("transpose-chars", EditCommand::TransposeChars),
+        ("undo", EditCommand::Undo),
+        ("unix-line-discard", EditCommand::UnixLineDiscard),
+        ("unix-word-rubout", EditCommand::UnixWordRubout),
+        ("yank", EditCommand::Yank),
+    ];
+
+    /// Looks up a command by its readline name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|&(_, command)| command)
+    }
+
+    /// Returns the command's readline name.
+    pub fn name(self) -> &'static str {
+        Self::ALL
+            .iter()
+            .find(|&&(_, command)| command == self)
+            .map(|&(name, _)| name)
+            .unwrap_or_default()
+    }
+}
+
+/// What a key sequence does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Binding {
+    /// Runs a line-editing command.
+    Command(EditCommand),
+    /// Runs a shell command (`bind -x`).
+    Shell(String),
+}
+
+/// The result of looking up the keys pressed so far.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lookup {
+    Bound(Binding),
+    /// The keys start a longer bound sequence, such as Ctrl-X.
+    Prefix,
+    Unbound,
+}
+
+/// Maps key sequences to what they do, as configured with `bind`.
+///
+/// Keys not bound to anything insert themselves if they are printable.
+/// Clones share their bindings until one of them is changed.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Arc<HashMap<Vec<Key>, Binding>>,
+}
+
+impl Default for Keymap {
+    /// The emacs-style bindings.
+    fn default() -> Self {
+        use EditCommand::*;
+        let defaults: &[(&[Key], EditCommand)] = &[
+            (&[Key::Char('\n')], AcceptLine),
+            (&[Key::Char('\r')], AcceptLine),
+            (&[Key::Ctrl('c')], Interrupt),
+            (&[Key::Ctrl('d')], EndOfFile),
+            (&[Key::Char('\t')], Complete),
+            (&[Key::BackTab], MenuCompleteBackward),
+            (&[Key::Backspace], BackwardDeleteChar),
+            (&[Key::Delete], DeleteChar),
+            (&[Key::Left], BackwardChar),
+            (&[Key::Right], ForwardChar),
+            (&[Key::Ctrl('f')], ForwardChar),
+            (&[Key::Alt('b')], BackwardWord),
+            (&[Key::Alt('f')], ForwardWord),
+            (&[Key::Home], BeginningOfLine),
+            (&[Key::Ctrl('a')], BeginningOfLine),
+            (&[Key::End], EndOfLine),
+            (&[Key::Ctrl('e')], EndOfLine),
+            (&[Key::Up], HistorySearchBackward),
+            (&[Key::Down], HistorySearchForward),
+            (&[Key::PageUp], BeginningOfHistory),
+            (&[Key::PageDown], EndOfHistory),
+            (&[Key::Ctrl('k')], KillLine),
+            (&[Key::Ctrl('u')], UnixLineDiscard),
+            (&[Key::Ctrl('w')], UnixWordRubout),
+            (&[Key::Alt('d')], KillWord),
+            (&[Key::Ctrl('y')], Yank),
+            (&[Key::Alt('w')], ClipboardCopy),
+            (&[Key::Ctrl('x'), Key::Ctrl('v')], ClipboardPaste),
+            (&[Key::Ctrl('t')], TransposeChars),
+            // Ctrl-_ arrives as 0x1F, which termion decodes as Ctrl-7
+            (&[Key::Ctrl('7')], Undo),
+            (&[Key::Ctrl('x'), Key::Ctrl('u')], Undo),
+            (&[Key::Ctrl('l')], ClearScreen),
+            (&[Key::Ctrl('x'), Key::Ctrl('e')], EditAndExecuteCommand),
+        ];
+        Self {
+            bindings: Arc::new(
+                defaults
+                    .iter()
+                    .map(|&(keys, command)| (keys.to_vec(), Binding::Command(command)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl Keymap {
+    /// Looks up the keys pressed so far.
+    pub fn lookup(&self, keys: &[Key]) -> Lookup {
+        if let Some(binding) = self.bindings.get(keys) {
+            return Lookup::Bound(binding.clone());
+        }
+        if self
+            .bindings
+            .keys()
+            .any(|bound| bound.len() > keys.len() && bound.starts_with(keys))
+        {
+            return Lookup::Prefix;
+        }
+        match keys {
+            [Key::Char(_)] => Lookup::Bound(Binding::Command(EditCommand::SelfInsert)),
+            _ => Lookup::Unbound,
+        }
+    }
+
+    /// Binds a key sequence, replacing any previous binding.
+    pub fn bind(&mut self, keys: Vec<Key>, binding: Binding) {
+        Arc::make_mut(&mut self.bindings).insert(keys, binding);
+    }
+
+    /// Removes the binding for a key sequence, returning false if there was
+    /// none.
+    pub fn unbind(&mut self, keys: &[Key]) -> bool {
+        self.bindings.contains_key(keys) && Arc::make_mut(&mut self.bindings).remove(keys).is_some()
+    }
+
+    /// Returns every binding with its key sequence in `bind` syntax, sorted
+    /// by what the keys do.
+    pub fn bindings(&self) -> Vec<(String, &Binding)> {
+        let mut bindings: Vec<_> = self
+            .bindings
+            .iter()
+            .map(|(keys, binding)| (format_key_sequence(keys), binding))
+            .collect();
+        bindings.sort_by(|(a_keys, a), (b_keys, b)| {
+            binding_sort_key(a)
+                .cmp(binding_sort_key(b))
+                .then_with(|| a_keys.cmp(b_keys))
+        });
+        bindings
+    }
+}
+
+fn binding_sort_key(binding: &Binding) -> &str {
+    match binding {
+        Binding::Command(command) => command.name(),
+        Binding::Shell(command) => command,
+    }
+}
+
+/// Splits a binding such as `"\C-g": undo`, as given to `bind` or written in
+/// an inputrc file, into its key sequence and what follows the colon.
+///
+/// Surrounding double quotes are removed from the second part, as used for
+/// `bind -x` commands. Returns `None` if the binding is malformed.
+pub fn parse_binding(line: &str) -> Option<(Vec<Key>, String)> {
+    let line = line.trim();
+    let (sequence, rest) = match line.strip_prefix('"') {
+        Some(quoted) => {
+            // Find the closing quote, skipping escaped characters
+            let mut end = None;
+            let mut escaped = false;
+            for (i, c) in quoted.char_indices() {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => {
+                        end = Some(i);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            let end = end?;
+            (
+                &quoted[..end],
+                quoted[end + 1..].trim_start().strip_prefix(':')?,
+            )
+        }
+        None => line.split_once(':')?,
+    };
+    let value = rest.trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value);
+    Some((parse_key_sequence(sequence)?, value.to_string()))
+}
+
+/// Parses a key sequence in readline syntax, such as `\C-g`, `\M-f`,
+/// `\C-x\C-e` or `\e[A`.
+///
+/// Returns `None` if the sequence is empty or uses an unknown escape.
+pub fn parse_key_sequence(sequence: &str) -> Option<Vec<Key>> {
+    let mut keys = Vec::new();
+    let mut chars = sequence.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            keys.push(Key::Char(c));
+            continue;
+        }
+        let key = match chars.next()? {
+            'C' if chars.next_if_eq(&'-').is_some() => control_key(chars.next()?)?,
+            'M' if chars.next_if_eq(&'-').is_some() => Key::Alt(chars.next()?),
+            'e' => match chars.next() {
+                Some('[') => {
+                    let mut code = String::new();
+                    for c in chars.by_ref() {
+                        code.push(c);
+                        if !c.is_ascii_digit() {
+                            break;
+                        }
+                    }
+                    escape_sequence_key(&code)?
+                }
+                Some(c) => Key::Alt(c),
+                None => Key::Esc,
+            },
+            't' => Key::Char('\t'),
+            'n' => Key::Char('\n'),
+            'r' => Key::Char('\r'),
+            'd' => Key::Backspace,
+            c @ ('\\' | '"' | '\'') => Key::Char(c),
+            _ => return None,
+        };
+        keys.push(key);
+    }
+    (!keys.is_empty()).then_some(keys)
+}
+
+/// Formats a key sequence in the syntax `parse_key_sequence` accepts.
+pub fn format_key_sequence(keys: &[Key]) -> String {
+    keys.iter()
+        .map(|key| match key {
+            Key::Char('\t') => "\\t".to_string(),
+            Key::Char('\n') => "\\n".to_string(),
+            Key::Char('\r') => "\\r".to_string(),
+            Key::Char(c @ ('\\' | '"')) => format!("\\{}", c),
+            Key::Char(c) => c.to_string(),
+            Key::Ctrl(c) => format!("\\C-{}", control_name(*c)),
+            Key::Alt(c) => format!("\\M-{}", c),
+            Key::Backspace => "\\C-?".to_string(),
+            Key::Esc => "\\e".to_string(),
+            Key::Up => "\\e[A".to_string(),
+            Key::Down => "\\e[B".to_string(),
+            Key::Right => "\\e[C".to_string(),
+            Key::Left => "\\e[D".to_string(),
+            Key::Home => "\\e[H".to_string(),
+            Key::End => "\\e[F".to_string(),
+            Key::Delete => "\\e[3~".to_string(),
+            Key::PageUp => "\\e[5~".to_string(),
+            Key::PageDown => "\\e[6~".to_string(),
+            Key::BackTab => "\\e[Z".to_string(),
+            other => format!("{:?}", other),
+        })
+        .collect()
+}
+
+/// Returns the key for `\C-c`, following termion's decoding of control
+/// bytes.
+fn control_key(c: char) -> Option<Key> {
+    match c {
+        '?' => Some(Key::Backspace),
+        'a'..='z' => Some(Key::Ctrl(c)),
+        'A'..='Z' => Some(Key::Ctrl(c.to_ascii_lowercase())),
+        '\\' => Some(Key::Ctrl('4')),
+        ']' => Some(Key::Ctrl('5')),
+        '^' => Some(Key::Ctrl('6')),
+        '_' => Some(Key::Ctrl('7')),
+        _ => None,
+    }
+}
+
+/// Returns the character written after `\C-` for a termion control key.
+fn control_name(c: char) -> char {
+    match c {
+        '4' => '\\',
+        '5' => ']',
+        '6' => '^',
+        '7' => '_',
+        c => c,
+    }
+}
+
+/// Returns the key for the part of an escape sequence after `\e[`.
+fn escape_sequence_key(code: &str) -> Option<Key> {
+    match code {
+        "A" => Some(Key::Up),
+        "B" => Some(Key::Down),
+        "C" => Some(Key::Right),
+        "D" => Some(Key::Left),
+        "H" => Some(Key::Home),
+        "F" => Some(Key::End),
+        "3~" => Some(Key::Delete),
+        "5~" => Some(Key::PageUp),
+        "6~" => Some(Key::PageDown),
+        "Z" => Some(Key::BackTab),
+        _ => None,
+    }
+}
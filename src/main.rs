@@ -1,314 +1,54 @@
-use codecrafters_shell::ShellStatus;
-use std::{
-    io::{self, Write},
-    process,
-};
-use termion::event::Key;
-use termion::input::TermRead;
-use termion::raw::IntoRawMode;
-
-/// Calculates the longest common prefix of a list of strings.
-fn longest_common_prefix(strings: &[String]) -> String {
-    if strings.is_empty() {
-        return String::new();
-    }
-
-    if strings.len() == 1 {
-        return strings[0].clone();
-    }
-
-    let first = &strings[0];
-    let mut prefix = String::new();
-
-    for (i, ch) in first.chars().enumerate() {
-        if strings.iter().all(|s| s.chars().nth(i) == Some(ch)) {
-            prefix.push(ch);
-        } else {
-            break;
+use codecrafters_shell::error::{self, ShellError};
+use codecrafters_shell::shell::Shell;
+use codecrafters_shell::signals;
+use codecrafters_shell::{ShellOptions, ShellStatus};
+use std::process;
+
+fn main() {
+    codecrafters_shell::trace::init();
+    let options = match ShellOptions::from_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            error::report_message(message, &Shell::default());
+            eprintln!(
+                "usage: shell [-l | --login] [-r | --restricted] [-n | --dry-run] [--norc] [--posix] [--rcfile path] [-c command | script] [args ...]"
+            );
+            process::exit(2);
         }
-    }
+    };
 
-    prefix
-}
+    // SIGTERM and SIGHUP end the shell through its EXIT trap
+    signals::install_termination_handler();
 
-/// Saves the history to the HISTFILE if the environment variable is set.
-fn save_history_to_file(history: &[String]) {
-    if let Ok(histfile) = std::env::var("HISTFILE")
-        && let Ok(mut file) = std::fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&histfile)
-    {
-        use std::io::Write;
-        for cmd in history {
-            let _ = writeln!(file, "{}", cmd);
-        }
+    // History from earlier sessions and the user's settings
+    let mut shell = Shell::new(options);
+    let arg0 = std::env::args().next().unwrap_or_default();
+    if let ShellStatus::Exit(code) = shell.start(&arg0) {
+        process::exit(shell.finish(code));
     }
-}
-
-fn main() -> io::Result<()> {
-    let mut command_history: Vec<String> = Vec::new();
-    let mut last_saved_index: usize = 0;
-
-    // Load history from HISTFILE environment variable if it exists
-    if let Ok(histfile) = std::env::var("HISTFILE")
-        && let Ok(file) = std::fs::File::open(&histfile)
-    {
-        use std::io::{BufRead, BufReader};
-        let reader = BufReader::new(file);
-        for line in reader.lines().map_while(Result::ok) {
-            command_history.push(line);
-        }
-    }
-
-    loop {
-        print!("$ ");
-        io::stdout().flush()?;
-
-        // Enter raw mode to handle input character by character
-        let mut stdout = io::stdout().into_raw_mode()?;
-        let stdin = io::stdin();
-        let mut buffer = String::new();
-        let mut last_was_tab = false;
-        let mut last_tab_matches: Vec<String> = Vec::new();
-        let mut last_tab_buffer = String::new();
-        let mut history_index: Option<usize> = None;
-
-        for c in stdin.keys() {
-            match c {
-                Ok(key) => match key {
-                    Key::Ctrl('c') => {
-                        buffer.clear();
-                        write!(stdout, "\r\n")?;
-                        break;
-                    }
-                    Key::Ctrl('d') => {
-                        if buffer.is_empty() {
-                            save_history_to_file(&command_history);
-                            return Ok(());
-                        }
-                    }
-                    Key::Char('\n') | Key::Char('\r') => {
-                        write!(stdout, "\r\n")?;
-                        break;
-                    }
-                    Key::Up => {
-                        if !command_history.is_empty() {
-                            // Navigate backwards in history
-                            let new_index = match history_index {
-                                None => command_history.len() - 1,
-                                Some(0) => 0, // Already at oldest
-                                Some(idx) => idx - 1,
-                            };
-
-                            history_index = Some(new_index);
-
-                            // Clear current line
-                            write!(stdout, "\r$ ")?;
-                            for _ in 0..buffer.len() {
-                                write!(stdout, " ")?;
-                            }
-                            write!(stdout, "\r$ ")?;
 
-                            // Load history entry
-                            buffer = command_history[new_index].clone();
-                            write!(stdout, "{}", buffer)?;
-                            stdout.flush()?;
-                        }
-                        last_was_tab = false;
-                    }
-                    Key::Down => {
-                        if let Some(idx) = history_index {
-                            // Navigate forwards in history
-                            let new_index = if idx + 1 >= command_history.len() {
-                                // At newest, clear buffer
-                                history_index = None;
-
-                                // Clear current line
-                                write!(stdout, "\r$ ")?;
-                                for _ in 0..buffer.len() {
-                                    write!(stdout, " ")?;
-                                }
-                                write!(stdout, "\r$ ")?;
-
-                                buffer.clear();
-                                stdout.flush()?;
-                                last_was_tab = false;
-                                continue;
-                            } else {
-                                idx + 1
-                            };
-
-                            history_index = Some(new_index);
-
-                            // Clear current line
-                            write!(stdout, "\r$ ")?;
-                            for _ in 0..buffer.len() {
-                                write!(stdout, " ")?;
-                            }
-                            write!(stdout, "\r$ ")?;
-
-                            // Load history entry
-                            buffer = command_history[new_index].clone();
-                            write!(stdout, "{}", buffer)?;
-                            stdout.flush()?;
-                        }
-                        last_was_tab = false;
-                    }
-                    Key::Char('\t') => {
-                        // Collect all possible completions: builtins and executables
-                        let builtins = ["echo", "exit", "type", "pwd", "cd", "history"];
-                        let mut all_commands: Vec<String> = builtins
-                            .iter()
-                            .filter(|cmd| cmd.starts_with(&buffer))
-                            .map(|s| s.to_string())
-                            .collect();
-
-                        // Add executables from PATH
-                        let executables = codecrafters_shell::get_all_executables();
-                        all_commands.extend(
-                            executables
-                                .into_iter()
-                                .filter(|cmd| cmd.starts_with(&buffer)),
-                        );
-
-                        // Remove duplicates and sort
-                        all_commands.sort();
-                        all_commands.dedup();
-
-                        if all_commands.len() == 1 {
-                            // Single match: complete it with trailing space
-                            let cmd = &all_commands[0];
-                            let remainder = &cmd[buffer.len()..];
-                            write!(stdout, "{} ", remainder)?;
-                            buffer.push_str(remainder);
-                            buffer.push(' ');
-                            stdout.flush()?;
-                            last_was_tab = false;
-                        } else if all_commands.is_empty() {
-                            // No matches: beep
-                            write!(stdout, "\x07")?;
-                            stdout.flush()?;
-                            last_was_tab = false;
-                        } else {
-                            // Multiple matches: try LCP completion
-                            let lcp = longest_common_prefix(&all_commands);
-
-                            if lcp.len() > buffer.len() {
-                                // We can complete more - complete to LCP without space
-                                let remainder = &lcp[buffer.len()..];
-                                write!(stdout, "{}", remainder)?;
-                                buffer.push_str(remainder);
-                                stdout.flush()?;
-                                last_was_tab = false;
-                            } else {
-                                // LCP equals buffer - can't complete further
-                                if last_was_tab
-                                    && buffer == last_tab_buffer
-                                    && !last_tab_matches.is_empty()
-                                {
-                                    // Second tab: display all matches
-                                    write!(stdout, "\r\n")?;
-                                    write!(stdout, "{}\r\n", last_tab_matches.join("  "))?;
-                                    write!(stdout, "$ {}", buffer)?;
-                                    stdout.flush()?;
-                                    last_was_tab = false;
-                                } else {
-                                    // First tab: beep and store matches
-                                    write!(stdout, "\x07")?;
-                                    stdout.flush()?;
-                                    last_was_tab = true;
-                                    last_tab_matches = all_commands;
-                                    last_tab_buffer = buffer.clone();
-                                }
-                            }
-                        }
-                    }
-                    Key::Backspace => {
-                        if !buffer.is_empty() {
-                            buffer.pop();
-                            // Move cursor back, erase char with space, move back again
-                            write!(stdout, "\x08 \x08")?;
-                            stdout.flush()?;
-                        }
-                        last_was_tab = false;
-                        history_index = None;
-                    }
-                    Key::Char(c) => {
-                        buffer.push(c);
-                        write!(stdout, "{}", c)?;
-                        stdout.flush()?;
-                        last_was_tab = false;
-                        history_index = None;
-                    }
-                    _ => {
-                        last_was_tab = false;
-                    }
-                },
-                Err(e) => {
-                    eprintln!("Error reading input: {}", e);
-                    break;
-                }
-            }
+    let arguments = shell.options.arguments.clone();
+    let status = if let Some(command) = shell.options.command.clone() {
+        // With -c, the shell runs that command instead of reading commands
+        if !arguments.is_empty() {
+            shell.variables.set_arguments(arguments);
         }
-
-        // Disable raw mode
-        drop(stdout);
-
-        let input_string = buffer.trim().to_string();
-        if input_string.is_empty() {
-            continue;
-        }
-
-        // Add to history
-        command_history.push(input_string.clone());
-
-        // Check if this is a pipeline command
-        if input_string.contains('|') {
-            match codecrafters_shell::execute_pipeline(&input_string) {
-                ShellStatus::Exit(code) => {
-                    save_history_to_file(&command_history);
-                    process::exit(code)
-                }
-                ShellStatus::LoadHistory(entries) => {
-                    command_history.extend(entries);
-                    continue;
-                }
-                ShellStatus::HistorySaved(index) => {
-                    last_saved_index = index;
-                    continue;
-                }
-                ShellStatus::Continue => continue,
-            }
-        }
-
-        let mut parts = codecrafters_shell::tokenize(&input_string).into_iter();
-        let command_str = match parts.next() {
-            Some(cmd) => cmd,
-            None => continue,
+        let status = match shell.run_commands(&command) {
+            ShellStatus::Exit(code) => code,
+            ShellStatus::Continue => shell.variables.status(),
         };
-        let args: Vec<String> = parts.collect();
-
-        match codecrafters_shell::handle_command(
-            &command_str,
-            args,
-            &command_history,
-            last_saved_index,
-        ) {
-            ShellStatus::Exit(code) => {
-                save_history_to_file(&command_history);
-                process::exit(code)
-            }
-            ShellStatus::LoadHistory(entries) => {
-                command_history.extend(entries);
-                continue;
-            }
-            ShellStatus::HistorySaved(index) => {
-                last_saved_index = index;
-                continue;
-            }
-            ShellStatus::Continue => continue,
-        }
-    }
+        shell.finish(status)
+    } else if let Some(path) = shell.options.script.clone() {
+        let status = shell.run_script(&path, &arguments).unwrap_or_else(|e| {
+            error::report(&ShellError::io(path.display().to_string(), e), &shell);
+            127
+        });
+        shell.finish(status)
+    } else {
+        shell.run_interactive().unwrap_or_else(|e| {
+            error::report_message(e, &shell);
+            1
+        })
+    };
+    process::exit(status);
 }
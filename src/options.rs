@@ -0,0 +1,269 @@
+use crate::redirect::Redirection;
+use std::path::PathBuf;
+
+/// Options controlling shell behavior, set from the command line or at
+/// runtime with `set -o` and `shopt`.
+///
+/// These are only flags and settings; what the shell builds up as it runs,
+/// such as its variables and history, is kept by [`Shell`](crate::Shell).
+#[derive(Debug, Default, Clone)]
+pub struct ShellOptions {
+    /// Restricted mode (`-r`/`--restricted`): forbids `cd` and `j`, command
+    /// names containing `/`, sourcing files named with `/`, and output
+    /// redirection to files.
+    pub restricted: bool,
+    /// Login shell (`-l`/`--login`, or started with a name beginning with
+    /// `-`): reads the profile files at startup and the logout file on
+    /// exit.
+    pub login: bool,
+    /// Skip the interactive startup file (`--norc`).
+    pub norc: bool,
+    /// Follow POSIX startup rules (`--posix`, or when started as `sh`):
+    /// read the file named by `ENV` rather than the rc file.
+    pub posix: bool,
+    /// Read this startup file instead of `~/.codecraftersshrc`
+    /// (`--rcfile path`).
+    pub rcfile: Option<PathBuf>,
+    /// Run this command and exit instead of reading commands interactively
+    /// (`-c command`).
+    pub command: Option<String>,
+    /// Run this script file and exit instead of reading commands
+    /// interactively (`shell path args...`).
+    pub script: Option<PathBuf>,
+    /// The arguments after the script's path, which become `$1` and so on.
+    /// After `-c command` they start from `$0`, as in bash.
+    pub arguments: Vec<String>,
+    /// Print the commands as they would run, with their words expanded and
+    /// quoted, instead of running them (`set -n`, `set -o noexec`, or
+    /// `-n`/`--dry-run` at startup).
+    pub no_exec: bool,
+    /// Use vi-style line editing (`set -o vi`) instead of emacs-style.
+    pub vi: bool,
+    /// Copy killed and yanked text to the system clipboard with OSC 52
+    /// escape sequences (`set -o clipboard`), which works over SSH in
+    /// terminals that allow it.
+    pub clipboard: bool,
+    /// Fall back to fuzzy matching when Tab finds no completions starting
+    /// with the word (`set -o fuzzycomplete`).
+    pub fuzzy_complete: bool,
+    /// Offer hidden files when completing names that don't start with `.`
+    /// (`set -o hiddencomplete`).
+    pub hidden_complete: bool,
+    /// Show the working directory in the primary prompt when `PS1` is not
+    /// set (`set -o dirprompt`).
+    pub dir_prompt: bool,
+    /// Show the git branch and whether the work tree has changes in the
+    /// primary prompt (`set -o gitprompt`).
+    pub git_prompt: bool,
+    /// Mark prompts, command lines and their output with OSC 133 escape
+    /// sequences for the terminal (`set -o semanticprompt`).
+    pub semantic_prompt: bool,
+    /// Share history with other shells using the same history file,
+    /// reading their commands before each prompt (`set -o sharehistory`).
+    pub share_history: bool,
+    /// Change to a directory given as a command name that isn't a command,
+    /// as if with `cd` (`shopt -s autocd`).
+    pub auto_cd: bool,
+    /// Correct small misspellings of the directory names given to `cd`,
+    /// going to the closest match instead of only suggesting it
+    /// (`shopt -s cdspell`).
+    pub cd_spell: bool,
+    /// Rewrite the history file with this shell's history on exit, rather
+    /// than only appending to it (`shopt -u histappend`).
+    pub hist_overwrite: bool,
+    /// Put the result of a history expansion on the command line to edit
+    /// instead of running it straight away (`shopt -s histverify`).
+    pub hist_verify: bool,
+    /// How many matches Tab lists before asking first, as set by readline's
+    /// `completion-query-items`. `None` uses the default of
+    /// [`completion::QUERY_ITEMS`](crate::completion::QUERY_ITEMS) and 0
+    /// never asks.
+    pub completion_query_items: Option<usize>,
+}
+
+impl ShellOptions {
+    /// Names accepted by `set -o`, in the order `set -o` lists them.
+    pub const NAMES: &'static [&'static str] = &[
+        "clipboard",
+        "dirprompt",
+        "emacs",
+        "fuzzycomplete",
+        "gitprompt",
+        "hiddencomplete",
+        "noexec",
+        "semanticprompt",
+        "sharehistory",
+        "vi",
+    ];
+
+    /// Names accepted by `shopt`, in the order `shopt` lists them.
+    pub const SHOPT_NAMES: &'static [&'static str] =
+        &["autocd", "cdspell", "histappend", "histverify"];
+
+    /// Returns the value of a `shopt` option, or `None` if it is unknown.
+    pub fn shopt(&self, name: &str) -> Option<bool> {
+        match name {
+            "autocd" => Some(self.auto_cd),
+            "cdspell" => Some(self.cd_spell),
+            "histappend" => Some(!self.hist_overwrite),
+            "histverify" => Some(self.hist_verify),
+            _ => None,
+        }
+    }
+
+    /// Turns a `shopt` option on or off. Returns an error message for
+    /// unknown names.
+    pub fn set_shopt(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        match name {
+            "autocd" => self.auto_cd = enabled,
+            "cdspell" => self.cd_spell = enabled,
+            "histappend" => self.hist_overwrite = !enabled,
+            "histverify" => self.hist_verify = enabled,
+            _ => return Err(format!("{}: invalid shell option name", name)),
+        }
+        Ok(())
+    }
+
+    /// Returns the value of a `set -o` option, or `None` if it is unknown.
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "clipboard" => Some(self.clipboard),
+            "dirprompt" => Some(self.dir_prompt),
+            "emacs" => Some(!self.vi),
+            "fuzzycomplete" => Some(self.fuzzy_complete),
+            "gitprompt" => Some(self.git_prompt),
+            "hiddencomplete" => Some(self.hidden_complete),
+            "noexec" => Some(self.no_exec),
+            "semanticprompt" => Some(self.semantic_prompt),
+            "sharehistory" => Some(self.share_history),
+            "vi" => Some(self.vi),
+            _ => None,
+        }
+    }
+
+    /// Turns a `set -o` option on or off.
+    ///
+    /// `emacs` and `vi` are the two editing modes, so enabling one disables
+    /// the other. Returns an error message for unknown names.
+    pub fn set(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        match name {
+            "clipboard" => self.clipboard = enabled,
+            "dirprompt" => self.dir_prompt = enabled,
+            "emacs" => self.vi = !enabled,
+            "fuzzycomplete" => self.fuzzy_complete = enabled,
+            "gitprompt" => self.git_prompt = enabled,
+            "hiddencomplete" => self.hidden_complete = enabled,
+            "noexec" => self.no_exec = enabled,
+            "semanticprompt" => self.semantic_prompt = enabled,
+            "sharehistory" => self.share_history = enabled,
+            "vi" => self.vi = enabled,
+            _ => return Err(format!("{}: invalid option name", name)),
+        }
+        Ok(())
+    }
+
+    /// Parses the shell's command-line flags (excluding the program name).
+    ///
+    /// Returns an error message for unrecognized flags.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut options = ShellOptions::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if let Some(path) = arg.strip_prefix("--rcfile=") {
+                options.rcfile = Some(PathBuf::from(path));
+                continue;
+            }
+            match arg.as_str() {
+                "-c" => match args.next() {
+                    Some(command) => options.command = Some(command),
+                    None => return Err("-c: option requires an argument".to_string()),
+                },
+                "-l" | "--login" => options.login = true,
+                "-n" | "--dry-run" => options.no_exec = true,
+                "--norc" => options.norc = true,
+                "--posix" => options.posix = true,
+                "--rcfile" => match args.next() {
+                    Some(path) => options.rcfile = Some(PathBuf::from(path)),
+                    None => return Err("--rcfile: option requires an argument".to_string()),
+                },
+                "-r" | "--restricted" => options.restricted = true,
+                _ if arg.starts_with('-') => return Err(format!("{}: invalid option", arg)),
+                // The first argument that isn't an option ends them
+                _ => {
+                    if options.command.is_none() {
+                        options.script = Some(PathBuf::from(arg));
+                    } else {
+                        options.arguments.push(arg);
+                    }
+                    options.arguments.extend(args);
+                    break;
+                }
+            }
+        }
+        Ok(options)
+    }
+
+    /// Variables a restricted shell may not change, since they decide which
+    /// commands run and how the shell starts.
+    pub const RESTRICTED_VARIABLES: &'static [&'static str] = &["PATH", "SHELL", "ENV"];
+
+    /// Checks whether a variable may be assigned, exported or unset under
+    /// the current options.
+    ///
+    /// Returns a bash-style error message (without the `shell: ` prefix)
+    /// for the variables restricted mode protects.
+    pub fn check_assignment(&self, name: &str) -> Result<(), String> {
+        if self.restricted && Self::RESTRICTED_VARIABLES.contains(&name) {
+            return Err(format!("{}: readonly variable", name));
+        }
+        Ok(())
+    }
+
+    /// Checks whether a command may run with `args` under the current
+    /// options.
+    ///
+    /// Returns a bash-style error message (without the `shell: ` prefix)
+    /// describing the first restriction the command violates. Duplicating
+    /// a file descriptor, as in `2>&1`, opens no file, so it is allowed.
+    pub fn check_restrictions(
+        &self,
+        command: &str,
+        args: &[String],
+        redirections: &[Redirection],
+    ) -> Result<(), String> {
+        if !self.restricted {
+            return Ok(());
+        }
+
+        // Loading a plugin runs arbitrary code in the shell
+        if command == "cd" || command == "j" || command == "plugin" {
+            return Err(format!("{}: restricted", command));
+        }
+
+        if command.contains('/') {
+            return Err(format!(
+                "{}: restricted: cannot specify `/' in command names",
+                command
+            ));
+        }
+
+        // Only the script named is restricted, not its arguments
+        if (command == "source" || command == ".")
+            && let Some(file) = args.first().filter(|file| file.contains('/'))
+        {
+            return Err(format!("{}: {}: restricted", command, file));
+        }
+
+        if let Some(path) = redirections
+            .iter()
+            .find_map(|redirection| match redirection {
+                Redirection::File { path, .. } => Some(path),
+                Redirection::Duplicate { .. } => None,
+            })
+        {
+            return Err(format!("{}: restricted: cannot redirect output", path));
+        }
+
+        Ok(())
+    }
+}
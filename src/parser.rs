@@ -1,3 +1,7 @@
+use crate::expand::{self, ExpansionError};
+use crate::variables::{Variables, is_name};
+use std::ops::Range;
+
 /// Splits an input string into a vector of arguments.
 ///
 /// This tokenizer handles:
@@ -12,10 +16,63 @@
 /// assert_eq!(args, vec!["echo", "hello world"]);
 /// ```
 pub fn tokenize(input: &str) -> Vec<String> {
+    tokenize_with_spans(input)
+        .into_iter()
+        .map(|(_, word)| word)
+        .collect()
+}
+
+/// Splits an input string into arguments like `tokenize`, with the
+/// expansions of [`crate::expand::expand`]: braces, `~`, variables and
+/// arithmetic, then field splitting and pathname expansion. Command
+/// substitutions are left as written, since this doesn't run commands.
+///
+/// An unset variable expands to nothing, and an unquoted word left empty
+/// by that is dropped. Unquoted values are split at the characters of
+/// `IFS`. Returns the first expansion that fails, such as a division by
+/// zero.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::parser::tokenize_expanded;
+/// use codecrafters_shell::variables::Variables;
+///
+/// let mut variables = Variables::default();
+/// variables.set("greeting", "hello world");
+/// let args = tokenize_expanded("echo \"$greeting\" '$greeting' ${greeting}! $unset_name", &variables)
+///     .unwrap();
+/// assert_eq!(args, vec!["echo", "hello world", "$greeting", "hello", "world!"]);
+/// ```
+pub fn tokenize_expanded(
+    input: &str,
+    variables: &Variables,
+) -> Result<Vec<String>, ExpansionError> {
+    expand::expand(input, &expand::Context::new(variables))
+}
+
+/// Splits input into arguments like `tokenize`, with the byte range of
+/// the input each came from, quotes and backslashes included.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::parser::tokenize_with_spans;
+/// let input = "cat 'my file'  a\\ b";
+/// let tokens = tokenize_with_spans(input);
+/// assert_eq!(
+///     tokens,
+///     vec![
+///         (0..3, "cat".to_string()),
+///         (4..13, "my file".to_string()),
+///         (15..19, "a b".to_string()),
+///     ]
+/// );
+/// assert_eq!(&input[tokens[1].0.clone()], "'my file'");
+/// ```
+pub fn tokenize_with_spans(input: &str) -> Vec<(Range<usize>, String)> {
     let mut args = Vec::new();
-    let mut chars = input.chars().peekable();
+    let mut chars = input.char_indices().peekable();
 
-    while let Some(&c) = chars.peek() {
+    while let Some(&(start, c)) = chars.peek() {
         if c.is_whitespace() {
             chars.next();
             continue;
@@ -24,10 +81,10 @@ pub fn tokenize(input: &str) -> Vec<String> {
         let mut arg = String::new();
 
         loop {
-            match chars.peek() {
+            match chars.peek().map(|&(_, c)| c) {
                 Some('\'') => {
                     chars.next(); // Consume opening '
-                    for c in chars.by_ref() {
+                    for (_, c) in chars.by_ref() {
                         if c == '\'' {
                             break;
                         }
@@ -36,26 +93,18 @@ pub fn tokenize(input: &str) -> Vec<String> {
                 }
                 Some('"') => {
                     chars.next(); // Consume opening "
-                    while let Some(&c) = chars.peek() {
+                    while let Some(&(_, c)) = chars.peek() {
                         if c == '"' {
                             chars.next();
                             break;
                         }
                         if c == '\\' {
                             chars.next(); // Consume \
-                            match chars.peek() {
-                                Some(&next_c)
-                                    if next_c == '\\'
-                                        || next_c == '$'
-                                        || next_c == '"'
-                                        || next_c == '\n' =>
-                                {
-                                    arg.push(next_c);
-                                    chars.next();
-                                }
-                                _ => {
-                                    arg.push('\\');
-                                }
+                            match chars
+                                .next_if(|&(_, next)| matches!(next, '\\' | '$' | '"' | '\n'))
+                            {
+                                Some((_, next_c)) => arg.push(next_c),
+                                None => arg.push('\\'),
                             }
                         } else {
                             arg.push(c);
@@ -65,19 +114,192 @@ pub fn tokenize(input: &str) -> Vec<String> {
                 }
                 Some('\\') => {
                     chars.next(); // Consume \
-                    if let Some(c) = chars.next() {
+                    if let Some((_, c)) = chars.next() {
                         arg.push(c);
                     }
                 }
                 Some(c) if c.is_whitespace() => break,
                 Some(c) => {
-                    arg.push(*c);
+                    arg.push(c);
                     chars.next();
                 }
                 None => break,
             }
         }
-        args.push(arg);
+        let end = chars.peek().map_or(input.len(), |&(i, _)| i);
+        args.push((start..end, arg));
     }
     args
 }
+
+/// Quotes a word so that it tokenizes back to itself: a word of only
+/// letters, digits and `-_./:,+=@%` is left as it is, and any other is
+/// put in single quotes.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::parser::{quote, tokenize};
+/// assert_eq!(quote("notes.txt"), "notes.txt");
+/// assert_eq!(quote("it's here"), "'it'\\''s here'");
+/// assert_eq!(tokenize(&quote("it's here")), vec!["it's here"]);
+/// assert_eq!(quote(""), "''");
+/// ```
+pub fn quote(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./:,+=@%".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        return word.to_string();
+    }
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+/// Why input is not complete yet and continues on the next line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Continuation {
+    /// A quote is still open, so the newline is part of the quoted text.
+    Quote(char),
+    /// The input ends with a backslash, which joins it to the next line.
+    Backslash,
+}
+
+/// Checks whether input needs another line before it can run: because of
+/// an unterminated quote, or a trailing backslash outside single quotes.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::parser::{Continuation, continuation};
+/// assert_eq!(continuation("echo 'it"), Some(Continuation::Quote('\'')));
+/// assert_eq!(continuation("echo a \\"), Some(Continuation::Backslash));
+/// assert_eq!(continuation("echo 'a' \"b\""), None);
+/// ```
+pub fn continuation(input: &str) -> Option<Continuation> {
+    let mut quote = None;
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        // A backslash escapes the next character, except in single quotes
+        if c == '\\' && quote != Some('\'') {
+            if chars.next().is_none() {
+                return Some(Continuation::Backslash);
+            }
+            continue;
+        }
+        match (quote, c) {
+            (Some(open), _) if c == open => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            _ => {}
+        }
+    }
+    quote.map(Continuation::Quote)
+}
+
+/// What a span of the input line is, for syntax highlighting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HighlightKind {
+    /// The command name at the start of a pipeline segment.
+    Command,
+    /// A single- or double-quoted string in an argument.
+    Quoted,
+    /// A pipe, `&`, `;` or redirection operator.
+    Operator,
+}
+
+/// Finds the spans of an input line worth highlighting, as byte ranges in
+/// order.
+///
+/// Unlike `tokenize`, this keeps the positions of quotes and operators, and
+/// it tolerates an unterminated quote since the line may still be typed.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::parser::{HighlightKind, highlight};
+/// let spans = highlight("ls | grep 'x'");
+/// assert_eq!(
+///     spans,
+///     vec![
+///         (0..2, HighlightKind::Command),
+///         (3..4, HighlightKind::Operator),
+///         (5..9, HighlightKind::Command),
+///         (10..13, HighlightKind::Quoted),
+///     ]
+/// );
+/// ```
+pub fn highlight(input: &str) -> Vec<(Range<usize>, HighlightKind)> {
+    let mut spans = Vec::new();
+    let mut command_position = true;
+    let mut i = 0;
+
+    while let Some(c) = input[i..].chars().next() {
+        if c.is_whitespace() {
+            i += c.len_utf8();
+            continue;
+        }
+
+        // Operators, including redirections with a file descriptor (`2>`)
+        let digits = input[i..].len()
+            - input[i..]
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .len();
+        if input[i + digits..].starts_with(is_operator_char) {
+            let start = i;
+            i += digits;
+            i += input[i..].len() - input[i..].trim_start_matches(is_operator_char).len();
+            let operator = &input[start..i];
+            if !operator.contains(['<', '>']) {
+                // A new command follows `|`, `;`, `&` and `&&`
+                command_position = true;
+            }
+            spans.push((start..i, HighlightKind::Operator));
+            continue;
+        }
+
+        // A word, which may contain quoted parts
+        let start = i;
+        let mut quoted = Vec::new();
+        while let Some(c) = input[i..].chars().next() {
+            if c.is_whitespace() || is_operator_char(c) {
+                break;
+            }
+            match c {
+                '\'' | '"' => {
+                    let quote_start = i;
+                    i += 1;
+                    while let Some(q) = input[i..].chars().next() {
+                        i += q.len_utf8();
+                        if q == c {
+                            break;
+                        }
+                        if q == '\\'
+                            && c == '"'
+                            && let Some(escaped) = input[i..].chars().next()
+                        {
+                            i += escaped.len_utf8();
+                        }
+                    }
+                    quoted.push((quote_start..i, HighlightKind::Quoted));
+                }
+                '\\' => {
+                    i += 1;
+                    if let Some(escaped) = input[i..].chars().next() {
+                        i += escaped.len_utf8();
+                    }
+                }
+                _ => i += c.len_utf8(),
+            }
+        }
+        // Assignments before a command leave it in command position
+        let assignment = input[start..i]
+            .split_once('=')
+            .is_some_and(|(name, _)| is_name(name));
+        if command_position && !assignment {
+            spans.push((start..i, HighlightKind::Command));
+            command_position = false;
+        } else {
+            spans.extend(quoted);
+        }
+    }
+    spans
+}
+
+/// Returns true for characters that form shell operators.
+fn is_operator_char(c: char) -> bool {
+    matches!(c, '|' | '&' | ';' | '<' | '>')
+}
@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The version of the plugin interface. A plugin's init function is given
+/// it in [`Registrar::version`] and should fail if it doesn't know it.
+pub const API_VERSION: u32 = 1;
+
+/// The symbol a plugin library exports as its [`InitFn`].
+pub const INIT_SYMBOL: &str = "codecrafters_shell_plugin_init";
+
+/// Where a plugin writes output: `write` called with `context` and the
+/// bytes to write.
+#[repr(C)]
+pub struct Output {
+    pub context: *mut c_void,
+    pub write: unsafe extern "C" fn(context: *mut c_void, data: *const u8, len: usize),
+}
+
+/// A builtin provided by a plugin. It is given its arguments, starting
+/// with its own name as `argv[0]`, and the streams to write to, and
+/// returns its exit status.
+pub type BuiltinFn = unsafe extern "C" fn(
+    argc: usize,
+    argv: *const *const c_char,
+    stdout: *const Output,
+    stderr: *const Output,
+) -> i32;
+
+/// A completer provided by a plugin for the arguments of a command. It is
+/// given the word being completed and writes the candidates to `out`, one
+/// per line.
+pub type CompleterFn = unsafe extern "C" fn(word: *const c_char, out: *const Output);
+
+/// A prompt segment provided by a plugin. It writes text to show before
+/// the primary prompt to `out`.
+pub type PromptSegmentFn = unsafe extern "C" fn(out: *const Output);
+
+/// What a plugin's init function is given to register what it provides.
+/// This is all of the shell a plugin sees.
+#[repr(C)]
+pub struct Registrar {
+    pub version: u32,
+    pub context: *mut c_void,
+    pub register_builtin:
+        unsafe extern "C" fn(context: *mut c_void, name: *const c_char, run: BuiltinFn),
+    pub register_completer:
+        unsafe extern "C" fn(context: *mut c_void, command: *const c_char, complete: CompleterFn),
+    pub register_prompt_segment: unsafe extern "C" fn(context: *mut c_void, show: PromptSegmentFn),
+}
+
+/// The function a plugin exports as [`INIT_SYMBOL`]. It registers what the
+/// plugin provides and returns 0, or anything else if it can't be loaded.
+///
+/// # Example
+/// A plugin built as a `cdylib` providing a `hello` builtin:
+/// ```no_run
+/// use codecrafters_shell::plugin::{API_VERSION, Output, Registrar};
+/// use std::ffi::c_char;
+///
+/// unsafe extern "C" fn hello(
+///     _argc: usize,
+///     _argv: *const *const c_char,
+///     stdout: *const Output,
+///     _stderr: *const Output,
+/// ) -> i32 {
+///     let message = "hello from a plugin\n";
+///     unsafe { ((*stdout).write)((*stdout).context, message.as_ptr(), message.len()) };
+///     0
+/// }
+///
+/// #[unsafe(no_mangle)]
+/// pub unsafe extern "C" fn codecrafters_shell_plugin_init(registrar: *const Registrar) -> i32 {
+///     let registrar = unsafe { &*registrar };
+///     if registrar.version != API_VERSION {
+///         return 1;
+///     }
+///     unsafe { (registrar.register_builtin)(registrar.context, c"hello".as_ptr(), hello) };
+///     0
+/// }
+/// ```
+pub type InitFn = unsafe extern "C" fn(registrar: *const Registrar) -> i32;
+
+/// The builtins, completers and prompt segments of the plugins loaded with
+/// `plugin load`.
+///
+/// Plugin libraries stay loaded for the life of the shell. Clones share
+/// what the plugins registered until another plugin is loaded.
+#[derive(Debug, Clone, Default)]
+pub struct Plugins {
+    loaded: Arc<Vec<PathBuf>>,
+    builtins: Arc<BTreeMap<String, BuiltinFn>>,
+    completers: Arc<BTreeMap<String, CompleterFn>>,
+    prompt_segments: Arc<Vec<PromptSegmentFn>>,
+}
+
+impl Plugins {
+    /// Loads a plugin library and registers what it provides.
+    ///
+    /// Returns an error message if the library can't be loaded, doesn't
+    /// export [`INIT_SYMBOL`], or its init function fails. Nothing is
+    /// registered then.
+    pub fn load(&mut self, path: &Path) -> Result<(), String> {
+        let path_c = CString::new(path.as_os_str().as_encoded_bytes())
+            .map_err(|_| "invalid library path".to_string())?;
+        // SAFETY: loading a library runs its initializers; loading plugins
+        // is trusted as running a command is
+        let handle = unsafe { libc::dlopen(path_c.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            return Err(dl_error());
+        }
+        let symbol = CString::new(INIT_SYMBOL).unwrap_or_default();
+        let init = unsafe { libc::dlsym(handle, symbol.as_ptr()) };
+        if init.is_null() {
+            unsafe { libc::dlclose(handle) };
+            return Err(format!("{}: not a shell plugin", INIT_SYMBOL));
+        }
+        // SAFETY: plugins export INIT_SYMBOL with the InitFn signature
+        let init: InitFn = unsafe { std::mem::transmute::<*mut c_void, InitFn>(init) };
+
+        let mut registered = Plugins::default();
+        let registrar = Registrar {
+            version: API_VERSION,
+            context: &mut registered as *mut Plugins as *mut c_void,
+            register_builtin,
+            register_completer,
+            register_prompt_segment,
+        };
+        if unsafe { init(&registrar) } != 0 {
+            unsafe { libc::dlclose(handle) };
+            return Err("plugin failed to initialize".to_string());
+        }
+
+        Arc::make_mut(&mut self.loaded).push(path.to_path_buf());
+        Arc::make_mut(&mut self.builtins).extend(Arc::unwrap_or_clone(registered.builtins));
+        Arc::make_mut(&mut self.completers).extend(Arc::unwrap_or_clone(registered.completers));
+        Arc::make_mut(&mut self.prompt_segments)
+            .extend(Arc::unwrap_or_clone(registered.prompt_segments));
+        Ok(())
+    }
+
+    /// Returns the paths of the loaded plugins, in the order they were
+    /// loaded.
+    pub fn loaded(&self) -> &[PathBuf] {
+        &self.loaded
+    }
+
+    /// Returns the names of the builtins plugins provide, sorted.
+    pub fn builtin_names(&self) -> impl Iterator<Item = &str> {
+        self.builtins.keys().map(|name| name.as_str())
+    }
+
+    /// Checks whether a plugin provides the builtin `name`.
+    pub fn has_builtin(&self, name: &str) -> bool {
+        self.builtins.contains_key(name)
+    }
+
+    /// Runs the plugin builtin `name`, returning its exit status, or
+    /// `None` if no plugin provides it.
+    pub fn run_builtin(
+        &self,
+        name: &str,
+        args: &[String],
+        stdout: &mut dyn Write,
+        stderr: &mut dyn Write,
+    ) -> Option<i32> {
+        let run = self.builtins.get(name)?;
+        // Arguments with a NUL byte can't be passed to C; like execve,
+        // they are cut short there
+        let argv: Vec<CString> = std::iter::once(name)
+            .chain(args.iter().map(|arg| arg.as_str()))
+            .map(|arg| CString::new(arg.split('\0').next().unwrap_or_default()).unwrap_or_default())
+            .collect();
+        let pointers: Vec<*const c_char> = argv.iter().map(|arg| arg.as_ptr()).collect();
+        let mut stdout = stdout;
+        let mut stderr = stderr;
+        let out = output(&mut stdout);
+        let err = output(&mut stderr);
+        Some(unsafe { run(pointers.len(), pointers.as_ptr(), &out, &err) })
+    }
+
+    /// Returns the candidates a plugin completer offers for `word` as an
+    /// argument of `command`, or `None` if no plugin completes it.
+    pub fn complete(&self, command: &str, word: &str) -> Option<Vec<String>> {
+        let complete = self.completers.get(command)?;
+        let word = CString::new(word).ok()?;
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut writer: &mut dyn Write = &mut buffer;
+        let out = output(&mut writer);
+        unsafe { complete(word.as_ptr(), &out) };
+        let text = String::from_utf8_lossy(&buffer);
+        Some(text.lines().map(String::from).collect())
+    }
+
+    /// Returns the text the plugin prompt segments show before the primary
+    /// prompt.
+    pub fn prompt_segments(&self) -> String {
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut writer: &mut dyn Write = &mut buffer;
+        for show in self.prompt_segments.iter() {
+            let out = output(&mut writer);
+            unsafe { show(&out) };
+        }
+        String::from_utf8_lossy(&buffer).into_owned()
+    }
+}
+
+/// Wraps a writer as an [`Output`] for a plugin. The output must not
+/// outlive the writer.
+fn output(writer: &mut &mut dyn Write) -> Output {
+    Output {
+        context: writer as *mut &mut dyn Write as *mut c_void,
+        write: write_output,
+    }
+}
+
+unsafe extern "C" fn write_output(context: *mut c_void, data: *const u8, len: usize) {
+    if data.is_null() {
+        return;
+    }
+    let writer = unsafe { &mut *(context as *mut &mut dyn Write) };
+    let bytes = unsafe { std::slice::from_raw_parts(data, len) };
+    let _ = writer.write_all(bytes);
+}
+
+/// Returns the registrations a plugin's init function is adding to.
+unsafe fn registered<'a>(context: *mut c_void) -> &'a mut Plugins {
+    unsafe { &mut *(context as *mut Plugins) }
+}
+
+/// Reads a name given by a plugin, which must be valid UTF-8.
+unsafe fn name(name: *const c_char) -> Option<String> {
+    if name.is_null() {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr(name) };
+    name.to_str().ok().map(String::from)
+}
+
+unsafe extern "C" fn register_builtin(context: *mut c_void, name_c: *const c_char, run: BuiltinFn) {
+    if let Some(name) = unsafe { name(name_c) } {
+        Arc::make_mut(&mut unsafe { registered(context) }.builtins).insert(name, run);
+    }
+}
+
+unsafe extern "C" fn register_completer(
+    context: *mut c_void,
+    command: *const c_char,
+    complete: CompleterFn,
+) {
+    if let Some(command) = unsafe { name(command) } {
+        Arc::make_mut(&mut unsafe { registered(context) }.completers).insert(command, complete);
+    }
+}
+
+unsafe extern "C" fn register_prompt_segment(context: *mut c_void, show: PromptSegmentFn) {
+    Arc::make_mut(&mut unsafe { registered(context) }.prompt_segments).push(show);
+}
+
+/// Returns the message for the last failed `dlopen`.
+fn dl_error() -> String {
+    let message = unsafe { libc::dlerror() };
+    if message.is_null() {
+        return "cannot load library".to_string();
+    }
+    unsafe { CStr::from_ptr(message) }
+        .to_string_lossy()
+        .into_owned()
+}
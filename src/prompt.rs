@@ -0,0 +1,390 @@
+use crate::Shell;
+use crate::variables::Variables;
+use std::env;
+use std::ffi::CStr;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// The prompt shown when `PS1` is not set.
+pub const DEFAULT_PS1: &str = "$ ";
+
+/// The prompt shown when `PS1` is not set with `set -o dirprompt`: the
+/// current directory, then `$`.
+pub const DIR_PS1: &str = r"\w \$ ";
+
+/// The prompt for continuation lines when `PS2` is not set.
+pub const DEFAULT_PS2: &str = "> ";
+
+/// How long the prompt waits for `git status` before leaving out the
+/// dirty marker, so a large repository doesn't hold up the prompt.
+pub const GIT_STATUS_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// OSC 133 mark written before the prompt, telling the terminal a new
+/// command starts here.
+pub const PROMPT_START_MARK: &str = "\x1b]133;A\x07";
+/// OSC 133 mark written after the prompt, where the command line begins.
+pub const INPUT_START_MARK: &str = "\x1b]133;B\x07";
+/// OSC 133 mark written when a command line is accepted, before its
+/// output.
+pub const OUTPUT_START_MARK: &str = "\x1b]133;C\x07";
+
+/// Marks the start of prompt text that takes no space on screen, written
+/// for `\[` as readline does.
+pub const START_IGNORE: char = '\x01';
+/// Marks the end of prompt text that takes no space on screen (`\]`).
+pub const END_IGNORE: char = '\x02';
+
+/// Returns the primary prompt: the `PS1` variable with its escapes
+/// expanded, or [`DEFAULT_PS1`] if it is not set. [`DIR_PS1`] is used
+/// instead with `set -o dirprompt`.
+///
+/// With `set -o gitprompt` inside a git repository, the branch is shown
+/// after the directory of the default prompt, or before a custom `PS1`.
+/// Prompt segments from plugins come before everything else.
+pub fn primary_prompt(shell: &Shell) -> String {
+    let git = if shell.options.git_prompt {
+        git_segment(shell)
+    } else {
+        None
+    };
+    let prompt = match (shell.variables.get("PS1"), git) {
+        (Some(ps1), None) => expand_prompt(&ps1, shell),
+        (Some(ps1), Some(git)) => format!("({}) {}", git, expand_prompt(&ps1, shell)),
+        (None, None) if shell.options.dir_prompt => expand_prompt(DIR_PS1, shell),
+        (None, None) => DEFAULT_PS1.to_string(),
+        (None, Some(git)) => format!(
+            "{} ({}) {}",
+            working_directory(false, shell),
+            git,
+            expand_prompt(r"\$ ", shell)
+        ),
+    };
+    // Plugin prompt segments go first
+    format!("{}{}", shell.plugins.prompt_segments(), prompt)
+}
+
+/// Describes the git repository containing the shell's working directory:
+/// the
+/// branch name (or abbreviated commit when `HEAD` is detached), followed
+/// by `*` if the work tree has changes. Returns `None` outside a
+/// repository.
+///
+/// The branch is read from `.git/HEAD` directly. Changes are found by
+/// running `git status --porcelain`, which is abandoned after
+/// [`GIT_STATUS_TIMEOUT`].
+pub fn git_segment(shell: &Shell) -> Option<String> {
+    let git_dir = find_git_dir(&shell.cwd)?;
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    let mut segment = match head.strip_prefix("ref: ") {
+        Some(reference) => reference
+            .strip_prefix("refs/heads/")
+            .unwrap_or(reference)
+            .to_string(),
+        None => head.chars().take(7).collect(),
+    };
+    if git_is_dirty(shell) == Some(true) {
+        segment.push('*');
+    }
+    Some(segment)
+}
+
+/// Finds the git directory for `dir` or its nearest ancestor that has a
+/// `.git`, following the `gitdir:` file used by worktrees and submodules.
+fn find_git_dir(dir: &Path) -> Option<PathBuf> {
+    for ancestor in dir.ancestors() {
+        let dot_git = ancestor.join(".git");
+        if dot_git.is_dir() {
+            return Some(dot_git);
+        }
+        if dot_git.is_file() {
+            let contents = fs::read_to_string(&dot_git).ok()?;
+            let target = contents.trim().strip_prefix("gitdir: ")?;
+            return Some(ancestor.join(target));
+        }
+    }
+    None
+}
+
+/// Checks whether the work tree has changes, or `None` if `git` can't be
+/// run or doesn't answer within [`GIT_STATUS_TIMEOUT`]. It runs in the
+/// shell's working directory and environment, as if the user had typed it.
+fn git_is_dirty(shell: &Shell) -> Option<bool> {
+    let mut child = Command::new("git")
+        .args(["status", "--porcelain"])
+        .current_dir(&*shell.cwd)
+        .env_clear()
+        .envs(shell.variables.exported())
+        // Don't take the index lock, which could get in the way of a git
+        // command the user runs next
+        .env("GIT_OPTIONAL_LOCKS", "0")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let mut stdout = child.stdout.take()?;
+
+    // Any output at all means changes, so one byte is enough
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut byte = [0u8; 1];
+        let _ = sender.send(stdout.read(&mut byte).ok().map(|len| len > 0));
+    });
+    let dirty = receiver.recv_timeout(GIT_STATUS_TIMEOUT).ok().flatten();
+    let _ = child.kill();
+    let status = child.wait().ok()?;
+    // A clean tree is only known once git has exited successfully
+    match dirty {
+        Some(false) if !status.success() => None,
+        dirty => dirty,
+    }
+}
+
+/// Wraps a prompt in the OSC 133 (FinalTerm) marks for its start and the
+/// start of the input after it, so that terminals such as WezTerm, Kitty
+/// and iTerm2 can tell prompts apart from command output.
+///
+/// The marks are escape sequences that take no space on screen, placed
+/// between `\[` and `\]` markers.
+pub fn mark_prompt(prompt: &str) -> String {
+    format!(
+        "{start}{}{end}{}{start}{}{end}",
+        PROMPT_START_MARK,
+        prompt,
+        INPUT_START_MARK,
+        start = START_IGNORE,
+        end = END_IGNORE,
+    )
+}
+
+/// Returns the OSC 133 mark written when a command has finished with
+/// `status`, which terminals show beside the command, e.g. to flag
+/// failures.
+///
+/// ```
+/// use codecrafters_shell::prompt::command_end_mark;
+///
+/// assert_eq!(command_end_mark(127), "\x1b]133;D;127\x07");
+/// ```
+pub fn command_end_mark(status: i32) -> String {
+    format!("\x1b]133;D;{}\x07", status)
+}
+
+/// Returns the secondary prompt, shown while a command continues over
+/// several lines: the `PS2` variable with its escapes expanded, or `> ` if
+/// it is not set.
+pub fn secondary_prompt(shell: &Shell) -> String {
+    match shell.variables.get("PS2") {
+        Some(ps2) => expand_prompt(&ps2, shell),
+        None => DEFAULT_PS2.to_string(),
+    }
+}
+
+/// Expands the backslash escapes of a bash-style prompt string.
+///
+/// Supports `\u` (user), `\h` and `\H` (host name), `\w` and `\W` (current
+/// directory, with `$HOME` shown as `~`), `\$` (`#` for root, otherwise
+/// `$`), the time and date escapes `\t`, `\T`, `\@`, `\A`, `\d` and
+/// `\D{format}`, `\s` and `\v`, `\n`, `\r`, `\a`, `\e`, `\\`, octal
+/// `\nnn`, and `\[`...`\]` around non-printing sequences such as colors.
+/// Other escapes are kept as written.
+///
+/// ```
+/// use codecrafters_shell::Shell;
+/// use codecrafters_shell::prompt::expand_prompt;
+///
+/// let shell = Shell::default();
+/// assert_eq!(expand_prompt(r"\e[1m>\e[0m ", &shell), "\x1b[1m>\x1b[0m ");
+/// ```
+pub fn expand_prompt(template: &str, shell: &Shell) -> String {
+    let variables = &shell.variables;
+    let mut prompt = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            prompt.push(c);
+            continue;
+        }
+        let Some(escape) = chars.next() else {
+            prompt.push('\\');
+            break;
+        };
+        match escape {
+            'u' => prompt.push_str(&user_name(variables)),
+            'h' => prompt.push_str(host_name().split('.').next().unwrap_or_default()),
+            'H' => prompt.push_str(&host_name()),
+            'w' => prompt.push_str(&working_directory(false, shell)),
+            'W' => prompt.push_str(&working_directory(true, shell)),
+            '$' => {
+                let root = unsafe { libc::geteuid() } == 0;
+                prompt.push(if root { '#' } else { '$' });
+            }
+            't' => prompt.push_str(&format_time("%H:%M:%S")),
+            'T' => prompt.push_str(&format_time("%I:%M:%S")),
+            '@' => prompt.push_str(&format_time("%I:%M %p")),
+            'A' => prompt.push_str(&format_time("%H:%M")),
+            'd' => prompt.push_str(&format_time("%a %b %d")),
+            'D' if chars.peek() == Some(&'{') => {
+                chars.next();
+                let format: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                // An empty format means the locale's time
+                let format = if format.is_empty() { "%X" } else { &format };
+                prompt.push_str(&format_time(format));
+            }
+            's' => prompt.push_str(&shell_name()),
+            'v' | 'V' => prompt.push_str(env!("CARGO_PKG_VERSION")),
+            'n' => prompt.push('\n'),
+            'r' => prompt.push('\r'),
+            'a' => prompt.push('\x07'),
+            'e' => prompt.push('\x1b'),
+            '\\' => prompt.push('\\'),
+            '[' => prompt.push(START_IGNORE),
+            ']' => prompt.push(END_IGNORE),
+            '0'..='7' => {
+                let mut code = escape.to_digit(8).unwrap_or_default();
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            code = code * 8 + digit;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                prompt.extend(char::from_u32(code));
+            }
+            other => {
+                prompt.push('\\');
+                prompt.push(other);
+            }
+        }
+    }
+    prompt
+}
+
+/// Returns the name of the user running the shell.
+fn user_name(variables: &Variables) -> String {
+    if let Some(user) = variables.get("USER") {
+        return user;
+    }
+    unsafe {
+        let passwd = libc::getpwuid(libc::geteuid());
+        if passwd.is_null() {
+            return String::new();
+        }
+        CStr::from_ptr((*passwd).pw_name)
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// Returns the machine's host name.
+fn host_name() -> String {
+    let mut buffer = [0u8; 256];
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) };
+    if result != 0 {
+        return String::new();
+    }
+    CStr::from_bytes_until_nul(&buffer)
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Returns the shell's working directory with `$HOME` abbreviated to `~`,
+/// or just its last component if `basename` is set.
+///
+/// Like bash, a positive `PROMPT_DIRTRIM` keeps only that many trailing
+/// components of the full form, replacing the rest with `...`.
+fn working_directory(basename: bool, shell: &Shell) -> String {
+    let (cwd, variables) = (&shell.cwd, &shell.variables);
+    if let Some(home) = variables.get("HOME")
+        && let Ok(rest) = cwd.strip_prefix(&home)
+    {
+        if rest.as_os_str().is_empty() {
+            return "~".to_string();
+        }
+        if !basename {
+            let trim = prompt_dirtrim(variables);
+            return shorten_path(&Path::new("~").join(rest).display().to_string(), trim);
+        }
+    }
+    match cwd.file_name() {
+        Some(name) if basename => name.to_string_lossy().into_owned(),
+        _ => shorten_path(&cwd.display().to_string(), prompt_dirtrim(variables)),
+    }
+}
+
+/// Returns the number of directory components `\w` keeps, from
+/// `PROMPT_DIRTRIM`; zero means the whole path.
+fn prompt_dirtrim(variables: &Variables) -> usize {
+    variables
+        .get("PROMPT_DIRTRIM")
+        .and_then(|trim| trim.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Shortens a path to its last `keep` components after a leading `~` or
+/// `/`, e.g. `~/.../src/bin` for two. Paths that are already short enough
+/// are returned as they are.
+///
+/// ```
+/// use codecrafters_shell::prompt::shorten_path;
+///
+/// assert_eq!(shorten_path("~/code/shell/src/bin", 2), "~/.../src/bin");
+/// assert_eq!(shorten_path("/usr/local/lib", 2), "/.../local/lib");
+/// assert_eq!(shorten_path("/usr/lib", 2), "/usr/lib");
+/// ```
+pub fn shorten_path(path: &str, keep: usize) -> String {
+    let (root, rest) = match path.strip_prefix('~') {
+        Some(rest) => ("~", rest),
+        None => ("", path),
+    };
+    let components: Vec<&str> = rest.split('/').filter(|part| !part.is_empty()).collect();
+    if keep == 0 || components.len() <= keep {
+        return path.to_string();
+    }
+    format!(
+        "{}/.../{}",
+        root,
+        components[components.len() - keep..].join("/")
+    )
+}
+
+/// Returns the name the shell was started as, without its directory.
+fn shell_name() -> String {
+    env::args()
+        .next()
+        .as_deref()
+        .map(Path::new)
+        .and_then(Path::file_name)
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Formats the current local time with `strftime`.
+fn format_time(format: &str) -> String {
+    let Ok(format) = std::ffi::CString::new(format) else {
+        return String::new();
+    };
+    let mut buffer = [0u8; 256];
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut local: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut local).is_null() {
+            return String::new();
+        }
+        let len = libc::strftime(
+            buffer.as_mut_ptr().cast(),
+            buffer.len(),
+            format.as_ptr(),
+            &local,
+        );
+        String::from_utf8_lossy(&buffer[..len]).into_owned()
+    }
+}
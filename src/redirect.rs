@@ -0,0 +1,255 @@
+use crate::error::ShellError;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::FromRawFd;
+use std::path::Path;
+
+/// A single redirection operator parsed from a command line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Redirection {
+    /// `[n]>file` or `[n]>>file`: send `fd` to a file.
+    File { fd: i32, path: String, append: bool },
+    /// `[n]>&m` or `[n]<&m`: make `fd` a copy of whatever `target`
+    /// currently points to, which may be any of the shell's descriptors,
+    /// such as a coprocess's.
+    Duplicate { fd: i32, target: i32 },
+}
+
+impl fmt::Display for Redirection {
+    /// Writes the redirection as it would be typed, e.g. `2>> errors.log`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fd = |fd: i32| {
+            if fd == 1 {
+                String::new()
+            } else {
+                fd.to_string()
+            }
+        };
+        match self {
+            Redirection::File {
+                fd: n,
+                path,
+                append,
+            } => {
+                let operator = if *append { ">>" } else { ">" };
+                write!(f, "{}{} {}", fd(*n), operator, crate::parser::quote(path))
+            }
+            Redirection::Duplicate { fd: 0, target } => write!(f, "<&{}", target),
+            Redirection::Duplicate { fd: n, target } => write!(f, "{}>&{}", fd(*n), target),
+        }
+    }
+}
+
+/// A redirection target that could not be opened.
+///
+/// Displays like bash, e.g. `out.txt: Permission denied`.
+#[derive(Debug, thiserror::Error)]
+#[error("{target}: {}", describe_io_error(.source))]
+pub struct RedirectionError {
+    /// The file name, or file descriptor number, that failed.
+    pub target: String,
+    pub source: io::Error,
+}
+
+/// The resolved sources and destinations of a command's stdin, stdout and
+/// stderr.
+///
+/// `None` means the stream is inherited from the shell.
+#[derive(Default)]
+pub struct StdioTargets {
+    pub stdin: Option<File>,
+    pub stdout: Option<File>,
+    pub stderr: Option<File>,
+}
+
+impl StdioTargets {
+    /// Returns the stream `fd` was redirected to, or `None` if it wasn't
+    /// or isn't one of the three.
+    fn get(&self, fd: i32) -> Option<&File> {
+        match fd {
+            0 => self.stdin.as_ref(),
+            1 => self.stdout.as_ref(),
+            2 => self.stderr.as_ref(),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, fd: i32, file: File) {
+        match fd {
+            0 => self.stdin = Some(file),
+            1 => self.stdout = Some(file),
+            _ => self.stderr = Some(file),
+        }
+    }
+}
+
+/// Separates redirection operators and their targets from the remaining
+/// command arguments, preserving the order in which they appeared.
+///
+/// Supports `>`, `>>`, `1>`, `1>>`, `2>`, `2>>` followed by a filename, and
+/// the duplications `[n]>&m` and `[n]<&m` (see [`parse_duplication`]).
+///
+/// An operator without a filename after it is a syntax error, as in bash,
+/// returned as its message.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::parse_redirections;
+///
+/// let words = |line: &str| line.split(' ').map(String::from).collect::<Vec<_>>();
+/// let (args, redirections) = parse_redirections(words("echo hi > out")).unwrap();
+/// assert_eq!(args, words("echo hi"));
+/// assert_eq!(redirections.len(), 1);
+/// assert!(parse_redirections(words("echo hi >")).is_err());
+/// let (_, redirections) = parse_redirections(words("cat <&5 2>&1")).unwrap();
+/// assert_eq!(redirections.len(), 2);
+/// ```
+pub fn parse_redirections(
+    args: Vec<String>,
+) -> Result<(Vec<String>, Vec<Redirection>), ShellError> {
+    let mut clean_args = Vec::new();
+    let mut redirections = Vec::new();
+    let mut args_iter = args.into_iter();
+
+    while let Some(arg) = args_iter.next() {
+        let (fd, append) = match arg.as_str() {
+            ">" | "1>" => (1, false),
+            ">>" | "1>>" => (1, true),
+            "2>" => (2, false),
+            "2>>" => (2, true),
+            _ if let Some(duplication) = parse_duplication(&arg) => {
+                redirections.push(duplication);
+                continue;
+            }
+            _ => {
+                clean_args.push(arg);
+                continue;
+            }
+        };
+
+        let token = match args_iter.next() {
+            Some(path) if !is_operator(&path) => {
+                redirections.push(Redirection::File { fd, path, append });
+                continue;
+            }
+            Some(operator) => operator,
+            None => "newline".to_string(),
+        };
+        return Err(ShellError::ParseError(format!(
+            "syntax error near unexpected token `{}'",
+            token
+        )));
+    }
+
+    Ok((clean_args, redirections))
+}
+
+/// Checks whether a word is one of the redirection operators
+/// [`parse_redirections`] understands.
+fn is_operator(word: &str) -> bool {
+    matches!(word, ">" | "1>" | ">>" | "1>>" | "2>" | "2>>") || parse_duplication(word).is_some()
+}
+
+/// Parses a duplication: `[n]>&m` makes output `n` (stdout if left out) a
+/// copy of descriptor `m`, and `[n]<&m` does the same for input `n`
+/// (stdin if left out). Only the standard streams can be redirected.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::Redirection;
+/// use codecrafters_shell::redirect::parse_duplication;
+///
+/// assert_eq!(parse_duplication("2>&1"), Some(Redirection::Duplicate { fd: 2, target: 1 }));
+/// assert_eq!(parse_duplication("<&5"), Some(Redirection::Duplicate { fd: 0, target: 5 }));
+/// assert_eq!(parse_duplication("7>&1"), None);
+/// assert_eq!(parse_duplication(">&file"), None);
+/// ```
+pub fn parse_duplication(word: &str) -> Option<Redirection> {
+    let (fd, target, default) = match word.split_once(">&") {
+        Some((fd, target)) => (fd, target, 1),
+        None => {
+            let (fd, target) = word.split_once("<&")?;
+            (fd, target, 0)
+        }
+    };
+    let number = |digits: &str| {
+        (!digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+            .then(|| digits.parse::<i32>().ok())
+            .flatten()
+    };
+    let fd = if fd.is_empty() { default } else { number(fd)? };
+    if !(0..=2).contains(&fd) {
+        return None;
+    }
+    Some(Redirection::Duplicate {
+        fd,
+        target: number(target)?,
+    })
+}
+
+/// Applies redirections left-to-right, like bash.
+///
+/// `stdio` holds where the streams go before any redirection is applied,
+/// such as the write end of a pipe; `None` is the shell's own descriptor,
+/// which a duplication then copies. Every file target is opened, so files
+/// named by earlier redirections are still created or truncated even if a
+/// later one overrides them. Relative paths are taken from `cwd`, the
+/// shell's working directory. Stops at the first target that cannot be
+/// opened.
+pub fn open_redirections(
+    redirections: &[Redirection],
+    stdio: StdioTargets,
+    cwd: &Path,
+) -> Result<StdioTargets, RedirectionError> {
+    let mut targets = stdio;
+
+    for redirection in redirections {
+        match redirection {
+            Redirection::File { fd, path, append } => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .append(*append)
+                    .truncate(!*append)
+                    .open(cwd.join(path))
+                    .map_err(|source| RedirectionError {
+                        target: path.clone(),
+                        source,
+                    })?;
+                targets.set(*fd, file);
+            }
+            Redirection::Duplicate { fd, target } => {
+                let file = match targets.get(*target) {
+                    Some(file) => file.try_clone(),
+                    None => duplicate_fd(*target),
+                }
+                .map_err(|source| RedirectionError {
+                    target: target.to_string(),
+                    source,
+                })?;
+                targets.set(*fd, file);
+            }
+        }
+    }
+
+    Ok(targets)
+}
+
+/// Duplicates one of the shell's own file descriptors as a close-on-exec `File`.
+fn duplicate_fd(fd: i32) -> io::Result<File> {
+    let copy = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+    if copy == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(copy) })
+}
+
+/// Formats an I/O error the way bash does, without Rust's `(os error N)` suffix.
+pub fn describe_io_error(error: &io::Error) -> String {
+    let message = error.to_string();
+    match message.find(" (os error") {
+        Some(end) => message[..end].to_string(),
+        None => message,
+    }
+}
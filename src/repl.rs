@@ -0,0 +1,1092 @@
+use crate::clipboard;
+use crate::completion::{self, CompletionContext, ListColors, Menu};
+use crate::editor::{Commands, LineEditor, ViAction, ViMode};
+use crate::error::{self, ShellError};
+use crate::history::{self, HistControl, History};
+use crate::keymap::{Binding, EditCommand, Lookup};
+use crate::parser::{self, Continuation};
+use crate::shell::{CommandLine, Shell};
+use crate::{ShellStatus, executables, hooks, jump, prompt, signals, terminal};
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    sync::Arc,
+    sync::atomic::{AtomicBool, Ordering},
+    sync::mpsc::{self, RecvTimeoutError, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+use termion::event::Key;
+
+/// Finds the completions of the text before the cursor: those of the word
+/// being typed or, failing that, previous command lines extending it.
+fn find_matches(line: &str, shell: &Shell) -> (CompletionContext, Vec<String>) {
+    let context = CompletionContext::parse(line);
+    let mut matches = context.candidates(shell);
+    if matches.is_empty() && shell.options.fuzzy_complete {
+        matches = context.fuzzy_candidates(shell);
+    }
+    if matches.is_empty() {
+        let context = CompletionContext::whole_line(line);
+        let matches = context.history_candidates(&shell.history);
+        return (context, matches);
+    }
+    (context, matches)
+}
+
+/// How long Tab waits for completions, so that a slow `PATH` directory
+/// such as a network mount can't freeze the editor.
+const COMPLETION_DEADLINE: Duration = Duration::from_millis(500);
+
+/// A worker thread scanning the `PATH` for the executable index that
+/// completion needs.
+struct IndexWorker {
+    /// The `PATH` being checked.
+    path: String,
+    /// Set to stop a scan, when the `PATH` changes before it is done.
+    cancel: Arc<AtomicBool>,
+    /// Receives a message, or is disconnected, when the index is up to date.
+    done: mpsc::Receiver<()>,
+}
+
+impl IndexWorker {
+    /// Starts checking the index against `path` and its directories, and
+    /// rescanning them if they changed.
+    fn start(path: String) -> Self {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (sender, done) = mpsc::channel();
+        let worker = IndexWorker {
+            path: path.clone(),
+            cancel: Arc::clone(&cancel),
+            done,
+        };
+        thread::spawn(move || {
+            executables::update_index(&path, &cancel);
+            let _ = sender.send(());
+        });
+        worker
+    }
+
+    /// Makes sure `worker` is bringing the index up to date with `path`,
+    /// cancelling a scan of a `PATH` that has since changed.
+    ///
+    /// The directories are only ever read by the worker, so the line editor
+    /// never waits on a slow one.
+    fn keep_current(worker: &mut Option<IndexWorker>, path: String) {
+        // A finished worker is replaced too, so that a later change to a
+        // directory is noticed
+        let stale = |worker: &mut IndexWorker| {
+            worker.path != path || !matches!(worker.done.try_recv(), Err(TryRecvError::Empty))
+        };
+        if let Some(stale) = worker.take_if(stale) {
+            stale.cancel.store(true, Ordering::Relaxed);
+        }
+        if worker.is_none() {
+            *worker = Some(IndexWorker::start(path));
+        }
+    }
+}
+
+/// Runs `find_matches` once there is an executable index for the `PATH`,
+/// scanning it on a worker thread so that the wait can be given up when the
+/// deadline passes or a key is pressed.
+///
+/// An index already built for this `PATH` is used while the worker checks
+/// it, rather than waiting on its directories. Returns `None` if it gave
+/// up. The worker is kept in `worker` and left to finish, which still fills
+/// the index for the next Tab, unless the `PATH` has changed by then.
+fn find_matches_in_background(
+    line: &str,
+    shell: &Shell,
+    worker: &mut Option<IndexWorker>,
+) -> Option<(CompletionContext, Vec<String>)> {
+    let path = shell.variables.get("PATH").unwrap_or_default();
+    IndexWorker::keep_current(worker, path.clone());
+
+    if executables::with_latest_index(&path, |_| ()).is_none()
+        && let Some(scanning) = worker
+    {
+        let deadline = Instant::now() + COMPLETION_DEADLINE;
+        loop {
+            match scanning.done.recv_timeout(Duration::from_millis(10)) {
+                Err(RecvTimeoutError::Timeout) if Instant::now() < deadline && !key_pending() => {}
+                Err(RecvTimeoutError::Timeout) => return None,
+                _ => break,
+            }
+        }
+        *worker = None;
+    }
+    Some(find_matches(line, shell))
+}
+
+/// Checks whether a key press is waiting to be read from stdin.
+fn key_pending() -> bool {
+    let mut stdin = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    unsafe { libc::poll(&mut stdin, 1, 0) > 0 }
+}
+
+/// Lists the matches for the word of `context` below the command line,
+/// colored by what they name. Previous command lines are listed one per
+/// line, other matches all on one line.
+fn list_matches<W: Write>(
+    out: &mut W,
+    context: &CompletionContext,
+    matches: &[String],
+    shell: &Shell,
+) -> io::Result<()> {
+    if context.whole_line {
+        for line in matches {
+            write!(out, "{}\r\n", line)?;
+        }
+        return Ok(());
+    }
+    let colors = ListColors::from_variables(&shell.variables);
+    let names: Vec<String> = matches
+        .iter()
+        .map(|m| {
+            let name = completion::display_name(m);
+            match &colors {
+                Some(colors) => colors.paint(context.kind(m, shell), name),
+                None => name.to_string(),
+            }
+        })
+        .collect();
+    write!(out, "{}\r\n", names.join("  "))
+}
+
+/// How long a command runs before its duration is reported, unless
+/// `REPORTTIME` says otherwise.
+const DEFAULT_REPORT_TIME: Duration = Duration::from_secs(5);
+
+/// Prints how long a foreground command took if it ran for at least
+/// `REPORTTIME` seconds (5 by default; negative turns reporting off).
+fn report_duration(elapsed: Duration, shell: &Shell) {
+    let threshold = match shell
+        .variables
+        .get("REPORTTIME")
+        .map(|value| value.trim().parse::<f64>())
+    {
+        Some(Ok(seconds)) if seconds < 0.0 => return,
+        Some(Ok(seconds)) => Duration::try_from_secs_f64(seconds).unwrap_or(DEFAULT_REPORT_TIME),
+        _ => DEFAULT_REPORT_TIME,
+    };
+    if elapsed < threshold {
+        return;
+    }
+    let mut stderr = shell.streams.stderr();
+    let seconds = elapsed.as_secs_f64();
+    let _ = if seconds < 60.0 {
+        writeln!(stderr, "took {:.1}s", seconds)
+    } else {
+        let seconds = elapsed.as_secs();
+        let (hours, minutes) = (seconds / 3600, seconds / 60 % 60);
+        if hours > 0 {
+            writeln!(stderr, "took {}h {}m {}s", hours, minutes, seconds % 60)
+        } else {
+            writeln!(stderr, "took {}m {}s", minutes, seconds % 60)
+        }
+    };
+}
+
+impl Shell {
+    /// Runs the shell as an interactive console: reads command lines with
+    /// the line editor and runs them until `exit` or Ctrl-D, then does what
+    /// is due before exiting (see [`Shell::finish`]). Returns the status to
+    /// exit with.
+    ///
+    /// When stdin isn't a terminal, commands are read from it one line at a
+    /// time instead, as from a script, and not saved to the history file.
+    ///
+    /// Call [`Shell::start`] first to load the history and run the startup
+    /// files.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use codecrafters_shell::{Shell, ShellOptions};
+    ///
+    /// let options = ShellOptions {
+    ///     vi: true,
+    ///     ..ShellOptions::default()
+    /// };
+    /// let mut shell = Shell::new(options);
+    /// shell.start("my-console");
+    /// let status = shell.run_interactive()?;
+    /// std::process::exit(status);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn run_interactive(&mut self) -> io::Result<i32> {
+        let status = if unsafe { libc::isatty(libc::STDIN_FILENO) } == 0 {
+            self.variables.set("HISTFILE", "");
+            run_piped(self)
+        } else {
+            interact(self)
+        };
+        match status {
+            Ok(status) => Ok(self.finish(status)),
+            Err(e) => {
+                self.finish(1);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Puts the most recently killed text on the system clipboard, with
+/// `set -o clipboard`.
+fn copy_kill<W: Write + ?Sized>(
+    editor: &LineEditor,
+    terminal: &mut W,
+    shell: &Shell,
+) -> io::Result<()> {
+    if shell.options.clipboard
+        && let Some(text) = editor.last_kill()
+    {
+        write!(terminal, "{}", clipboard::copy_sequence(text))?;
+    }
+    Ok(())
+}
+
+/// Reads command lines with the line editor and runs them, returning the
+/// status to exit with.
+fn interact(shell: &mut Shell) -> io::Result<i32> {
+    // Set after warning about jobs; a repeated exit attempt then goes through
+    let mut exit_warned = false;
+    // Further lines from a Ctrl-X Ctrl-E edit, run one per prompt
+    let mut queued_lines: VecDeque<String> = VecDeque::new();
+    // The scan of the `PATH` a slow completion left running
+    let mut index_worker = None;
+    // A history expansion to edit before running it, with histverify
+    let mut verify_line: Option<String> = None;
+    let mut editor = LineEditor::new();
+    let mut terminal = terminal::open();
+    if let Ok((width, _)) = terminal.size() {
+        editor.set_width(width as usize);
+    }
+    signals::install_resize_handler();
+
+    // The lines of a command that continues on the next line, such as one
+    // with an unterminated quote
+    let mut continued = String::new();
+    // The directory last recorded for `j`
+    let mut last_dir = None;
+
+    loop {
+        // A SIGTERM or SIGHUP while a command ran
+        if let Some(signal) = signals::take_termination() {
+            return Ok(128 + signal);
+        }
+
+        // Report asynchronous jobs that finished since the last prompt
+        for job in shell.reap_jobs() {
+            let mut stdout = shell.streams.stdout();
+            let _ = writeln!(stdout, "[{}]+  {:<24}{}", job.id, "Done", job.command);
+        }
+
+        // Commands other shells sharing the history file have run
+        if continued.is_empty() && shell.options.share_history {
+            shell.save_history();
+        }
+
+        // Remember each directory the shell changes to for `j`, once per
+        // change rather than once per prompt
+        let dir = shell.cwd.to_path_buf();
+        if last_dir.as_ref() != Some(&dir) {
+            if let Err(e) = jump::record_visit(&dir, &shell.variables) {
+                error::report_message(e, shell);
+            }
+            last_dir = Some(dir);
+        }
+
+        // PROMPT_COMMAND and precmd callbacks run before each new command,
+        // but not between the lines of one
+        if continued.is_empty()
+            && queued_lines.is_empty()
+            && let ShellStatus::Exit(code) = hooks::run_precmd(shell)
+        {
+            return Ok(code);
+        }
+
+        // Enter raw mode to handle input character by character
+        terminal.enable_raw_mode()?;
+        editor.reset();
+        editor.set_commands(Commands::new(shell));
+        // Commands are colored from the executable index, so have it ready
+        // for typing without making the prompt wait for it
+        let path = shell.variables.get("PATH").unwrap_or_default();
+        IndexWorker::keep_current(&mut index_worker, path);
+        if let Some(line) = verify_line.take() {
+            editor.set(&line);
+        }
+
+        // PS1 is expanded afresh for each line, e.g. for `\w` after `cd`
+        let prompt = if continued.is_empty() && shell.options.semantic_prompt {
+            prompt::mark_prompt(&prompt::primary_prompt(shell))
+        } else if continued.is_empty() {
+            prompt::primary_prompt(shell)
+        } else {
+            prompt::secondary_prompt(shell)
+        };
+        editor.redraw(&mut terminal, &prompt)?;
+        let mut last_was_tab = false;
+        let mut last_tab_matches: Vec<String> = Vec::new();
+        let mut last_tab_buffer = String::new();
+        // The completions Tab cycles through once they have been listed
+        let mut menu: Option<Menu> = None;
+        // The word whose matches are listed if "Display all N
+        // possibilities?" is answered with y
+        let mut listing_query: Option<CompletionContext> = None;
+        let mut history_index: Option<usize> = None;
+        // The line as typed when Up started browsing history
+        let mut history_prefix = String::new();
+        // Keys read so far of a multi-key binding such as Ctrl-X Ctrl-E
+        let mut pending_keys: Vec<Key> = Vec::new();
+        // The pattern being typed after `/` in vi normal mode
+        let mut vi_search: Option<String> = None;
+
+        // A queued line is shown as if typed and run without reading keys
+        let queued_line = queued_lines.pop_front();
+        if let Some(line) = &queued_line {
+            editor.set(line);
+            editor.redraw(&mut terminal, &prompt)?;
+            editor.finish_line(&mut terminal, &prompt)?;
+        }
+        while queued_line.is_none() {
+            let Some(c) = terminal.read_key() else {
+                break;
+            };
+            if let Some(context) = listing_query.take() {
+                match c {
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                        listing_query = Some(context);
+                        continue;
+                    }
+                    Ok(Key::Char('y' | 'Y' | ' ')) => {
+                        write!(terminal, "\r\n")?;
+                        list_matches(&mut terminal, &context, &last_tab_matches, shell)?;
+                        menu = Some(Menu::new(&context, &last_tab_matches));
+                    }
+                    _ => write!(terminal, "\r\n")?,
+                }
+                editor.redraw(&mut terminal, &prompt)?;
+                continue;
+            }
+
+            // In vi mode, normal-mode keys are either handled by the editor
+            // or mapped onto the equivalent emacs-mode key below
+            let c = match c {
+                Ok(key) if shell.options.vi => {
+                    if let Some(pattern) = vi_search.as_mut() {
+                        match key {
+                            Key::Char('\n') | Key::Char('\r') => {
+                                // Search backwards from the entry being shown
+                                let end = history_index.unwrap_or(shell.history.len());
+                                let found = shell
+                                    .history
+                                    .iter()
+                                    .take(end)
+                                    .rposition(|entry| entry.contains(pattern.as_str()));
+                                match found {
+                                    Some(index) => {
+                                        history_index = Some(index);
+                                        history_prefix.clear();
+                                        editor.set(&shell.history[index]);
+                                        editor.move_home();
+                                    }
+                                    None => write!(terminal, "\x07")?,
+                                }
+                                vi_search = None;
+                                editor.redraw(&mut terminal, &prompt)?;
+                            }
+                            Key::Esc | Key::Ctrl('c') => {
+                                vi_search = None;
+                                editor.redraw(&mut terminal, &prompt)?;
+                            }
+                            Key::Backspace if pattern.is_empty() => {
+                                vi_search = None;
+                                editor.redraw(&mut terminal, &prompt)?;
+                            }
+                            Key::Backspace => {
+                                pattern.pop();
+                                write!(terminal, "\r/{}{}", pattern, termion::clear::UntilNewline)?;
+                                terminal.flush()?;
+                            }
+                            Key::Char(ch) => {
+                                pattern.push(ch);
+                                write!(terminal, "{}", ch)?;
+                                terminal.flush()?;
+                            }
+                            _ => {}
+                        }
+                        continue;
+                    }
+
+                    if editor.vi_mode() == ViMode::Insert && !matches!(key, Key::Esc | Key::Alt(_))
+                    {
+                        Ok(key)
+                    } else {
+                        let key = match key {
+                            Key::Esc if editor.vi_mode() == ViMode::Insert => {
+                                editor.vi_enter_normal();
+                                editor.redraw(&mut terminal, &prompt)?;
+                                continue;
+                            }
+                            // Esc followed quickly by a key arrives as a single Alt key
+                            Key::Alt(ch) if editor.vi_mode() == ViMode::Insert => {
+                                editor.vi_enter_normal();
+                                Key::Char(ch)
+                            }
+                            key => key,
+                        };
+                        let killed = editor.last_kill().map(str::to_string);
+                        match editor.vi_key(&key) {
+                            ViAction::Key(mapped) => Ok(mapped),
+                            ViAction::Redraw => {
+                                if editor.last_kill() != killed.as_deref() {
+                                    copy_kill(&editor, &mut terminal, shell)?;
+                                }
+                                editor.redraw(&mut terminal, &prompt)?;
+                                last_was_tab = false;
+                                continue;
+                            }
+                            ViAction::Search => {
+                                vi_search = Some(String::new());
+                                write!(terminal, "\r/{}", termion::clear::UntilNewline)?;
+                                terminal.flush()?;
+                                continue;
+                            }
+                            ViAction::Pending => continue,
+                            ViAction::Beep => {
+                                write!(terminal, "\x07")?;
+                                terminal.flush()?;
+                                continue;
+                            }
+                        }
+                    }
+                }
+                other => other,
+            };
+
+            let key = match c {
+                Ok(key) => key,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    if let Some(signal) = signals::take_termination() {
+                        write!(terminal, "\r\n")?;
+                        terminal.disable_raw_mode()?;
+                        return Ok(128 + signal);
+                    }
+                    // SIGWINCH interrupts the read; redraw for the new width
+                    if crate::signals::take_resize() {
+                        if let Ok((width, _)) = terminal.size() {
+                            editor.set_width(width as usize);
+                        }
+                        editor.redraw(&mut terminal, &prompt)?;
+                    }
+                    continue;
+                }
+                Err(e) => {
+                    error::report_message(format_args!("error reading input: {}", e), shell);
+                    break;
+                }
+            };
+
+            // Keys accumulate until they form a bound sequence such as
+            // Ctrl-X Ctrl-E
+            pending_keys.push(key);
+            let command = match shell.keymap.lookup(&pending_keys) {
+                Lookup::Prefix => continue,
+                Lookup::Unbound => {
+                    if pending_keys.len() > 1 {
+                        write!(terminal, "\x07")?;
+                        terminal.flush()?;
+                    }
+                    pending_keys.clear();
+                    last_was_tab = false;
+                    continue;
+                }
+                Lookup::Bound(Binding::Shell(command)) => {
+                    // `bind -x`: run the command below the line, then
+                    // redraw the line to continue editing it
+                    pending_keys.clear();
+                    editor.finish_line(&mut terminal, &prompt)?;
+                    terminal.disable_raw_mode()?;
+                    crate::execute_pipeline(&command, shell);
+                    shell.streams.stdout().flush()?;
+                    terminal.enable_raw_mode()?;
+                    editor.redraw(&mut terminal, &prompt)?;
+                    last_was_tab = false;
+                    continue;
+                }
+                Lookup::Bound(Binding::Command(command)) => command,
+            };
+            let keys = std::mem::take(&mut pending_keys);
+            editor.before_command(command);
+
+            // Tab after the matches have been listed cycles through them,
+            // until any other command is used
+            let command = match command {
+                EditCommand::Complete if menu.is_some() => EditCommand::MenuComplete,
+                EditCommand::MenuComplete | EditCommand::MenuCompleteBackward => command,
+                _ => {
+                    menu = None;
+                    command
+                }
+            };
+
+            match command {
+                EditCommand::Interrupt => {
+                    editor.finish_line(&mut terminal, &prompt)?;
+                    editor.clear();
+                    continued.clear();
+                    break;
+                }
+                EditCommand::EndOfFile => {
+                    if editor.is_empty()
+                        && let Some(Continuation::Quote(quote)) = parser::continuation(&continued)
+                    {
+                        write!(
+                            terminal,
+                            "\r\nshell: unexpected EOF while looking for matching `{}'\r\n",
+                            quote
+                        )?;
+                        continued.clear();
+                        break;
+                    }
+                    if editor.is_empty() {
+                        if !exit_warned && let Some(warning) = shell.exit_warning() {
+                            let message = format!("{}: {}", error::SHELL_NAME, warning);
+                            let color = terminal::colors_enabled();
+                            write!(terminal, "\r\n{}\r\n", error::format(&message, color))?;
+                            exit_warned = true;
+                            break;
+                        }
+                        // Back to cooked mode for the logout file
+                        terminal.disable_raw_mode()?;
+                        return Ok(shell.variables.status());
+                    }
+
+                    // On a non-empty line, delete the character under the cursor
+                    if editor.delete_forward() {
+                        editor.redraw(&mut terminal, &prompt)?;
+                    } else {
+                        write!(terminal, "\x07")?;
+                        terminal.flush()?;
+                    }
+                    last_was_tab = false;
+                    history_index = None;
+                }
+                EditCommand::AcceptLine => {
+                    if editor.expand_abbreviation(&shell.abbreviations) {
+                        editor.redraw(&mut terminal, &prompt)?;
+                    }
+                    editor.finish_line(&mut terminal, &prompt)?;
+                    break;
+                }
+                EditCommand::PreviousHistory | EditCommand::HistorySearchBackward => {
+                    // The line as typed is restored after the newest entry
+                    if history_index.is_none() {
+                        history_prefix = editor.buffer().to_string();
+                    }
+                    // A history search only visits entries starting with
+                    // the typed prefix, skipping ones matching the line shown
+                    let search = command == EditCommand::HistorySearchBackward;
+                    let end = history_index.unwrap_or(shell.history.len());
+                    let found = shell.history.iter().take(end).rposition(|entry| {
+                        !search || (entry.starts_with(&history_prefix) && entry != editor.buffer())
+                    });
+                    match found {
+                        Some(index) => {
+                            history_index = Some(index);
+                            editor.set(&shell.history[index]);
+                            editor.redraw(&mut terminal, &prompt)?;
+                        }
+                        None => {
+                            write!(terminal, "\x07")?;
+                            terminal.flush()?;
+                        }
+                    }
+                    last_was_tab = false;
+                }
+                EditCommand::NextHistory | EditCommand::HistorySearchForward => {
+                    if let Some(idx) = history_index {
+                        // Navigate forwards in history
+                        let search = command == EditCommand::HistorySearchForward;
+                        let found = shell
+                            .history
+                            .iter()
+                            .skip(idx + 1)
+                            .position(|entry| {
+                                !search
+                                    || (entry.starts_with(&history_prefix)
+                                        && entry != editor.buffer())
+                            })
+                            .map(|offset| idx + 1 + offset);
+                        match found {
+                            Some(index) => {
+                                history_index = Some(index);
+                                editor.set(&shell.history[index]);
+                            }
+                            None => {
+                                // Past the newest entry, back to what was typed
+                                history_index = None;
+                                editor.set(&history_prefix);
+                            }
+                        }
+                        editor.redraw(&mut terminal, &prompt)?;
+                    }
+                    last_was_tab = false;
+                }
+                EditCommand::BeginningOfHistory => {
+                    // Jump to the oldest history entry
+                    if !shell.history.is_empty() {
+                        history_index = Some(0);
+                        history_prefix.clear();
+                        editor.set(&shell.history[0]);
+                        editor.redraw(&mut terminal, &prompt)?;
+                    }
+                    last_was_tab = false;
+                }
+                EditCommand::EndOfHistory => {
+                    // Jump back past the newest entry to an empty line
+                    if history_index.is_some() {
+                        history_index = None;
+                        editor.clear();
+                        editor.redraw(&mut terminal, &prompt)?;
+                    }
+                    last_was_tab = false;
+                }
+                EditCommand::BeginningOfLine => {
+                    editor.move_home();
+                    editor.redraw(&mut terminal, &prompt)?;
+                    last_was_tab = false;
+                }
+                EditCommand::EndOfLine => {
+                    editor.move_end();
+                    editor.redraw(&mut terminal, &prompt)?;
+                    last_was_tab = false;
+                }
+                EditCommand::DeleteChar => {
+                    if editor.delete_forward() {
+                        editor.redraw(&mut terminal, &prompt)?;
+                    }
+                    last_was_tab = false;
+                    history_index = None;
+                }
+                EditCommand::BackwardChar => {
+                    if editor.move_left() {
+                        editor.redraw(&mut terminal, &prompt)?;
+                    }
+                    last_was_tab = false;
+                }
+                EditCommand::ForwardChar => {
+                    if editor.accept_suggestion() || editor.move_right() {
+                        editor.redraw(&mut terminal, &prompt)?;
+                    }
+                    last_was_tab = false;
+                }
+                EditCommand::KillLine => {
+                    if editor.kill_to_end() {
+                        copy_kill(&editor, &mut terminal, shell)?;
+                        editor.redraw(&mut terminal, &prompt)?;
+                    }
+                    last_was_tab = false;
+                    history_index = None;
+                }
+                EditCommand::UnixLineDiscard => {
+                    if editor.kill_to_start() {
+                        copy_kill(&editor, &mut terminal, shell)?;
+                        editor.redraw(&mut terminal, &prompt)?;
+                    }
+                    last_was_tab = false;
+                    history_index = None;
+                }
+                EditCommand::UnixWordRubout => {
+                    if editor.kill_word_before() {
+                        copy_kill(&editor, &mut terminal, shell)?;
+                        editor.redraw(&mut terminal, &prompt)?;
+                    }
+                    last_was_tab = false;
+                    history_index = None;
+                }
+                EditCommand::BackwardWord => {
+                    if editor.move_word_left() {
+                        editor.redraw(&mut terminal, &prompt)?;
+                    }
+                    last_was_tab = false;
+                }
+                EditCommand::ForwardWord => {
+                    if editor.move_word_right() {
+                        editor.redraw(&mut terminal, &prompt)?;
+                    }
+                    last_was_tab = false;
+                }
+                EditCommand::KillWord => {
+                    if editor.kill_word_after() {
+                        copy_kill(&editor, &mut terminal, shell)?;
+                        editor.redraw(&mut terminal, &prompt)?;
+                    }
+                    last_was_tab = false;
+                    history_index = None;
+                }
+                EditCommand::TransposeChars => {
+                    if editor.transpose_chars() {
+                        editor.redraw(&mut terminal, &prompt)?;
+                    } else {
+                        write!(terminal, "\x07")?;
+                        terminal.flush()?;
+                    }
+                    last_was_tab = false;
+                    history_index = None;
+                }
+                EditCommand::Undo => {
+                    if editor.undo() {
+                        editor.redraw(&mut terminal, &prompt)?;
+                    }
+                    last_was_tab = false;
+                    history_index = None;
+                }
+                EditCommand::EditAndExecuteCommand => {
+                    // Edit the line in $EDITOR, then run what was saved
+                    editor.finish_line(&mut terminal, &prompt)?;
+                    terminal.disable_raw_mode()?;
+                    let edited =
+                        crate::editor::edit_in_external_editor(editor.buffer(), &shell.variables);
+                    terminal.enable_raw_mode()?;
+                    match edited {
+                        Ok(text) => {
+                            let mut lines = text
+                                .lines()
+                                .filter(|line| !line.trim().is_empty())
+                                .map(str::to_string);
+                            editor.set(&lines.next().unwrap_or_default());
+                            queued_lines.extend(lines);
+                            editor.redraw(&mut terminal, &prompt)?;
+                            editor.finish_line(&mut terminal, &prompt)?;
+                            break;
+                        }
+                        Err(e) => {
+                            let message = format!("{}: {}", error::SHELL_NAME, e);
+                            let color = terminal::colors_enabled();
+                            write!(terminal, "{}\r\n", error::format(&message, color))?;
+                            editor.redraw(&mut terminal, &prompt)?;
+                        }
+                    }
+                    last_was_tab = false;
+                }
+                EditCommand::ClearScreen => {
+                    // Clear the screen, keeping the line being edited
+                    editor.clear_screen(&mut terminal, &prompt)?;
+                    last_was_tab = false;
+                }
+                EditCommand::Yank => {
+                    if editor.yank() {
+                        copy_kill(&editor, &mut terminal, shell)?;
+                        editor.redraw(&mut terminal, &prompt)?;
+                    }
+                    last_was_tab = false;
+                    history_index = None;
+                }
+                EditCommand::ClipboardCopy => {
+                    write!(terminal, "{}", clipboard::copy_sequence(editor.buffer()))?;
+                    terminal.flush()?;
+                    last_was_tab = false;
+                }
+                EditCommand::ClipboardPaste => {
+                    match terminal.read_clipboard()? {
+                        Some(text) => {
+                            editor.insert_str(&text);
+                            editor.redraw(&mut terminal, &prompt)?;
+                        }
+                        None => {
+                            write!(terminal, "\x07")?;
+                            terminal.flush()?;
+                        }
+                    }
+                    last_was_tab = false;
+                    history_index = None;
+                }
+                EditCommand::Complete => {
+                    // Complete the word before the cursor
+                    let buffer = editor.before_cursor().to_string();
+                    let Some((context, all_matches)) =
+                        find_matches_in_background(&buffer, shell, &mut index_worker)
+                    else {
+                        // Too slow, or cancelled by typing on
+                        write!(terminal, "\x07")?;
+                        terminal.flush()?;
+                        last_was_tab = false;
+                        continue;
+                    };
+                    let word = &context.word;
+
+                    if all_matches.len() == 1 {
+                        // Single match: complete it, with a trailing space
+                        // unless it is a directory to continue into or a
+                        // whole command line
+                        let completed = &all_matches[0];
+                        let finished = !completed.ends_with('/') && !context.whole_line;
+                        editor.replace_before_cursor(
+                            context.start,
+                            &context.quote(completed, finished),
+                        );
+                        if finished {
+                            editor.insert(' ');
+                        }
+                        editor.redraw(&mut terminal, &prompt)?;
+                        last_was_tab = false;
+                    } else if all_matches.is_empty() {
+                        // No matches: beep
+                        write!(terminal, "\x07")?;
+                        terminal.flush()?;
+                        last_was_tab = false;
+                    } else {
+                        // Multiple matches: try LCP completion
+                        let lcp = completion::longest_common_prefix(&all_matches);
+
+                        if lcp.len() > word.len() {
+                            // We can complete more - complete to LCP without space
+                            editor
+                                .replace_before_cursor(context.start, &context.quote(&lcp, false));
+                            editor.redraw(&mut terminal, &prompt)?;
+                            last_was_tab = false;
+                        } else {
+                            // LCP equals the word - can't complete further
+                            if last_was_tab
+                                && buffer == last_tab_buffer
+                                && !last_tab_matches.is_empty()
+                            {
+                                // Second tab: display all matches, asking
+                                // first if there are many
+                                editor.finish_line(&mut terminal, &prompt)?;
+                                let query_items = shell
+                                    .options
+                                    .completion_query_items
+                                    .unwrap_or(completion::QUERY_ITEMS);
+                                if query_items > 0 && last_tab_matches.len() >= query_items {
+                                    write!(
+                                        terminal,
+                                        "Display all {} possibilities? (y or n)",
+                                        last_tab_matches.len()
+                                    )?;
+                                    terminal.flush()?;
+                                    listing_query = Some(context);
+                                } else {
+                                    list_matches(
+                                        &mut terminal,
+                                        &context,
+                                        &last_tab_matches,
+                                        shell,
+                                    )?;
+                                    editor.redraw(&mut terminal, &prompt)?;
+                                    menu = Some(Menu::new(&context, &last_tab_matches));
+                                }
+                                last_was_tab = false;
+                            } else {
+                                // First tab: beep and store matches
+                                write!(terminal, "\x07")?;
+                                terminal.flush()?;
+                                last_was_tab = true;
+                                last_tab_matches = all_matches;
+                                last_tab_buffer = buffer;
+                            }
+                        }
+                    }
+                }
+                EditCommand::MenuComplete | EditCommand::MenuCompleteBackward => {
+                    if menu.is_none()
+                        && let Some((context, matches)) = find_matches_in_background(
+                            editor.before_cursor(),
+                            shell,
+                            &mut index_worker,
+                        )
+                        && !matches.is_empty()
+                    {
+                        menu = Some(Menu::new(&context, &matches));
+                    }
+                    match menu.as_mut() {
+                        Some(menu) => {
+                            let completion = if command == EditCommand::MenuComplete {
+                                menu.forward()
+                            } else {
+                                menu.backward()
+                            }
+                            .to_string();
+                            editor.replace_before_cursor(menu.start(), &completion);
+                            editor.redraw(&mut terminal, &prompt)?;
+                        }
+                        None => {
+                            write!(terminal, "\x07")?;
+                            terminal.flush()?;
+                        }
+                    }
+                    last_was_tab = false;
+                }
+                EditCommand::BackwardDeleteChar => {
+                    // Redraw rather than echo "\x08 \x08", since the
+                    // erased character may be more than one column wide
+                    if editor.backspace() {
+                        editor.redraw(&mut terminal, &prompt)?;
+                    }
+                    last_was_tab = false;
+                    history_index = None;
+                }
+                EditCommand::SelfInsert => {
+                    let [Key::Char(c)] = keys[..] else {
+                        continue;
+                    };
+                    // Typing a space after an abbreviation expands it
+                    if c == ' ' && editor.expand_abbreviation(&shell.abbreviations) {
+                        editor.insert(c);
+                        editor.redraw(&mut terminal, &prompt)?;
+                    } else {
+                        editor.type_char(&mut terminal, &prompt, c)?;
+                    }
+                    last_was_tab = false;
+                    history_index = None;
+                }
+            }
+
+            // Offer the latest history entry extending what has been typed
+            if editor.suggest(&shell.history) {
+                editor.redraw(&mut terminal, &prompt)?;
+            }
+        }
+
+        // Disable raw mode
+        terminal.disable_raw_mode()?;
+
+        // Unterminated quotes and trailing backslashes continue the command
+        // on the next line
+        let mut input = std::mem::take(&mut continued);
+        input.push_str(editor.buffer());
+        match parser::continuation(&input) {
+            Some(Continuation::Backslash) => {
+                input.pop();
+                continued = input;
+                continue;
+            }
+            Some(Continuation::Quote(_)) => {
+                input.push('\n');
+                continued = input;
+                continue;
+            }
+            None => {}
+        }
+
+        // History references such as `!!` and `!$`; like bash, the line is
+        // shown once expanded, and dropped if a reference fails
+        let input = match history::expand_history(&input, &shell.history) {
+            Ok(expanded) if expanded != input && shell.options.hist_verify => {
+                verify_line = Some(expanded);
+                continue;
+            }
+            Ok(expanded) if expanded != input => {
+                writeln!(shell.streams.stdout(), "{}", expanded.trim())?;
+                expanded
+            }
+            Ok(expanded) => expanded,
+            Err(message) => {
+                error::report(&ShellError::ParseError(message), shell);
+                continue;
+            }
+        };
+
+        let input_string = input.trim().to_string();
+        if input_string.is_empty() {
+            continue;
+        }
+
+        // Any command other than an immediate second exit clears the warning
+        let exit_confirmed = std::mem::take(&mut exit_warned);
+
+        // Add to history, unless HISTCONTROL leaves the line out. It is
+        // written straight away, so a shell that is killed loses nothing
+        shell.history.set_limit(History::limit(&shell.variables));
+        if shell
+            .history
+            .record(&input, HistControl::from_variables(&shell.variables))
+        {
+            shell.save_history();
+        }
+
+        // Background jobs and coprocesses run asynchronously and are
+        // tracked in the job table
+        let Some(command) = CommandLine::parse(&input_string) else {
+            continue;
+        };
+        if !matches!(command, CommandLine::Foreground(_)) {
+            shell.execute_with_hooks(&command);
+            continue;
+        }
+
+        if shell.options.semantic_prompt {
+            let mut stdout = shell.streams.stdout();
+            write!(stdout, "{}", prompt::OUTPUT_START_MARK)?;
+            stdout.flush()?;
+        }
+        let result = shell.execute_with_hooks(&command);
+        report_duration(result.duration, shell);
+        if shell.options.semantic_prompt {
+            let mut stdout = shell.streams.stdout();
+            write!(stdout, "{}", prompt::command_end_mark(result.status))?;
+            stdout.flush()?;
+        }
+
+        match result.shell {
+            ShellStatus::Exit(code) => {
+                if !exit_confirmed && let Some(warning) = shell.exit_warning() {
+                    error::report_message(warning, shell);
+                    exit_warned = true;
+                    continue;
+                }
+                return Ok(code);
+            }
+            ShellStatus::Continue => continue,
+        }
+    }
+}
+
+/// Runs the commands piped in until the end of the input, returning the
+/// status to exit with.
+#[cfg(not(feature = "async"))]
+fn run_piped(shell: &mut Shell) -> io::Result<i32> {
+    loop {
+        if let Some(signal) = signals::take_termination() {
+            return Ok(128 + signal);
+        }
+        // Stdin is locked only while the command is read, so that `read`
+        // can read the lines after it
+        let command = match shell.read_command(&mut io::stdin().lock())? {
+            Ok(command) => command,
+            Err(ShellStatus::Exit(code)) => return Ok(code),
+            Err(ShellStatus::Continue) => continue,
+        };
+        if let ShellStatus::Exit(code) = shell.execute_read(&command) {
+            return Ok(code);
+        }
+    }
+}
+
+/// Runs the commands piped in on a tokio runtime until the end of the
+/// input, returning the status to exit with.
+#[cfg(feature = "async")]
+fn run_piped(shell: &mut Shell) -> io::Result<i32> {
+    crate::runtime::build()?.block_on(async {
+        loop {
+            if let Some(signal) = signals::take_termination() {
+                return Ok(128 + signal);
+            }
+            let command = match shell.read_stdin_command().await? {
+                Ok(command) => command,
+                Err(ShellStatus::Exit(code)) => return Ok(code),
+                Err(ShellStatus::Continue) => continue,
+            };
+            if let ShellStatus::Exit(code) = shell.execute_read_async(&command).await {
+                return Ok(code);
+            }
+        }
+    })
+}
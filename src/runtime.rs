@@ -0,0 +1,363 @@
+use crate::error::{self, ShellError};
+use crate::executor::SharedExecutor;
+use crate::shell::{self, CommandLine, Shell};
+use crate::streams::Connection;
+use crate::{Builtin, CommandResult, ShellStatus, hooks, tokenize_expanded};
+use std::io::{self, BufRead};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tokio::signal::unix::{Signal, SignalKind, signal};
+
+/// The exit status of a command stopped for running out of time, as the
+/// `timeout` utility gives it.
+pub const TIMED_OUT: i32 = 124;
+
+/// A pipeline started on the async runtime, which can be waited for
+/// without blocking it.
+///
+/// Its processes are reaped as `SIGCHLD` reports them, so other tasks, such
+/// as another pipeline or a [`JobMonitor`], keep running while it does.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::Shell;
+/// use codecrafters_shell::runtime;
+/// use std::time::Duration;
+///
+/// let runtime = runtime::build().unwrap();
+/// let shell = Shell::default();
+/// let result = runtime.block_on(async {
+///     let mut pipeline = runtime::spawn("sleep 5", &shell).unwrap();
+///     pipeline.wait_timeout(Duration::from_millis(100)).await
+/// });
+/// assert_eq!(result.status, runtime::TIMED_OUT);
+/// ```
+pub struct RunningPipeline {
+    pids: Vec<i32>,
+    /// The processes that haven't been reaped yet.
+    running: Vec<i32>,
+    /// The process of the last command, unless it was skipped.
+    last: Option<i32>,
+    /// The status of the last command, once it has exited.
+    status: i32,
+    started: Instant,
+    children: Signal,
+    connection: Option<Connection>,
+    executor: SharedExecutor,
+}
+
+/// Builds a runtime for running commands: one thread, with the signal and
+/// time drivers enabled.
+pub fn build() -> io::Result<tokio::runtime::Runtime> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+}
+
+/// Starts a pipeline without waiting for it, like a foreground pipeline
+/// with the shell's streams. Must be called from within a runtime.
+///
+/// Builtins in the pipeline run in child processes, as they would in a
+/// pipeline of more than one command, so they can't change the shell.
+pub fn spawn(input: &str, shell: &Shell) -> Result<RunningPipeline, ShellError> {
+    let started = Instant::now();
+    // Registered first, so that no process can exit unnoticed
+    let children = signal(SignalKind::child()).map_err(|e| ShellError::io("SIGCHLD", e))?;
+    let parts: Vec<&str> = input.split('|').map(|s| s.trim()).collect();
+    trace!(input, segments = ?parts, "parsed pipeline");
+
+    let mut connection = shell.streams.connect();
+    let (stdin, stdout, stderr) = (
+        connection.stdin.take(),
+        connection.stdout.take(),
+        connection.stderr.take(),
+    );
+    match crate::exec::spawn_pipeline(&parts, shell, stdin, stdout, stderr) {
+        Ok(spawned) => Ok(RunningPipeline {
+            running: spawned.pids.clone(),
+            last: spawned
+                .pids
+                .last()
+                .copied()
+                .filter(|_| !spawned.last_skipped),
+            status: if spawned.last_skipped { 1 } else { 0 },
+            pids: spawned.pids,
+            started,
+            children,
+            connection: Some(connection),
+            executor: shell.executor.clone(),
+        }),
+        Err(e) => {
+            connection.finish();
+            Err(e)
+        }
+    }
+}
+
+impl RunningPipeline {
+    /// The process IDs of the pipeline's commands.
+    pub fn pids(&self) -> &[i32] {
+        &self.pids
+    }
+
+    /// Sends `signal` to every process of the pipeline still running.
+    pub fn kill(&self, signal: i32) {
+        for &pid in &self.running {
+            self.executor.kill(pid, signal);
+        }
+    }
+
+    /// Waits for every process of the pipeline to exit. The status is that
+    /// of its last command.
+    pub async fn wait(&mut self) -> CommandResult {
+        loop {
+            let last = self.last;
+            let status = &mut self.status;
+            self.running.retain(|&pid| match try_reap(pid) {
+                Some(reaped) => {
+                    if Some(pid) == last {
+                        *status = reaped;
+                    }
+                    false
+                }
+                None => true,
+            });
+            if self.running.is_empty() {
+                break;
+            }
+            self.children.recv().await;
+        }
+
+        // The threads copying to replaced streams end with the processes
+        if let Some(connection) = self.connection.take() {
+            let _ = tokio::task::spawn_blocking(move || connection.finish()).await;
+        }
+        CommandResult {
+            duration: self.started.elapsed(),
+            pid: self.last,
+            ..CommandResult::finished(self.status)
+        }
+    }
+
+    /// Waits like [`RunningPipeline::wait`] for up to `limit`, then
+    /// terminates the pipeline with `SIGTERM`. A pipeline that was
+    /// terminated has the status [`TIMED_OUT`].
+    pub async fn wait_timeout(&mut self, limit: Duration) -> CommandResult {
+        if let Ok(result) = tokio::time::timeout(limit, self.wait()).await {
+            return result;
+        }
+        trace!(pids = ?self.running, "timed out");
+        self.kill(libc::SIGTERM);
+        CommandResult {
+            status: TIMED_OUT,
+            ..self.wait().await
+        }
+    }
+}
+
+/// Reaps `pid` if it has exited, returning its status as `$?` shows it.
+fn try_reap(pid: i32) -> Option<i32> {
+    let mut status: i32 = 0;
+    match unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) } {
+        0 => None,
+        // Already reaped, as by the `wait` builtin
+        -1 => Some(0),
+        _ if libc::WIFSIGNALED(status) => {
+            trace!(pid, signal = libc::WTERMSIG(status), "process ended");
+            Some(128 + libc::WTERMSIG(status))
+        }
+        _ => {
+            trace!(pid, status = libc::WEXITSTATUS(status), "process ended");
+            Some(libc::WEXITSTATUS(status))
+        }
+    }
+}
+
+/// Checks whether a pipeline has to run in the shell itself: a single
+/// command that is a builtin, an assignment, or anything else that isn't an
+/// executable on the `PATH`, such as a plugin or a directory for `autocd`.
+fn runs_in_shell(input: &str, shell: &Shell) -> bool {
+    if shell.options.no_exec {
+        return true;
+    }
+    if input.contains('|') {
+        return false;
+    }
+    // A word that doesn't expand is reported by the shell
+    let Ok(words) = tokenize_expanded(input, &shell.variables) else {
+        return true;
+    };
+    let (_, words) = crate::exec::split_assignments(words);
+    match words.first() {
+        Some(command) => {
+            command.parse::<Builtin>().is_ok()
+                || shell.plugins.has_builtin(command)
+                || crate::exec::resolve_command(command, shell).is_none()
+        }
+        None => true,
+    }
+}
+
+/// Watches for changes in the shell's background jobs, so that a task on
+/// the runtime can report a job as soon as it finishes.
+///
+/// # Example
+/// ```no_run
+/// use codecrafters_shell::Shell;
+/// use codecrafters_shell::runtime::JobMonitor;
+/// use std::io::Write;
+///
+/// # async fn report(shell: &mut Shell) -> std::io::Result<()> {
+/// let mut monitor = JobMonitor::new()?;
+/// loop {
+///     monitor.changed().await;
+///     for job in shell.reap_jobs() {
+///         let mut stdout = shell.streams.stdout();
+///         writeln!(stdout, "[{}]+  Done  {}", job.id, job.command)?;
+///     }
+/// }
+/// # }
+/// ```
+pub struct JobMonitor {
+    children: Signal,
+}
+
+impl JobMonitor {
+    /// Starts watching. Must be called from within a runtime.
+    pub fn new() -> io::Result<Self> {
+        Ok(JobMonitor {
+            children: signal(SignalKind::child())?,
+        })
+    }
+
+    /// Waits until a child process of the shell exits, stops or continues.
+    /// Changes since the monitor was created, or since the last call, are
+    /// reported at once.
+    pub async fn changed(&mut self) {
+        self.children.recv().await;
+    }
+}
+
+impl Shell {
+    /// Runs a command line like [`Shell::execute`], waiting for external
+    /// commands without blocking the runtime.
+    ///
+    /// Builtins and assignments still run in the shell, as they change it,
+    /// and background jobs and coprocesses start as they would otherwise.
+    pub async fn execute_async(&mut self, command: &CommandLine) -> CommandResult {
+        self.execute_within(command, None).await
+    }
+
+    /// Runs a command line like [`Shell::execute_async`], terminating the
+    /// pipeline if it runs for longer than `limit`. Its status is then
+    /// [`TIMED_OUT`].
+    ///
+    /// # Example
+    /// ```
+    /// use codecrafters_shell::Shell;
+    /// use codecrafters_shell::runtime;
+    /// use codecrafters_shell::shell::CommandLine;
+    /// use std::time::Duration;
+    ///
+    /// let mut shell = Shell::default();
+    /// let command = CommandLine::parse("sleep 5 | cat").unwrap();
+    /// let result = runtime::build()
+    ///     .unwrap()
+    ///     .block_on(shell.execute_timeout(&command, Duration::from_millis(100)));
+    /// assert_eq!(result.status, 124);
+    /// assert_eq!(shell.variables.status(), 124);
+    /// ```
+    pub async fn execute_timeout(
+        &mut self,
+        command: &CommandLine,
+        limit: Duration,
+    ) -> CommandResult {
+        self.execute_within(command, Some(limit)).await
+    }
+
+    async fn execute_within(
+        &mut self,
+        command: &CommandLine,
+        limit: Option<Duration>,
+    ) -> CommandResult {
+        let line = match command {
+            CommandLine::Foreground(line) if !runs_in_shell(line, self) => line,
+            _ => return self.execute(command),
+        };
+        let started = Instant::now();
+        let result = match spawn(line, self) {
+            Ok(mut pipeline) => match limit {
+                Some(limit) => pipeline.wait_timeout(limit).await,
+                None => pipeline.wait().await,
+            },
+            Err(e) => {
+                trace!(error = %e, code = %e.code(), "command failed");
+                error::report(&e, self);
+                CommandResult {
+                    duration: started.elapsed(),
+                    ..CommandResult::finished(e.status())
+                }
+            }
+        };
+        trace!(status = result.status, duration = ?result.duration, "command finished");
+        self.variables.set_status(result.status);
+        result
+    }
+
+    /// Reads and runs one command like [`Shell::read_and_execute`], from
+    /// an async reader and with external commands run like
+    /// [`Shell::execute_async`].
+    pub async fn read_and_execute_async<R: AsyncBufRead + Unpin>(
+        &mut self,
+        input: &mut R,
+    ) -> io::Result<ShellStatus> {
+        let mut command = String::new();
+        loop {
+            let mut line = String::new();
+            if input.read_line(&mut line).await? == 0 {
+                return Ok(self.end_of_input(&command));
+            }
+            if shell::continue_command(&mut command, &line) {
+                return Ok(self.execute_read_async(&command).await);
+            }
+        }
+    }
+
+    /// Reads one command from the process's stdin like
+    /// [`Shell::read_command`], on a blocking thread.
+    ///
+    /// Lines are read through the same buffer as the `read` builtin, so
+    /// that it still gets the lines after the command.
+    pub(crate) async fn read_stdin_command(&self) -> io::Result<Result<String, ShellStatus>> {
+        let mut command = String::new();
+        loop {
+            let line = tokio::task::spawn_blocking(|| {
+                let mut line = String::new();
+                io::stdin().lock().read_line(&mut line).map(|_| line)
+            })
+            .await
+            .map_err(io::Error::other)??;
+            if line.is_empty() {
+                return Ok(Err(self.end_of_input(&command)));
+            }
+            if shell::continue_command(&mut command, &line) {
+                return Ok(Ok(command));
+            }
+        }
+    }
+
+    /// Runs a command that was read like [`Shell::execute_read`], with
+    /// external commands run like [`Shell::execute_async`].
+    pub(crate) async fn execute_read_async(&mut self, command: &str) -> ShellStatus {
+        let Some(parsed) = self.accept(command) else {
+            return ShellStatus::Continue;
+        };
+        let line = parsed.to_string();
+        if let ShellStatus::Exit(code) = hooks::run_preexec(&line, self) {
+            return ShellStatus::Exit(code);
+        }
+        let result = self.execute_async(&parsed).await;
+        hooks::run_postexec(&line, &result, self);
+        result.shell
+    }
+}
@@ -0,0 +1,513 @@
+use crate::completion::Completions;
+use crate::environment::WorkingDir;
+use crate::error::{self, ShellError};
+use crate::executor::SharedExecutor;
+use crate::history::{self, HistControl, History};
+use crate::hooks::Hooks;
+use crate::jobs::Job;
+use crate::keymap::Keymap;
+use crate::options::ShellOptions;
+use crate::parser::{self, Continuation};
+use crate::plugin::Plugins;
+use crate::streams::{Buffer, Streams};
+use crate::variables::Variables;
+use crate::{CommandResult, JobTable, ShellStatus, exec, hooks, startup};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::sync::Arc;
+
+/// A command line, split into how the shell runs it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandLine {
+    /// A pipeline the shell waits for.
+    Foreground(String),
+    /// A pipeline run as a background job (`command &`).
+    Background(String),
+    /// A pipeline run as a coprocess (`coproc command`).
+    Coproc(String),
+}
+
+impl fmt::Display for CommandLine {
+    /// Writes the command line as it would be typed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandLine::Foreground(line) => write!(f, "{}", line),
+            CommandLine::Background(line) => write!(f, "{} &", line),
+            CommandLine::Coproc(line) => write!(f, "coproc {}", line),
+        }
+    }
+}
+
+impl CommandLine {
+    /// Parses a command line, or returns `None` if it is blank.
+    ///
+    /// # Example
+    /// ```
+    /// use codecrafters_shell::shell::CommandLine;
+    /// assert_eq!(
+    ///     CommandLine::parse("sleep 1 &"),
+    ///     Some(CommandLine::Background("sleep 1".to_string()))
+    /// );
+    /// assert_eq!(
+    ///     CommandLine::parse("true && false"),
+    ///     Some(CommandLine::Foreground("true && false".to_string()))
+    /// );
+    /// assert_eq!(CommandLine::parse("  "), None);
+    /// ```
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() {
+            return None;
+        }
+        if let Some(command) = line.strip_prefix("coproc ") {
+            return Some(CommandLine::Coproc(command.trim().to_string()));
+        }
+        // A trailing `&` (but not `&&`) runs the command as a background job
+        if let Some(command) = line.strip_suffix('&')
+            && !command.ends_with('&')
+        {
+            return Some(CommandLine::Background(command.trim().to_string()));
+        }
+        Some(CommandLine::Foreground(line.to_string()))
+    }
+}
+
+/// What commands run by [`Shell::capture`] wrote, and how they ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Captured {
+    /// Everything written to stdout.
+    pub stdout: String,
+    /// Everything written to stderr.
+    pub stderr: String,
+    /// The status of the `exit` that ended them, or else that of the last
+    /// command.
+    pub status: i32,
+}
+
+/// The state of a running shell: its options, and what it builds up as it
+/// runs, such as its variables, history and jobs.
+///
+/// The interactive shell drives this from its line editor with
+/// [`Shell::run_interactive`]; code embedding the shell can do the same, or
+/// run commands with [`Shell::execute`], script files with
+/// [`Shell::run_script`], whole scripts with [`Shell::run_commands`], or
+/// lines read from any reader with
+/// [`Shell::read_and_execute`]. The streams commands use can be replaced
+/// through [`Shell::streams`], or their output captured with
+/// [`Shell::capture`].
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::Shell;
+///
+/// let mut shell = Shell::default();
+/// shell.run_commands("greeting=hello\nset -o vi");
+/// assert_eq!(shell.variables.get("greeting").as_deref(), Some("hello"));
+/// assert!(shell.options.vi);
+/// ```
+#[derive(Default)]
+pub struct Shell {
+    /// The flags the shell was started with, and those set with `set -o`
+    /// and `shopt`.
+    pub options: ShellOptions,
+    /// The command lines entered so far, including those loaded from the
+    /// history file.
+    pub history: History,
+    /// Shell variables, set with `name=value` and exported with `export`.
+    pub variables: Variables,
+    /// The working directory, changed with `cd`, that commands run in and
+    /// relative paths are taken from.
+    pub cwd: WorkingDir,
+    /// The stdin, stdout and stderr commands use unless redirected.
+    pub streams: Streams,
+    /// What starts external commands: real processes, unless replaced.
+    pub executor: SharedExecutor,
+    /// Callbacks registered by code embedding the shell, such as those run
+    /// before each prompt.
+    pub hooks: Hooks,
+    /// Builtins, completers and prompt segments of the plugins loaded with
+    /// `plugin load`.
+    pub plugins: Plugins,
+    /// The commands set with `trap`, by the condition they run on. Only
+    /// `EXIT` is supported: its commands run once as the shell exits.
+    pub traps: BTreeMap<String, String>,
+    /// Key bindings for the line editor, changed with `bind`.
+    pub keymap: Keymap,
+    /// Fish-style abbreviations defined with `abbr`, expanded in place by
+    /// the line editor.
+    pub abbreviations: Arc<BTreeMap<String, String>>,
+    /// Completions for the arguments of specific commands, defined with
+    /// `complete`.
+    pub completions: Completions,
+    /// The background jobs and coprocesses started.
+    pub jobs: JobTable,
+}
+
+impl Shell {
+    /// Creates a shell with the given options and no jobs.
+    pub fn new(options: ShellOptions) -> Self {
+        Shell {
+            options,
+            ..Shell::default()
+        }
+    }
+
+    /// Returns a copy of the shell to run commands in as a subshell, such
+    /// as a command substitution, so that nothing they do changes this
+    /// shell. It has none of this shell's jobs.
+    ///
+    /// Only the variables, working directory and traps are copied. The
+    /// history, bindings, completions, abbreviations, hooks and plugins
+    /// are shared, and copied only if the subshell changes them.
+    pub fn subshell(&self) -> Shell {
+        Shell {
+            options: self.options.clone(),
+            history: self.history.clone(),
+            variables: self.variables.clone(),
+            cwd: self.cwd.clone(),
+            streams: self.streams.clone(),
+            executor: self.executor.clone(),
+            hooks: self.hooks.clone(),
+            plugins: self.plugins.clone(),
+            traps: self.traps.clone(),
+            keymap: self.keymap.clone(),
+            abbreviations: self.abbreviations.clone(),
+            completions: self.completions.clone(),
+            jobs: JobTable::default(),
+        }
+    }
+
+    /// Changes the working directory, as `cd` does, setting `PWD` to the
+    /// new directory and `OLDPWD` to the one left, both exported.
+    pub fn change_dir(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let old = self.cwd.to_string_lossy().into_owned();
+        self.cwd.change(path)?;
+        self.variables.export("OLDPWD", Some(&old));
+        self.variables
+            .export("PWD", Some(&self.cwd.to_string_lossy()));
+        Ok(())
+    }
+
+    /// Prepares the shell as the program `arg0`: sets the path variables,
+    /// `PWD` and `$0`, loads the history file, and runs the startup files.
+    /// The startup files run even with `--dry-run`, which applies to the
+    /// commands after them.
+    ///
+    /// Returns `Exit` if a startup file exits the shell.
+    pub fn start(&mut self, arg0: &str) -> ShellStatus {
+        startup::set_path_variables(self);
+        self.variables
+            .export("PWD", Some(&self.cwd.to_string_lossy()));
+        self.variables.set_arguments(vec![arg0.to_string()]);
+
+        // Commands from earlier sessions; a missing history file just means
+        // there are none yet
+        self.history.set_limit(History::limit(&self.variables));
+        if let Some(path) = History::file(&self.variables) {
+            let _ = self.history.load(&path);
+        }
+
+        // The user's settings, run like any other commands
+        self.options.login = startup::is_login(arg0, self);
+        self.options.posix = startup::is_posix(arg0, self);
+        let no_exec = std::mem::take(&mut self.options.no_exec);
+        let status = startup::source_startup_files(self);
+        self.options.no_exec |= no_exec;
+        status
+    }
+
+    /// Runs a command line, starting background jobs and coprocesses in
+    /// the job table. See [`exec::eval`].
+    pub fn execute(&mut self, command: &CommandLine) -> CommandResult {
+        exec::eval(self, command)
+    }
+
+    /// Runs a command line entered by the user: like [`Shell::execute`],
+    /// but with the preexec callbacks and `PREEXEC_COMMAND` run before it
+    /// and the postexec callbacks after it.
+    ///
+    /// # Example
+    /// ```
+    /// use codecrafters_shell::Shell;
+    /// use codecrafters_shell::shell::CommandLine;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let mut shell = Shell::default();
+    /// let seen = Arc::new(Mutex::new(Vec::new()));
+    /// let log = seen.clone();
+    /// shell.hooks.add_preexec(move |line, _| log.lock().unwrap().push(line.to_string()));
+    ///
+    /// shell.execute_with_hooks(&CommandLine::parse("x=1").unwrap());
+    /// assert_eq!(*seen.lock().unwrap(), vec!["x=1"]);
+    /// ```
+    pub fn execute_with_hooks(&mut self, command: &CommandLine) -> CommandResult {
+        let line = command.to_string();
+        if let ShellStatus::Exit(code) = hooks::run_preexec(&line, self) {
+            return CommandResult::exit(code);
+        }
+        let result = self.execute(command);
+        hooks::run_postexec(&line, &result, self);
+        result
+    }
+
+    /// Runs the commands of a script one line at a time, as `source` does,
+    /// but with background jobs tracked by this shell. Stops early if a
+    /// command exits the shell, returning that status.
+    pub fn run_commands(&mut self, script: &str) -> ShellStatus {
+        exec::eval_script(self, script)
+    }
+
+    /// Runs the script file at `path` like [`Shell::run_commands`], with
+    /// `args` as its positional parameters `$1`, `$2` and so on, and the
+    /// path as `$0`. The parameters the shell had are restored afterwards.
+    ///
+    /// Returns the status of the `exit` that ended the script, or else that
+    /// of its last command.
+    ///
+    /// # Example
+    /// ```
+    /// use codecrafters_shell::Shell;
+    /// use std::path::Path;
+    ///
+    /// let path = std::env::temp_dir().join("run-script-example.sh");
+    /// std::fs::write(&path, "greeting=\"hello $1\"\nexit $#").unwrap();
+    /// let mut shell = Shell::default();
+    /// let status = shell.run_script(&path, &["world".to_string()]).unwrap();
+    /// assert_eq!(status, 1);
+    /// assert_eq!(shell.variables.get("greeting").as_deref(), Some("hello world"));
+    /// ```
+    pub fn run_script(&mut self, path: &Path, args: &[String]) -> io::Result<i32> {
+        let script = fs::read_to_string(path)?;
+        let mut arguments = vec![path.display().to_string()];
+        arguments.extend_from_slice(args);
+        let saved = self.variables.set_arguments(arguments);
+        let status = match self.run_commands(&script) {
+            ShellStatus::Exit(code) => code,
+            ShellStatus::Continue => self.variables.status(),
+        };
+        self.variables.set_arguments(saved);
+        Ok(status)
+    }
+
+    /// Runs commands like [`Shell::run_commands`], capturing what they
+    /// write to stdout and stderr instead of passing it on. Redirected
+    /// output still goes where it was redirected.
+    ///
+    /// The commands run in the shell itself, so the variables they set and
+    /// the directory they change to stay in effect. An `exit` only ends
+    /// the captured commands, with its status.
+    ///
+    /// # Example
+    /// ```
+    /// use codecrafters_shell::Shell;
+    ///
+    /// let mut shell = Shell::default();
+    /// let captured = shell.capture("greeting=hello\necho $greeting\ntype no-such-command");
+    /// assert_eq!(captured.stdout, "hello\n");
+    /// assert_eq!(captured.stderr, "no-such-command: not found\n");
+    /// assert_eq!(captured.status, 1);
+    /// assert_eq!(shell.variables.get("greeting").as_deref(), Some("hello"));
+    /// ```
+    pub fn capture(&mut self, commands: &str) -> Captured {
+        let (stdout, stderr) = (Buffer::default(), Buffer::default());
+        let streams = self.streams.clone();
+        self.streams = streams
+            .clone()
+            .with_stdout(stdout.clone())
+            .with_stderr(stderr.clone());
+        let status = match self.run_commands(commands) {
+            ShellStatus::Exit(code) => code,
+            ShellStatus::Continue => self.variables.status(),
+        };
+        self.streams = streams;
+        Captured {
+            stdout: stdout.contents(),
+            stderr: stderr.contents(),
+            status,
+        }
+    }
+
+    /// Reads one command from `input`, reading further lines while a quote
+    /// is open or a line ends in a backslash, then expands history
+    /// references, records it in the history, and runs it.
+    ///
+    /// Returns `Exit` with the status of the last command at the end of the
+    /// input.
+    pub fn read_and_execute<R: BufRead>(&mut self, input: &mut R) -> io::Result<ShellStatus> {
+        match self.read_command(input)? {
+            Ok(command) => Ok(self.execute_read(&command)),
+            Err(status) => Ok(status),
+        }
+    }
+
+    /// Reads one command from `input` as [`Shell::read_and_execute`] does,
+    /// without running it. At the end of the input, returns `Err` with the
+    /// status to exit with.
+    pub(crate) fn read_command<R: BufRead>(
+        &self,
+        input: &mut R,
+    ) -> io::Result<Result<String, ShellStatus>> {
+        let mut command = String::new();
+        loop {
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(Err(self.end_of_input(&command)));
+            }
+            if continue_command(&mut command, &line) {
+                return Ok(Ok(command));
+            }
+        }
+    }
+
+    /// Runs a command returned by [`Shell::read_command`].
+    pub(crate) fn execute_read(&mut self, command: &str) -> ShellStatus {
+        let Some(parsed) = self.accept(command) else {
+            return ShellStatus::Continue;
+        };
+        self.execute_with_hooks(&parsed).shell
+    }
+
+    /// Expands history references in a command that was read, and records
+    /// it in the history. Returns the command line to run, or `None` if
+    /// there is nothing to run.
+    pub(crate) fn accept(&mut self, command: &str) -> Option<CommandLine> {
+        let command = match history::expand_history(command, &self.history) {
+            Ok(expanded) => expanded,
+            Err(message) => {
+                error::report(&ShellError::ParseError(message), self);
+                return None;
+            }
+        };
+        let parsed = CommandLine::parse(&command)?;
+        self.history.set_limit(History::limit(&self.variables));
+        if self
+            .history
+            .record(&command, HistControl::from_variables(&self.variables))
+        {
+            self.save_history();
+        }
+        Some(parsed)
+    }
+
+    /// Ends reading commands with `command` only partly read, returning
+    /// `Exit` with the status of the last command.
+    pub(crate) fn end_of_input(&self, command: &str) -> ShellStatus {
+        if let Some(Continuation::Quote(quote)) = parser::continuation(command) {
+            let message = format!("unexpected EOF while looking for matching `{}'", quote);
+            error::report(&ShellError::ParseError(message), self);
+        }
+        ShellStatus::Exit(self.variables.status())
+    }
+
+    /// Appends new history entries to the history file (see
+    /// [`History::file`]). With `set -o sharehistory`, also reads the
+    /// entries other shells added to it.
+    pub fn save_history(&mut self) {
+        let Some(path) = History::file(&self.variables) else {
+            return;
+        };
+        self.history
+            .set_timestamps(History::timestamps(&self.variables));
+        let result = if self.options.share_history {
+            self.history.sync(&path)
+        } else {
+            self.history.append(&path)
+        };
+        if let Err(e) = result {
+            error::report(&ShellError::io(path.display().to_string(), e), self);
+        }
+    }
+
+    /// Removes the jobs that have finished from the job table and returns
+    /// them, so that the caller can report them. The variables of a
+    /// coprocess that finished are unset, as its descriptors are closed.
+    pub fn reap_jobs(&mut self) -> Vec<Job> {
+        let done = self.jobs.reap();
+        for coproc in done.iter().filter_map(|job| job.coproc.as_ref()) {
+            let variables = &mut self.variables;
+            variables.unset(&coproc.name);
+            variables.unset(&format!("{}_PID", coproc.name));
+        }
+        done
+    }
+
+    /// Returns the warning to print if exiting now would abandon jobs.
+    pub fn exit_warning(&mut self) -> Option<&'static str> {
+        self.jobs.update();
+        if self.jobs.has_stopped() {
+            Some("There are stopped jobs.")
+        } else if self.jobs.has_unfinished() {
+            Some("There are running jobs.")
+        } else {
+            None
+        }
+    }
+
+    /// Does what is due before the shell exits with `status`: runs the
+    /// `EXIT` trap, saves the history and, for a login shell, runs the
+    /// logout file.
+    ///
+    /// Returns the status to exit with: that of an `exit` in the trap, or
+    /// else `status`.
+    ///
+    /// # Example
+    /// ```
+    /// use codecrafters_shell::Shell;
+    ///
+    /// let mut shell = Shell::default();
+    /// shell.variables.set("HISTFILE", "");
+    /// shell.run_commands("trap 'exit $(( $? + 1 ))' EXIT");
+    /// assert_eq!(shell.finish(2), 3);
+    /// ```
+    pub fn finish(&mut self, status: i32) -> i32 {
+        let status = self.run_exit_trap(status);
+        self.save_history();
+        // Without histappend, the file ends up holding this shell's history
+        if self.options.hist_overwrite
+            && let Some(path) = History::file(&self.variables)
+            && let Err(e) = self.history.save(&path)
+        {
+            error::report(&ShellError::io(path.display().to_string(), e), self);
+        }
+        if self.options.login
+            && let Some(logout) = startup::logout_file()
+        {
+            startup::source_if_present(&logout, self);
+        }
+        status
+    }
+
+    /// Runs the commands set with `trap ... EXIT`, once, with `$?` set to
+    /// the status the shell is exiting with.
+    fn run_exit_trap(&mut self, status: i32) -> i32 {
+        let Some(command) = self.traps.remove("EXIT") else {
+            return status;
+        };
+        self.variables.set_status(status);
+        match self.run_commands(&command) {
+            ShellStatus::Exit(code) => code,
+            ShellStatus::Continue => status,
+        }
+    }
+}
+
+/// Adds a line that was read to `command`, returning true once the command
+/// is complete: false while a quote is open or the line ends in a
+/// backslash.
+pub(crate) fn continue_command(command: &mut String, line: &str) -> bool {
+    command.push_str(line.trim_end_matches('\n'));
+    match parser::continuation(command) {
+        Some(Continuation::Backslash) => {
+            command.pop();
+            false
+        }
+        Some(Continuation::Quote(_)) => {
+            command.push('\n');
+            false
+        }
+        None => true,
+    }
+}
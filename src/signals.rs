@@ -0,0 +1,65 @@
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+/// Set by the SIGWINCH handler when the terminal window changes size.
+static WINDOW_RESIZED: AtomicBool = AtomicBool::new(false);
+
+/// Set by the SIGTERM and SIGHUP handler to the signal received, or 0.
+static TERMINATED: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn handle_sigwinch(_: libc::c_int) {
+    WINDOW_RESIZED.store(true, Ordering::SeqCst);
+}
+
+/// Installs a SIGWINCH handler that records terminal resizes.
+///
+/// The handler is installed without `SA_RESTART`, so a blocking read of
+/// keyboard input fails with `ErrorKind::Interrupted` and the line editor
+/// gets a chance to redraw for the new size.
+pub fn install_resize_handler() {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handle_sigwinch as *const () as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGWINCH, &action, std::ptr::null_mut());
+    }
+}
+
+/// Returns true if the terminal was resized since the last call.
+pub fn take_resize() -> bool {
+    WINDOW_RESIZED.swap(false, Ordering::SeqCst)
+}
+
+/// Records a terminal resize that was reported other than by SIGWINCH,
+/// such as by crossterm.
+pub fn note_resize() {
+    WINDOW_RESIZED.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn handle_termination(signal: libc::c_int) {
+    TERMINATED.store(signal, Ordering::SeqCst);
+}
+
+/// Installs a handler for SIGTERM and SIGHUP that records the signal
+/// instead of killing the shell, so that it can run its `EXIT` trap and
+/// save its history before exiting.
+///
+/// Like the SIGWINCH handler, it is installed without `SA_RESTART`, so
+/// that the line editor's read of keyboard input is interrupted. Scripts
+/// check for the signal between commands.
+pub fn install_termination_handler() {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handle_termination as *const () as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGTERM, &action, std::ptr::null_mut());
+        libc::sigaction(libc::SIGHUP, &action, std::ptr::null_mut());
+    }
+}
+
+/// Returns the SIGTERM or SIGHUP received since the last call, if any.
+pub fn take_termination() -> Option<i32> {
+    match TERMINATED.swap(0, Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
@@ -0,0 +1,79 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// The largest [`distance`] between two names that still counts as a
+/// misspelling.
+const MAX_DISTANCE: usize = 2;
+
+/// Measures how far apart two names are, in the way bash's `cdspell` does:
+/// 0 if they are the same, 1 if two neighbouring characters are swapped, 2
+/// if one character is wrong, added or missing, and 3 otherwise.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::spell::distance;
+///
+/// assert_eq!(distance("Documents", "Documents"), 0);
+/// assert_eq!(distance("Documnets", "Documents"), 1);
+/// assert_eq!(distance("Dokuments", "Documents"), 2);
+/// assert_eq!(distance("Documets", "Documents"), 2);
+/// assert_eq!(distance("Downloads", "Documents"), 3);
+/// ```
+pub fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let common = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[common..], &b[common..]);
+    if a.is_empty() && b.is_empty() {
+        return 0;
+    }
+    if a.len() >= 2 && b.len() >= 2 && a[0] == b[1] && a[1] == b[0] && a[2..] == b[2..] {
+        return 1;
+    }
+    let changed = !a.is_empty() && !b.is_empty() && a[1..] == b[1..];
+    let added = !a.is_empty() && a[1..] == *b;
+    let missing = !b.is_empty() && *a == b[1..];
+    if changed || added || missing { 2 } else { 3 }
+}
+
+/// Corrects small misspellings in the names of a directory path, as bash's
+/// `cdspell` does for `cd`.
+///
+/// Each component that doesn't exist is replaced with the directory in its
+/// parent whose name is closest by [`distance`], if any is close enough.
+/// A relative path is taken from `base`, the working directory, and stays
+/// relative. Returns `None` if nothing needed correcting or the path
+/// couldn't be corrected into a directory.
+pub fn correct_dir(base: &Path, path: &Path) -> Option<PathBuf> {
+    let mut corrected = PathBuf::new();
+    let mut changed = false;
+    for component in path.components() {
+        let Component::Normal(name) = component else {
+            corrected.push(component);
+            continue;
+        };
+        let candidate = corrected.join(name);
+        if base.join(&candidate).exists() {
+            corrected = candidate;
+            continue;
+        }
+        let name = name.to_str()?;
+        let mut entries: Vec<String> = fs::read_dir(base.join(&corrected))
+            .ok()?
+            .flatten()
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect();
+        // Of equally close names, take the first in byte order, so that
+        // the choice doesn't depend on the order of the directory
+        entries.sort();
+        let best = entries
+            .into_iter()
+            .map(|entry| (distance(name, &entry), entry))
+            .filter(|(distance, _)| *distance <= MAX_DISTANCE)
+            .min_by_key(|(distance, _)| *distance)?;
+        corrected.push(best.1);
+        changed = true;
+    }
+    (changed && base.join(&corrected).is_dir()).then_some(corrected)
+}
@@ -0,0 +1,232 @@
+use crate::Shell;
+use crate::ShellStatus;
+use crate::dirs;
+use crate::error::{self, ShellError};
+use crate::history::History;
+use crate::variables::Variables;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// The startup file in the configuration directory that interactive
+/// shells read.
+pub const CONFIG_RC_FILE: &str = "rc";
+
+/// The startup file in the home directory that interactive shells read if
+/// there is none in the configuration directory.
+pub const RC_FILE: &str = ".codecraftersshrc";
+
+/// The system-wide startup file that login shells read first.
+pub const SYSTEM_PROFILE: &str = "/etc/profile";
+
+/// The startup file in the home directory that login shells read.
+pub const PROFILE: &str = ".profile";
+
+/// The file in the configuration directory holding saved shell options,
+/// read at startup before the other startup files.
+pub const OPTIONS_FILE: &str = "options";
+
+/// The file in the configuration directory that login shells read when
+/// they exit.
+pub const CONFIG_LOGOUT_FILE: &str = "logout";
+
+/// The file in the home directory that login shells read when they exit
+/// if there is none in the configuration directory.
+pub const LOGOUT_FILE: &str = ".codecrafterssh_logout";
+
+/// Checks whether the shell was started as a login shell: by a program
+/// such as `login` that puts `-` before its name, or with `-l`/`--login`.
+pub fn is_login(arg0: &str, shell: &Shell) -> bool {
+    arg0.starts_with('-') || shell.options.login
+}
+
+/// Checks whether the shell follows POSIX startup rules: when started as
+/// `sh` or with `--posix`.
+pub fn is_posix(arg0: &str, shell: &Shell) -> bool {
+    let name = Path::new(arg0.trim_start_matches('-')).file_name();
+    name.is_some_and(|name| name == "sh") || shell.options.posix
+}
+
+/// Returns the file named by `ENV`, which POSIX shells read at startup in
+/// place of an rc file, with `~` and `$NAME`/`${NAME}` in it expanded.
+/// Returns `None` if `ENV` is unset or empty.
+pub fn env_file(variables: &Variables) -> Option<PathBuf> {
+    let value = variables.get("ENV").filter(|value| !value.is_empty())?;
+    Some(PathBuf::from(expand_variables(&value, variables)))
+}
+
+/// Expands a leading `~` and the `$NAME` and `${NAME}` references in a
+/// startup file name. Unset variables expand to nothing.
+///
+/// ```
+/// use codecrafters_shell::startup::expand_variables;
+/// use codecrafters_shell::variables::Variables;
+///
+/// let mut variables = Variables::default();
+/// variables.set("RC_DIR", "/etc/sh");
+/// assert_eq!(
+///     expand_variables("$RC_DIR/env.${RC_DIR_UNSET}sh", &variables),
+///     "/etc/sh/env.sh"
+/// );
+/// ```
+pub fn expand_variables(value: &str, variables: &Variables) -> String {
+    let mut expanded = String::new();
+    let mut rest = value;
+    if let Some(after) = rest.strip_prefix('~')
+        && (after.is_empty() || after.starts_with('/'))
+        && let Some(home) = variables.get("HOME")
+    {
+        expanded.push_str(&home);
+        rest = after;
+    }
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, remainder) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => (braced, ""),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        if name.is_empty() {
+            expanded.push('$');
+        } else {
+            expanded.push_str(&variables.get(name).unwrap_or_default());
+        }
+        rest = remainder;
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Returns the files a login shell reads at startup, in order:
+/// `/etc/profile`, then `~/.profile`.
+pub fn profile_files() -> Vec<PathBuf> {
+    let mut files = vec![PathBuf::from(SYSTEM_PROFILE)];
+    files.extend(env::var_os("HOME").map(|home| Path::new(&home).join(PROFILE)));
+    files
+}
+
+/// Returns the file a login shell reads when it exits,
+/// `$XDG_CONFIG_HOME/codecrafters-shell/logout`, or
+/// `~/.codecrafterssh_logout` if only that exists.
+pub fn logout_file() -> Option<PathBuf> {
+    dirs::resolve(
+        dirs::config_dir().map(|dir| dir.join(CONFIG_LOGOUT_FILE)),
+        dirs::home_file(LOGOUT_FILE),
+    )
+}
+
+/// Returns the interactive startup file,
+/// `$XDG_CONFIG_HOME/codecrafters-shell/rc`, or `~/.codecraftersshrc` if
+/// only that exists. Returns `None` if there is no home directory.
+pub fn rc_file() -> Option<PathBuf> {
+    dirs::resolve(
+        dirs::config_dir().map(|dir| dir.join(CONFIG_RC_FILE)),
+        dirs::home_file(RC_FILE),
+    )
+}
+
+/// Returns the file of saved shell options,
+/// `$XDG_CONFIG_HOME/codecrafters-shell/options`. It holds commands such as
+/// those printed by `shopt -p` and `shopt -op`, so options saved with
+/// `shopt -p > "$CODECRAFTERS_SHELL_CONFIG_DIR/options"` stick between
+/// sessions.
+pub fn options_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(OPTIONS_FILE))
+}
+
+/// Sets shell variables holding the paths the shell uses, so that startup
+/// files and commands can refer to them: `CODECRAFTERS_SHELL_CONFIG_DIR`,
+/// `CODECRAFTERS_SHELL_STATE_DIR`, and `HISTFILE` unless it is already
+/// set. The variables are not exported.
+pub fn set_path_variables(shell: &mut Shell) {
+    let variables = &mut shell.variables;
+    let paths = [
+        ("CODECRAFTERS_SHELL_CONFIG_DIR", dirs::config_dir()),
+        ("CODECRAFTERS_SHELL_STATE_DIR", dirs::state_dir()),
+    ];
+    for (name, path) in paths {
+        if let Some(path) = path {
+            variables.set(name, &path.to_string_lossy());
+        }
+    }
+    if variables.get("HISTFILE").is_none()
+        && let Some(path) = History::default_file()
+    {
+        variables.set("HISTFILE", &path.to_string_lossy());
+    }
+}
+
+/// Runs the startup files for how the shell was started, stopping if one
+/// of them exits the shell:
+///
+/// - Unless started with `--norc`, the shell first reads its saved options
+///   (see [`options_file`]).
+/// - A login shell reads `/etc/profile` and `~/.profile`.
+/// - In POSIX mode, an interactive shell then reads the file named by
+///   `ENV`.
+/// - Otherwise an interactive shell that isn't a login shell reads its rc
+///   file (see [`rc_file`]), or the file given with `--rcfile`, unless
+///   started with `--norc`.
+///
+/// A shell running a command given with `-c`, or a script, isn't
+/// interactive.
+pub fn source_startup_files(shell: &mut Shell) -> ShellStatus {
+    let mut files = Vec::new();
+    if !shell.options.norc {
+        files.extend(options_file());
+    }
+    if shell.options.login {
+        files.extend(profile_files());
+    }
+    let mut rcfile = None;
+    let interactive = shell.options.command.is_none() && shell.options.script.is_none();
+    if shell.options.posix && interactive {
+        files.extend(env_file(&shell.variables));
+    } else if interactive && !shell.options.posix && !shell.options.login && !shell.options.norc {
+        match shell.options.rcfile.clone() {
+            Some(path) => rcfile = Some(path),
+            None => files.extend(rc_file()),
+        }
+    }
+    for file in files {
+        let status = source_if_present(&file, shell);
+        if matches!(status, ShellStatus::Exit(_)) {
+            return status;
+        }
+    }
+    // A file named with --rcfile is expected to exist
+    if let Some(rcfile) = rcfile {
+        return source(&rcfile, shell);
+    }
+    ShellStatus::Continue
+}
+
+/// Runs the commands of a startup file if it exists.
+///
+/// Returns the status of the file's commands, so that an `exit` in it
+/// ends the shell.
+pub fn source_if_present(path: &Path, shell: &mut Shell) -> ShellStatus {
+    if !path.exists() {
+        return ShellStatus::Continue;
+    }
+    source(path, shell)
+}
+
+/// Runs the commands of a startup file, reporting an error if it can't be
+/// read.
+pub fn source(path: &Path, shell: &mut Shell) -> ShellStatus {
+    match crate::source_file(path, shell) {
+        Ok(status) => status,
+        Err(e) => {
+            error::report(&ShellError::io(path.display().to_string(), e), shell);
+            ShellStatus::Continue
+        }
+    }
+}
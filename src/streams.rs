@@ -0,0 +1,216 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// A reader shared between the shell and the commands it runs.
+pub type SharedReader = Arc<Mutex<dyn Read + Send>>;
+
+/// A writer shared between the shell and the commands it runs.
+pub type SharedWriter = Arc<Mutex<dyn Write + Send>>;
+
+/// The streams commands read from and write to when they aren't
+/// redirected.
+///
+/// By default these are the shell's own stdin, stdout and stderr. Code
+/// embedding the shell can replace any of them with its own reader or
+/// writer; external commands are then connected to it through a pipe.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::Shell;
+/// use codecrafters_shell::streams::{Buffer, Streams};
+///
+/// let output = Buffer::default();
+/// let errors = Buffer::default();
+/// let mut shell = Shell::default();
+/// shell.streams = Streams::default()
+///     .with_stdout(output.clone())
+///     .with_stderr(errors.clone());
+///
+/// shell.run_commands("echo hello\nno-such-command");
+/// assert_eq!(output.contents(), "hello\n");
+/// assert_eq!(errors.contents(), "no-such-command: command not found\n");
+/// ```
+#[derive(Clone, Default)]
+pub struct Streams {
+    stdin: Option<SharedReader>,
+    stdout: Option<SharedWriter>,
+    stderr: Option<SharedWriter>,
+}
+
+impl fmt::Debug for Streams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Streams")
+            .field("stdin", &self.stdin.is_some())
+            .field("stdout", &self.stdout.is_some())
+            .field("stderr", &self.stderr.is_some())
+            .finish()
+    }
+}
+
+impl Streams {
+    /// Makes commands read their input from `reader`.
+    pub fn with_stdin(mut self, reader: impl Read + Send + 'static) -> Self {
+        self.stdin = Some(Arc::new(Mutex::new(reader)));
+        self
+    }
+
+    /// Makes commands write their output to `writer`.
+    pub fn with_stdout(mut self, writer: impl Write + Send + 'static) -> Self {
+        self.stdout = Some(Arc::new(Mutex::new(writer)));
+        self
+    }
+
+    /// Makes commands and the shell's own error messages go to `writer`.
+    pub fn with_stderr(mut self, writer: impl Write + Send + 'static) -> Self {
+        self.stderr = Some(Arc::new(Mutex::new(writer)));
+        self
+    }
+
+    /// Checks whether these are all the shell's own streams.
+    pub fn is_inherited(&self) -> bool {
+        self.stdin.is_none() && self.stdout.is_none() && self.stderr.is_none()
+    }
+
+    /// Checks whether the standard error is the shell's own.
+    pub fn inherits_stderr(&self) -> bool {
+        self.stderr.is_none()
+    }
+
+    /// Returns a reader for the standard input.
+    pub fn stdin(&self) -> Box<dyn Read + Send> {
+        match &self.stdin {
+            Some(shared) => Box::new(SharedInput(shared.clone())),
+            None => Box::new(io::stdin()),
+        }
+    }
+
+    /// Returns a writer for the standard output.
+    pub fn stdout(&self) -> Box<dyn Write + Send> {
+        match &self.stdout {
+            Some(shared) => Box::new(Shared(shared.clone())),
+            None => Box::new(io::stdout()),
+        }
+    }
+
+    /// Returns a writer for the standard error.
+    pub fn stderr(&self) -> Box<dyn Write + Send> {
+        match &self.stderr {
+            Some(shared) => Box::new(Shared(shared.clone())),
+            None => Box::new(io::stderr()),
+        }
+    }
+
+    /// Connects the replaced streams to pipes for external commands,
+    /// returning the ends to hand to them. The ends for streams that
+    /// weren't replaced are `None`, so commands inherit the shell's own.
+    pub(crate) fn connect(&self) -> Connection {
+        let mut connection = self.connect_output();
+        if let Some(reader) = &self.stdin
+            && let Ok((read, write)) = crate::create_pipe()
+        {
+            let reader = reader.clone();
+            connection.stdin = Some(read);
+            connection.threads.push(thread::spawn(move || {
+                let mut reader = reader.lock().unwrap_or_else(|e| e.into_inner());
+                let mut write = write;
+                // The command may exit without reading everything
+                let _ = io::copy(&mut *reader, &mut write);
+            }));
+        }
+        connection
+    }
+
+    /// Connects only the replaced stdout and stderr, like
+    /// [`connect`](Self::connect). Builtins read their input from the
+    /// streams themselves, but need files to copy one output onto the
+    /// other, as `2>&1` does.
+    pub(crate) fn connect_output(&self) -> Connection {
+        let mut connection = Connection::default();
+        for (shared, end) in [
+            (&self.stdout, &mut connection.stdout),
+            (&self.stderr, &mut connection.stderr),
+        ] {
+            if let Some(writer) = shared
+                && let Ok((read, write)) = crate::create_pipe()
+            {
+                let mut writer = Shared(writer.clone());
+                *end = Some(write);
+                connection.threads.push(thread::spawn(move || {
+                    let mut read = read;
+                    let _ = io::copy(&mut read, &mut writer);
+                }));
+            }
+        }
+        connection
+    }
+}
+
+/// Pipes connecting external commands to replaced streams, with the
+/// threads copying between them.
+#[derive(Default)]
+pub(crate) struct Connection {
+    pub stdin: Option<File>,
+    pub stdout: Option<File>,
+    pub stderr: Option<File>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Connection {
+    /// Waits until everything the commands wrote has been copied. The
+    /// ends handed to the commands must have been closed by then.
+    pub fn finish(self) {
+        drop((self.stdin, self.stdout, self.stderr));
+        for thread in self.threads {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A shared writer, locked for each write.
+struct Shared(SharedWriter);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).flush()
+    }
+}
+
+/// A shared reader, locked for each read.
+struct SharedInput(SharedReader);
+
+impl Read for SharedInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).read(buf)
+    }
+}
+
+/// An in-memory stream that keeps what is written to it. Clones share
+/// the same contents, so one can be given to [`Streams`] and another kept
+/// to read what the commands wrote.
+#[derive(Debug, Clone, Default)]
+pub struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Buffer {
+    /// Returns what has been written so far.
+    pub fn contents(&self) -> String {
+        let bytes = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+use crate::clipboard;
+use std::env;
+use std::io::{self, Read, Stdin, Stdout, Write};
+use std::time::{Duration, Instant};
+use termion::event::Key;
+use termion::input::{Keys, TermRead};
+use termion::raw::{IntoRawMode, RawTerminal};
+
+/// Checks whether the shell may color its output.
+///
+/// Colors are off when `NO_COLOR` is set to anything but the empty string
+/// (see <https://no-color.org>), when `TERM` is `dumb`, and when stdout is
+/// not a terminal. Everything the shell colors, from highlighting the
+/// command line to listing matches, checks this first.
+pub fn colors_enabled() -> bool {
+    colors_enabled_on(libc::STDOUT_FILENO)
+}
+
+/// Checks whether the shell may color what it writes to stderr: as for
+/// [`colors_enabled`], but with stderr being a terminal.
+pub fn stderr_colors_enabled() -> bool {
+    colors_enabled_on(libc::STDERR_FILENO)
+}
+
+fn colors_enabled_on(fd: i32) -> bool {
+    if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        return false;
+    }
+    if env::var_os("TERM").is_some_and(|term| term == "dumb") {
+        return false;
+    }
+    unsafe { libc::isatty(fd) == 1 }
+}
+
+/// The terminal the line editor reads keys from and draws on.
+///
+/// Keys are decoded into termion's [`Key`], which the key map and the
+/// editor use whatever the backend; the screen is drawn by writing to the
+/// terminal, with ANSI escape sequences. [`open`] picks the backend: termion,
+/// or crossterm with the `crossterm` feature. The `test-util` feature adds
+/// a fake one, for driving the editor from tests.
+pub trait Terminal: Write {
+    /// Switches to raw mode: keys are read as they are typed, without
+    /// being echoed.
+    fn enable_raw_mode(&mut self) -> io::Result<()>;
+
+    /// Switches back to the mode the terminal was in before, as for running
+    /// a command.
+    fn disable_raw_mode(&mut self) -> io::Result<()>;
+
+    /// Reads the next key, or returns `None` at the end of the input. An
+    /// `Interrupted` error means a signal, such as for a resize, arrived
+    /// first.
+    fn read_key(&mut self) -> Option<io::Result<Key>>;
+
+    /// Returns the size of the terminal in columns and rows.
+    fn size(&self) -> io::Result<(u16, u16)>;
+
+    /// Asks the terminal for the system clipboard's contents with OSC 52.
+    /// Returns `None` if it doesn't answer in time, as most refuse to.
+    fn read_clipboard(&mut self) -> io::Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+/// How long to wait for the terminal to answer an OSC 52 query.
+const CLIPBOARD_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// Opens the shell's own terminal, through crossterm.
+#[cfg(feature = "crossterm")]
+pub fn open() -> Box<dyn Terminal> {
+    Box::new(CrosstermTerminal::default())
+}
+
+/// Opens the shell's own terminal, through termion; with the `crossterm`
+/// feature, through crossterm instead.
+#[cfg(not(feature = "crossterm"))]
+pub fn open() -> Box<dyn Terminal> {
+    Box::new(TermionTerminal::new())
+}
+
+/// The shell's stdin and stdout, through termion.
+pub struct TermionTerminal {
+    keys: Keys<Stdin>,
+    /// Raw mode, entered the first time it is enabled and suspended in
+    /// between.
+    raw: Option<RawTerminal<Stdout>>,
+}
+
+impl TermionTerminal {
+    pub fn new() -> Self {
+        TermionTerminal {
+            keys: io::stdin().keys(),
+            raw: None,
+        }
+    }
+}
+
+impl Default for TermionTerminal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for TermionTerminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::stdout().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+impl Terminal for TermionTerminal {
+    fn enable_raw_mode(&mut self) -> io::Result<()> {
+        match &self.raw {
+            Some(raw) => raw.activate_raw_mode(),
+            None => {
+                self.raw = Some(io::stdout().into_raw_mode()?);
+                Ok(())
+            }
+        }
+    }
+
+    fn disable_raw_mode(&mut self) -> io::Result<()> {
+        match &self.raw {
+            Some(raw) => raw.suspend_raw_mode(),
+            None => Ok(()),
+        }
+    }
+
+    fn read_key(&mut self) -> Option<io::Result<Key>> {
+        self.keys.next()
+    }
+
+    fn size(&self) -> io::Result<(u16, u16)> {
+        termion::terminal_size()
+    }
+
+    fn read_clipboard(&mut self) -> io::Result<Option<String>> {
+        write!(self, "{}", clipboard::PASTE_QUERY)?;
+        self.flush()?;
+        // Read the answer byte by byte, leaving any keys typed after it
+        let deadline = Instant::now() + CLIPBOARD_TIMEOUT;
+        let mut reply = Vec::new();
+        let mut stdin = io::stdin();
+        while !reply.ends_with(b"\x07") && !reply.ends_with(b"\x1b\\") {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let mut poll = libc::pollfd {
+                fd: libc::STDIN_FILENO,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            if left.is_zero() || unsafe { libc::poll(&mut poll, 1, left.as_millis() as i32) } <= 0 {
+                return Ok(None);
+            }
+            let mut byte = [0];
+            if stdin.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            reply.push(byte[0]);
+        }
+        Ok(clipboard::parse_reply(&reply))
+    }
+}
+
+/// The shell's stdin and stdout, through crossterm.
+#[cfg(feature = "crossterm")]
+#[derive(Default)]
+pub struct CrosstermTerminal {
+    raw: bool,
+}
+
+#[cfg(feature = "crossterm")]
+impl Write for CrosstermTerminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::stdout().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+#[cfg(feature = "crossterm")]
+impl Terminal for CrosstermTerminal {
+    fn enable_raw_mode(&mut self) -> io::Result<()> {
+        crossterm::terminal::enable_raw_mode()?;
+        self.raw = true;
+        Ok(())
+    }
+
+    fn disable_raw_mode(&mut self) -> io::Result<()> {
+        if self.raw {
+            crossterm::terminal::disable_raw_mode()?;
+            self.raw = false;
+        }
+        Ok(())
+    }
+
+    fn read_key(&mut self) -> Option<io::Result<Key>> {
+        use crossterm::event::{self, Event, KeyEventKind};
+
+        loop {
+            match event::read() {
+                Ok(Event::Key(key)) if key.kind != KeyEventKind::Release => {
+                    if let Some(key) = termion_key(key) {
+                        return Some(Ok(key));
+                    }
+                }
+                Ok(Event::Resize(..)) => {
+                    crate::signals::note_resize();
+                    return Some(Err(io::ErrorKind::Interrupted.into()));
+                }
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+
+    fn size(&self) -> io::Result<(u16, u16)> {
+        crossterm::terminal::size()
+    }
+}
+
+#[cfg(feature = "crossterm")]
+impl Drop for CrosstermTerminal {
+    fn drop(&mut self) {
+        let _ = self.disable_raw_mode();
+    }
+}
+
+/// Converts a key crossterm read into the key termion would have decoded
+/// from the same bytes, or `None` for keys termion has no name for.
+#[cfg(feature = "crossterm")]
+fn termion_key(key: crossterm::event::KeyEvent) -> Option<Key> {
+    use crossterm::event::{KeyCode, KeyModifiers};
+
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    let alt = key.modifiers.contains(KeyModifiers::ALT);
+    let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+    Some(match key.code {
+        // Ctrl-Space is NUL, and Ctrl-_ is 0x1F, which termion reads as Ctrl-7
+        KeyCode::Char(' ') if ctrl => Key::Null,
+        KeyCode::Char('_') if ctrl => Key::Ctrl('7'),
+        KeyCode::Char(c) if ctrl => Key::Ctrl(c.to_ascii_lowercase()),
+        KeyCode::Char(c) if alt => Key::Alt(c),
+        KeyCode::Char(c) => Key::Char(c),
+        KeyCode::Enter => Key::Char('\n'),
+        KeyCode::Tab => Key::Char('\t'),
+        KeyCode::BackTab => Key::BackTab,
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Left if ctrl => Key::CtrlLeft,
+        KeyCode::Left if alt => Key::AltLeft,
+        KeyCode::Left if shift => Key::ShiftLeft,
+        KeyCode::Left => Key::Left,
+        KeyCode::Right if ctrl => Key::CtrlRight,
+        KeyCode::Right if alt => Key::AltRight,
+        KeyCode::Right if shift => Key::ShiftRight,
+        KeyCode::Right => Key::Right,
+        KeyCode::Up if ctrl => Key::CtrlUp,
+        KeyCode::Up if alt => Key::AltUp,
+        KeyCode::Up if shift => Key::ShiftUp,
+        KeyCode::Up => Key::Up,
+        KeyCode::Down if ctrl => Key::CtrlDown,
+        KeyCode::Down if alt => Key::AltDown,
+        KeyCode::Down if shift => Key::ShiftDown,
+        KeyCode::Down => Key::Down,
+        KeyCode::Home if ctrl => Key::CtrlHome,
+        KeyCode::Home => Key::Home,
+        KeyCode::End if ctrl => Key::CtrlEnd,
+        KeyCode::End => Key::End,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::PageDown => Key::PageDown,
+        KeyCode::Delete => Key::Delete,
+        KeyCode::Insert => Key::Insert,
+        KeyCode::F(n) => Key::F(n),
+        KeyCode::Esc => Key::Esc,
+        KeyCode::Null => Key::Null,
+        _ => return None,
+    })
+}
@@ -0,0 +1,522 @@
+use crate::clipboard;
+use crate::executor::{Executor, Spawn};
+use crate::redirect::Redirection;
+use crate::terminal::Terminal;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use termion::event::Key;
+
+/// How long [`Session::expect`] waits unless told otherwise.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A shell started for a test, driven by sending it input and waiting for
+/// its output.
+///
+/// Started with [`Session::pty`], the shell runs on a pseudo-terminal, as
+/// it would for a user, so keystrokes such as Tab and the arrow keys go
+/// through the line editor. Started with [`Session::piped`], it reads
+/// commands from a pipe and writes to one, like a shell running a script.
+/// Output is matched with escape sequences and carriage returns removed
+/// (see [`strip_escapes`]).
+///
+/// # Example
+/// In an integration test of this crate, built with `--features test-util`,
+/// the shell is `env!("CARGO_BIN_EXE_codecrafters-shell")`:
+/// ```no_run
+/// use codecrafters_shell::testing::Session;
+/// use std::process::Command;
+///
+/// let mut shell = Command::new("target/debug/codecrafters-shell");
+/// shell.env("HOME", "/tmp/test-home").env("NO_COLOR", "1");
+/// let mut session = Session::pty(shell).unwrap();
+/// session.send("ech\t");
+/// session.expect("echo ").unwrap();
+/// session.send_line("hello | tr a-z A-Z");
+/// session.expect("HELLO").unwrap();
+/// ```
+pub struct Session {
+    child: Child,
+    input: Option<File>,
+    output: File,
+    /// Everything the shell has written so far.
+    received: Vec<u8>,
+    /// How much of the stripped output earlier expectations matched.
+    matched: usize,
+    /// Whether the shell has closed its output.
+    closed: bool,
+    newline: &'static str,
+}
+
+impl Session {
+    /// Starts `command` on a new pseudo-terminal of 80 by 24 characters,
+    /// as its controlling terminal.
+    pub fn pty(mut command: Command) -> io::Result<Session> {
+        let (master, terminal) = open_pty()?;
+        let size = libc::winsize {
+            ws_row: 24,
+            ws_col: 80,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ, &size) };
+        command
+            .stdin(terminal.try_clone()?)
+            .stdout(terminal.try_clone()?)
+            .stderr(terminal);
+        // SAFETY: only async-signal-safe calls between fork and exec
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = command.spawn()?;
+        // The terminal's ends in `command` must close, so that reading
+        // reports the end of the output once the shell exits
+        drop(command);
+        Ok(Session::new(child, master.try_clone()?, master, "\r"))
+    }
+
+    /// Starts `command` reading from a pipe, with its stdout and stderr
+    /// going to a single pipe.
+    pub fn piped(mut command: Command) -> io::Result<Session> {
+        let (output, write) = crate::create_pipe()?;
+        command
+            .stdin(Stdio::piped())
+            .stdout(write.try_clone()?)
+            .stderr(write);
+        let mut child = command.spawn()?;
+        drop(command);
+        let input = child
+            .stdin
+            .take()
+            .map(|stdin| File::from(OwnedFd::from(stdin)));
+        let input = input.ok_or_else(|| io::Error::other("no stdin"))?;
+        Ok(Session::new(child, input, output, "\n"))
+    }
+
+    fn new(child: Child, input: File, output: File, newline: &'static str) -> Session {
+        Session {
+            child,
+            input: Some(input),
+            output,
+            received: Vec::new(),
+            matched: 0,
+            closed: false,
+            newline,
+        }
+    }
+
+    /// Sends input as it is, such as keystrokes: `"\t"` for Tab or
+    /// `"\x1b[A"` for the up arrow.
+    pub fn send(&mut self, input: &str) {
+        if let Some(file) = &mut self.input {
+            let _ = file.write_all(input.as_bytes());
+        }
+    }
+
+    /// Sends a line of input, ended as Enter would end it.
+    pub fn send_line(&mut self, line: &str) {
+        self.send(line);
+        self.send(self.newline);
+    }
+
+    /// Ends the input: closes the pipe, or on a terminal sends Ctrl-D.
+    /// The terminal only takes Ctrl-D as the end of the input once the
+    /// line editor is reading again, so wait for the prompt first.
+    pub fn close_input(&mut self) {
+        if self.newline == "\r" {
+            self.send("\x04");
+        } else {
+            self.input = None;
+        }
+    }
+
+    /// Waits up to [`TIMEOUT`] for `text` to appear in the output after
+    /// what earlier expectations matched, and returns the output up to and
+    /// including it.
+    pub fn expect(&mut self, text: &str) -> io::Result<String> {
+        self.expect_within(text, TIMEOUT)
+    }
+
+    /// Waits like [`Session::expect`], for up to `timeout`. The error for
+    /// a timeout shows the output that was received instead.
+    pub fn expect_within(&mut self, text: &str, timeout: Duration) -> io::Result<String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let output = self.output();
+            if let Some(found) = output[self.matched..].find(text) {
+                let end = self.matched + found + text.len();
+                let matched = output[self.matched..end].to_string();
+                self.matched = end;
+                return Ok(matched);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if self.closed || remaining.is_zero() {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("expected {:?}, got {:?}", text, &output[self.matched..]),
+                ));
+            }
+            self.receive(remaining)?;
+        }
+    }
+
+    /// Returns all the output so far, with escape sequences and carriage
+    /// returns removed.
+    pub fn output(&self) -> String {
+        strip_escapes(&String::from_utf8_lossy(&self.received))
+    }
+
+    /// Returns all the output so far, exactly as the shell wrote it.
+    pub fn raw_output(&self) -> &[u8] {
+        &self.received
+    }
+
+    /// Ends the input and waits up to [`TIMEOUT`] for the shell to exit,
+    /// collecting the rest of its output. Returns its exit status.
+    pub fn wait(&mut self) -> io::Result<i32> {
+        self.close_input();
+        let deadline = Instant::now() + TIMEOUT;
+        while !self.closed {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "the shell did not exit",
+                ));
+            }
+            self.receive(remaining)?;
+        }
+        let status = self.child.wait()?;
+        Ok(status.code().unwrap_or(-1))
+    }
+
+    /// Reads whatever output arrives within `timeout`.
+    fn receive(&mut self, timeout: Duration) -> io::Result<()> {
+        let mut poll = libc::pollfd {
+            fd: self.output.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let millis = timeout.as_millis().min(i32::MAX as u128) as i32;
+        if unsafe { libc::poll(&mut poll, 1, millis) } <= 0 {
+            return Ok(());
+        }
+        let mut buffer = [0; 4096];
+        match self.output.read(&mut buffer) {
+            Ok(0) => self.closed = true,
+            Ok(n) => self.received.extend_from_slice(&buffer[..n]),
+            // A terminal reports EIO once the shell has exited
+            Err(e) if e.raw_os_error() == Some(libc::EIO) => self.closed = true,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A terminal that plays back keys given to it and keeps what is drawn
+/// on it, for driving the line editor without a real terminal.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::editor::LineEditor;
+/// use codecrafters_shell::terminal::Terminal;
+/// use codecrafters_shell::testing::FakeTerminal;
+/// use termion::event::Key;
+///
+/// let mut terminal = FakeTerminal::new([Key::Char('h'), Key::Char('i')]);
+/// let mut editor = LineEditor::new();
+/// while let Some(Ok(Key::Char(c))) = terminal.read_key() {
+///     editor.type_char(&mut terminal, "$ ", c).unwrap();
+/// }
+/// assert_eq!(editor.buffer(), "hi");
+/// assert!(terminal.output().ends_with("hi"));
+/// ```
+#[derive(Debug, Default)]
+pub struct FakeTerminal {
+    keys: VecDeque<Key>,
+    written: Vec<u8>,
+    raw: bool,
+    size: (u16, u16),
+    clipboard: Option<String>,
+}
+
+impl FakeTerminal {
+    /// A terminal of 80 by 24 characters that reads `keys`, and then
+    /// reaches the end of its input.
+    pub fn new(keys: impl IntoIterator<Item = Key>) -> Self {
+        FakeTerminal {
+            keys: keys.into_iter().collect(),
+            size: (80, 24),
+            ..FakeTerminal::default()
+        }
+    }
+
+    /// Queues more keys to read.
+    pub fn push_keys(&mut self, keys: impl IntoIterator<Item = Key>) {
+        self.keys.extend(keys);
+    }
+
+    /// Changes the size the terminal reports.
+    pub fn set_size(&mut self, columns: u16, rows: u16) {
+        self.size = (columns, rows);
+    }
+
+    /// Sets what the terminal answers when asked for the clipboard;
+    /// without it, the terminal doesn't answer.
+    pub fn set_clipboard(&mut self, text: &str) {
+        self.clipboard = Some(text.to_string());
+    }
+
+    /// Checks whether the terminal is in raw mode.
+    pub fn is_raw(&self) -> bool {
+        self.raw
+    }
+
+    /// Returns everything drawn so far, with escape sequences and carriage
+    /// returns removed.
+    pub fn output(&self) -> String {
+        strip_escapes(&String::from_utf8_lossy(&self.written))
+    }
+
+    /// Returns everything drawn so far, exactly as it was written.
+    pub fn raw_output(&self) -> &[u8] {
+        &self.written
+    }
+}
+
+impl Write for FakeTerminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Terminal for FakeTerminal {
+    fn enable_raw_mode(&mut self) -> io::Result<()> {
+        self.raw = true;
+        Ok(())
+    }
+
+    fn disable_raw_mode(&mut self) -> io::Result<()> {
+        self.raw = false;
+        Ok(())
+    }
+
+    fn read_key(&mut self) -> Option<io::Result<Key>> {
+        self.keys.pop_front().map(Ok)
+    }
+
+    fn size(&self) -> io::Result<(u16, u16)> {
+        Ok(self.size)
+    }
+
+    fn read_clipboard(&mut self) -> io::Result<Option<String>> {
+        self.written
+            .extend_from_slice(clipboard::PASTE_QUERY.as_bytes());
+        Ok(self.clipboard.clone())
+    }
+}
+
+/// An external command a [`RecordingExecutor`] was asked to start.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spawned {
+    pub program: String,
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub redirections: Vec<Redirection>,
+}
+
+/// An executor that starts no processes: it records the commands the
+/// shell asks for, writes the output set for them to their stdout, and
+/// gives them the status set for them, 0 by default.
+///
+/// Every command name counts as found. Clones share their records, so a
+/// test can keep one and give another to the shell.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::Shell;
+/// use codecrafters_shell::executor::SharedExecutor;
+/// use codecrafters_shell::testing::RecordingExecutor;
+///
+/// let executor = RecordingExecutor::default()
+///     .with_output("git", "main\n")
+///     .with_status("make", 2);
+/// let mut shell = Shell::default();
+/// shell.executor = SharedExecutor::new(executor.clone());
+///
+/// let captured = shell.capture("git branch --show-current\nmake -j4 > build.log");
+/// assert_eq!(captured.stdout, "main\n");
+/// assert_eq!(captured.status, 2);
+/// let spawned = executor.spawned();
+/// assert_eq!(spawned[0].args, vec!["branch", "--show-current"]);
+/// assert_eq!(spawned[1].redirections[0].to_string(), "> build.log");
+/// # let _ = std::fs::remove_file("build.log");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RecordingExecutor {
+    state: Arc<Mutex<Recorded>>,
+}
+
+#[derive(Debug, Default)]
+struct Recorded {
+    spawned: Vec<Spawned>,
+    outputs: HashMap<String, String>,
+    statuses: HashMap<String, i32>,
+    /// The status of each process ID handed out, until it is waited for.
+    running: HashMap<i32, i32>,
+}
+
+/// The first process ID a [`RecordingExecutor`] hands out, far above any
+/// real one.
+const FIRST_FAKE_PID: i32 = 1 << 30;
+
+impl RecordingExecutor {
+    /// Makes `program` write `output` to its stdout.
+    pub fn with_output(self, program: &str, output: &str) -> Self {
+        self.lock()
+            .outputs
+            .insert(program.to_string(), output.to_string());
+        self
+    }
+
+    /// Makes `program` exit with `status`.
+    pub fn with_status(self, program: &str, status: i32) -> Self {
+        self.lock().statuses.insert(program.to_string(), status);
+        self
+    }
+
+    /// Returns the commands started so far, in order.
+    pub fn spawned(&self) -> Vec<Spawned> {
+        self.lock().spawned.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Recorded> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Executor for RecordingExecutor {
+    fn resolve(&self, program: &str, _path: &str) -> Option<PathBuf> {
+        Some(PathBuf::from(program))
+    }
+
+    fn spawn(&self, spawn: Spawn) -> io::Result<i32> {
+        let mut state = self.lock();
+        if let (Some(output), Some(mut stdout)) = (state.outputs.get(&spawn.program), spawn.stdout)
+        {
+            stdout.write_all(output.as_bytes())?;
+        }
+        let pid = FIRST_FAKE_PID + state.spawned.len() as i32;
+        let status = state.statuses.get(&spawn.program).copied().unwrap_or(0);
+        state.running.insert(pid, status);
+        state.spawned.push(Spawned {
+            program: spawn.program,
+            args: spawn.args,
+            env: spawn.env,
+            redirections: spawn.redirections,
+        });
+        Ok(pid)
+    }
+
+    fn wait(&self, pid: i32) -> io::Result<i32> {
+        self.lock()
+            .running
+            .remove(&pid)
+            .ok_or_else(|| io::Error::from_raw_os_error(libc::ECHILD))
+    }
+
+    fn kill(&self, pid: i32, signal: i32) {
+        if let Some(status) = self.lock().running.get_mut(&pid) {
+            *status = 128 + signal;
+        }
+    }
+}
+
+/// Opens a pseudo-terminal, returning its master end and the terminal.
+fn open_pty() -> io::Result<(File, File)> {
+    let flags = libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC;
+    let master = unsafe { libc::posix_openpt(flags) };
+    if master == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let master = unsafe { File::from_raw_fd(master) };
+    let fd = master.as_raw_fd();
+    if unsafe { libc::grantpt(fd) } == -1 || unsafe { libc::unlockpt(fd) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut name = [0; 128];
+    let error = unsafe { libc::ptsname_r(fd, name.as_mut_ptr(), name.len()) };
+    if error != 0 {
+        return Err(io::Error::from_raw_os_error(error));
+    }
+    let name = unsafe { CStr::from_ptr(name.as_ptr()) };
+    let terminal = unsafe { libc::open(name.as_ptr(), flags) };
+    if terminal == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((master, unsafe { File::from_raw_fd(terminal) }))
+}
+
+/// Removes terminal escape sequences (CSI sequences such as colors and
+/// cursor movement, and OSC sequences such as titles) and carriage returns,
+/// leaving the text as it reads.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::testing::strip_escapes;
+/// assert_eq!(strip_escapes("\x1b[1;32mok\x1b[0m\r\n\x1b]0;title\x07$ "), "ok\n$ ");
+/// ```
+pub fn strip_escapes(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' => {}
+            '\x1b' => match chars.next() {
+                // CSI: parameters, then a final byte from `@` to `~`
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // OSC: ended by BEL or by ESC `\`
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            _ => stripped.push(c),
+        }
+    }
+    stripped
+}
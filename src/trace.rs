@@ -0,0 +1,32 @@
+/// Logs a debug event about running commands under the `shell` target,
+/// with `tracing`'s field syntax. Without the `tracing` feature this
+/// compiles to nothing and the arguments aren't evaluated.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(target: "shell", $($arg)*);
+    };
+}
+
+/// Starts logging to stderr how commands are parsed, expanded and run,
+/// filtered by `RUST_LOG`: `RUST_LOG=shell=debug` shows the pipelines
+/// parsed, the words they expand to, the processes spawned and their exit
+/// statuses or signals. Nothing is logged unless `RUST_LOG` is set.
+#[cfg(feature = "tracing")]
+pub fn init() {
+    use tracing_subscriber::EnvFilter;
+
+    let Ok(filter) = EnvFilter::try_from_default_env() else {
+        return;
+    };
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(crate::terminal::colors_enabled())
+        .try_init();
+}
+
+/// Starts logging, which needs the `tracing` feature; without it this
+/// does nothing.
+#[cfg(not(feature = "tracing"))]
+pub fn init() {}
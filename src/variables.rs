@@ -0,0 +1,216 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+
+/// The shell's variables.
+///
+/// Variables set with `name=value` are local to the shell until exported
+/// with `export`, which puts them in the environment that commands
+/// inherit. The shell starts with the variables of its own environment,
+/// already exported. The process's environment itself never changes: the
+/// commands the shell starts are given the exported variables instead.
+#[derive(Debug, Clone)]
+pub struct Variables {
+    /// Every variable that isn't an array, with its value.
+    values: BTreeMap<String, String>,
+    /// The names of the variables exported to commands. An exported name
+    /// that isn't set yet has no value.
+    exported: BTreeSet<String>,
+    /// The exit status of the last command, `$?`.
+    status: i32,
+    /// The positional parameters: the script's name, `$0`, then its
+    /// arguments.
+    arguments: Vec<String>,
+    /// Indexed arrays, such as those `read -a` assigns. They are never
+    /// exported, and a name is never both an array and a variable.
+    arrays: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for Variables {
+    /// The variables of the process's environment, exported. Those whose
+    /// name or value isn't UTF-8 are left out.
+    fn default() -> Self {
+        let values: BTreeMap<String, String> = env::vars_os()
+            .filter_map(|(n, v)| Some((n.into_string().ok()?, v.into_string().ok()?)))
+            .collect();
+        Variables {
+            exported: values.keys().cloned().collect(),
+            values,
+            status: 0,
+            arguments: Vec::new(),
+            arrays: BTreeMap::new(),
+        }
+    }
+}
+
+impl Variables {
+    /// Returns the value of a variable. The value of an array is its first
+    /// element, as in bash.
+    pub fn get(&self, name: &str) -> Option<String> {
+        match self.arrays.get(name) {
+            Some(array) => array.first().cloned(),
+            None => self.values.get(name).cloned(),
+        }
+    }
+
+    /// Assigns a variable, which stays exported if it already was.
+    /// Assigning to an array replaces its first element.
+    pub fn set(&mut self, name: &str, value: &str) {
+        match self.arrays.get_mut(name) {
+            Some(array) => match array.first_mut() {
+                Some(first) => *first = value.to_string(),
+                None => array.push(value.to_string()),
+            },
+            None => {
+                self.values.insert(name.to_string(), value.to_string());
+            }
+        }
+    }
+
+    /// Exports a variable, assigning it first if a value is given. A name
+    /// without a value that isn't set yet is exported as empty. Arrays
+    /// can't be exported, so for one only the value is assigned.
+    pub fn export(&mut self, name: &str, value: Option<&str>) {
+        if self.arrays.contains_key(name) {
+            if let Some(value) = value {
+                self.set(name, value);
+            }
+            return;
+        }
+        match value {
+            Some(value) => self.set(name, value),
+            None if !self.values.contains_key(name) => self.set(name, ""),
+            None => {}
+        }
+        self.exported.insert(name.to_string());
+    }
+
+    /// Stops exporting a variable, keeping it as a shell variable.
+    pub fn unexport(&mut self, name: &str) {
+        self.exported.remove(name);
+    }
+
+    /// Removes a variable, from the environment too if it was exported.
+    pub fn unset(&mut self, name: &str) {
+        if self.arrays.remove(name).is_none() {
+            self.values.remove(name);
+            self.exported.remove(name);
+        }
+    }
+
+    /// Checks whether a variable is exported to commands.
+    ///
+    /// # Example
+    /// ```
+    /// use codecrafters_shell::variables::Variables;
+    ///
+    /// let mut variables = Variables::default();
+    /// variables.set("EXPORT_EXAMPLE", "1");
+    /// assert!(!variables.is_exported("EXPORT_EXAMPLE"));
+    /// variables.export("EXPORT_EXAMPLE", None);
+    /// assert!(variables.is_exported("EXPORT_EXAMPLE"));
+    /// assert!(std::env::var_os("EXPORT_EXAMPLE").is_none());
+    /// ```
+    pub fn is_exported(&self, name: &str) -> bool {
+        self.exported.contains(name)
+    }
+
+    /// Returns the exported variables with their values, sorted by name:
+    /// the environment of the commands the shell starts.
+    pub fn exported(&self) -> Vec<(String, String)> {
+        self.exported
+            .iter()
+            .filter_map(|name| Some((name.clone(), self.values.get(name)?.clone())))
+            .collect()
+    }
+
+    /// Returns the elements of an array, or `None` if `name` isn't one.
+    ///
+    /// # Example
+    /// ```
+    /// use codecrafters_shell::variables::Variables;
+    ///
+    /// let mut variables = Variables::default();
+    /// variables.set_array("words", vec!["one".to_string(), "two".to_string()]);
+    /// assert_eq!(variables.array("words").map(<[String]>::len), Some(2));
+    /// assert_eq!(variables.get("words").as_deref(), Some("one"));
+    /// variables.unset("words");
+    /// assert_eq!(variables.array("words"), None);
+    /// ```
+    pub fn array(&self, name: &str) -> Option<&[String]> {
+        self.arrays.get(name).map(Vec::as_slice)
+    }
+
+    /// Makes `name` an array of `values`, replacing any variable of that
+    /// name.
+    pub fn set_array(&mut self, name: &str, values: Vec<String>) {
+        self.unset(name);
+        self.arrays.insert(name.to_string(), values);
+    }
+
+    /// Returns the exit status of the last command, `$?`.
+    pub fn status(&self) -> i32 {
+        self.status
+    }
+
+    /// Records the exit status of the command that just finished.
+    pub fn set_status(&mut self, status: i32) {
+        self.status = status;
+    }
+
+    /// Returns the positional parameter `$n`: `$0` is the name of the
+    /// script or shell, `$1` its first argument.
+    pub fn argument(&self, n: usize) -> Option<&str> {
+        self.arguments.get(n).map(String::as_str)
+    }
+
+    /// Returns the number of arguments, `$#`, not counting `$0`.
+    pub fn argument_count(&self) -> usize {
+        self.arguments.len().saturating_sub(1)
+    }
+
+    /// Replaces the positional parameters, `$0` first, returning the ones
+    /// they replace.
+    pub fn set_arguments(&mut self, arguments: Vec<String>) -> Vec<String> {
+        std::mem::replace(&mut self.arguments, arguments)
+    }
+
+    /// Returns every variable with its value, sorted by name.
+    pub fn all(&self) -> Vec<(String, String)> {
+        self.values
+            .iter()
+            .map(|(n, v)| (n.clone(), v.clone()))
+            .collect()
+    }
+}
+
+/// Checks whether a string is a valid variable name: a letter or
+/// underscore, followed by letters, digits and underscores.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::variables::is_name;
+/// assert!(is_name("_path2"));
+/// assert!(!is_name("2path"));
+/// assert!(!is_name("a-b"));
+/// ```
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits a `name=value` word into its name and value, or returns `None`
+/// if the word isn't an assignment.
+///
+/// # Example
+/// ```
+/// use codecrafters_shell::variables::parse_assignment;
+/// assert_eq!(parse_assignment("x=5"), Some(("x", "5")));
+/// assert_eq!(parse_assignment("x="), Some(("x", "")));
+/// assert_eq!(parse_assignment("--x=5"), None);
+/// ```
+pub fn parse_assignment(word: &str) -> Option<(&str, &str)> {
+    word.split_once('=').filter(|(name, _)| is_name(name))
+}
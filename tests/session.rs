@@ -0,0 +1,185 @@
+//! Runs the shell binary with `Session`, as a user at a terminal or a
+//! script piping commands in would, so that completion, history and
+//! pipelines are checked end to end.
+
+use codecrafters_shell::testing::Session;
+use std::fs::{self, DirBuilder};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A private home directory for one test, removed when dropped, so that
+/// no test reads the user's startup files or history.
+struct Home(PathBuf);
+
+impl Home {
+    fn new(test: &str) -> Home {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.subsec_nanos());
+        let name = format!("shell-{}-{}-{}", test, std::process::id(), nanos);
+        let path = std::env::temp_dir().join(name);
+        // Only this test's directory: fails rather than use one that exists
+        DirBuilder::new().mode(0o700).create(&path).unwrap();
+        Home(path)
+    }
+
+    /// Returns the shell started in this home directory, with a `PATH`
+    /// of the system directories and `bin` in it.
+    fn shell(&self) -> Command {
+        let mut shell = Command::new(env!("CARGO_BIN_EXE_codecrafters-shell"));
+        shell
+            .current_dir(&self.0)
+            .env_clear()
+            .env("HOME", &self.0)
+            .env("PATH", format!("{}:/usr/bin:/bin", self.bin().display()))
+            .env("TERM", "xterm")
+            .env("NO_COLOR", "1")
+            .env("HISTFILE", self.0.join("history"));
+        shell
+    }
+
+    fn bin(&self) -> PathBuf {
+        self.0.join("bin")
+    }
+
+    /// Adds an executable script called `name` to `bin`.
+    fn add_command(&self, name: &str, script: &str) {
+        fs::create_dir_all(self.bin()).unwrap();
+        let path = self.bin().join(name);
+        fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Home {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn tab_completes_a_builtin() {
+    let home = Home::new("complete-builtin");
+    let mut session = Session::pty(home.shell()).unwrap();
+    session.expect("$ ").unwrap();
+    session.send("ech\t");
+    session.expect("echo ").unwrap();
+    session.send_line("done");
+    session.expect("done").unwrap();
+}
+
+#[test]
+fn tab_completes_an_executable_on_the_path() {
+    let home = Home::new("complete-executable");
+    home.add_command("custom_exe_4821", "echo ran custom");
+    let mut session = Session::pty(home.shell()).unwrap();
+    session.expect("$ ").unwrap();
+    session.send("custom_exe_48\t");
+    session.expect("custom_exe_4821 ").unwrap();
+    session.send_line("");
+    session.expect("ran custom").unwrap();
+}
+
+#[test]
+fn tab_completes_a_file_name() {
+    let home = Home::new("complete-file");
+    fs::write(home.path().join("notes.txt"), "remember\n").unwrap();
+    let mut session = Session::pty(home.shell()).unwrap();
+    session.expect("$ ").unwrap();
+    session.send("cat not\t");
+    session.expect("notes.txt").unwrap();
+    session.send_line("");
+    session.expect("remember").unwrap();
+}
+
+#[test]
+fn up_arrow_recalls_the_previous_command() {
+    let home = Home::new("history-recall");
+    let mut session = Session::pty(home.shell()).unwrap();
+    session.expect("$ ").unwrap();
+    session.send_line("echo first");
+    session.send_line("echo recalled");
+    session.expect("recalled\n").unwrap();
+    session.expect("$ ").unwrap();
+    session.send("\x1b[A");
+    session.expect("echo recalled").unwrap();
+    session.send("\r");
+    session.expect("recalled\n").unwrap();
+}
+
+#[test]
+fn history_is_saved_for_the_next_session() {
+    let home = Home::new("history-file");
+    let mut session = Session::pty(home.shell()).unwrap();
+    session.expect("$ ").unwrap();
+    session.send_line("echo from-the-first-session");
+    session.expect("from-the-first-session\n").unwrap();
+    session.expect("$ ").unwrap();
+    assert_eq!(session.wait().unwrap(), 0);
+
+    let mut session = Session::piped(home.shell()).unwrap();
+    session.send_line("history");
+    session.expect("echo from-the-first-session").unwrap();
+}
+
+#[test]
+fn history_lists_commands_in_order() {
+    let home = Home::new("history-list");
+    let mut session = Session::piped(home.shell()).unwrap();
+    session.send_line("echo one");
+    session.send_line("echo two");
+    session.send_line("history 3");
+    session.expect("echo one\n").unwrap();
+    session.expect("echo two\n").unwrap();
+    session.expect("history 3\n").unwrap();
+}
+
+#[test]
+fn pipeline_connects_external_commands() {
+    let home = Home::new("pipeline-external");
+    let mut session = Session::piped(home.shell()).unwrap();
+    session.send_line("echo hello | tr a-z A-Z");
+    session.expect("HELLO").unwrap();
+    session.send_line("printf 'b\\na\\nc\\n' | sort | head -n 2 | tr '\\n' ,");
+    session.expect("a,b,").unwrap();
+}
+
+#[test]
+fn pipeline_connects_builtins() {
+    let home = Home::new("pipeline-builtin");
+    let mut session = Session::piped(home.shell()).unwrap();
+    session.send_line("type echo | tr a-z A-Z");
+    session.expect("ECHO IS A SHELL BUILTIN").unwrap();
+    session.send_line("echo piped | cat | cat");
+    session.expect("piped").unwrap();
+}
+
+#[test]
+fn pipeline_status_is_that_of_the_last_command() {
+    let home = Home::new("pipeline-status");
+    let mut session = Session::piped(home.shell()).unwrap();
+    session.send_line("false | true");
+    session.send_line("echo status=$?");
+    session.expect("status=0").unwrap();
+    session.send_line("true | false");
+    session.send_line("echo status=$?");
+    session.expect("status=1").unwrap();
+}
+
+#[test]
+fn pipeline_output_can_be_redirected() {
+    let home = Home::new("pipeline-redirect");
+    let mut session = Session::piped(home.shell()).unwrap();
+    session.send_line("echo saved | tr a-z A-Z > out.txt");
+    session.send_line("cat out.txt");
+    session.expect("SAVED").unwrap();
+    assert_eq!(session.wait().unwrap(), 0);
+    let saved = fs::read_to_string(home.path().join("out.txt")).unwrap();
+    assert_eq!(saved, "SAVED\n");
+}